@@ -0,0 +1,111 @@
+//! S-DSP register layout and decoding
+
+/// Number of DSP voices
+pub const VOICES: usize = 8;
+
+/// Sample rate of the DSP output
+pub const SAMPLE_RATE: u32 = 32000;
+
+/// Names of the voice registers `$x0`-`$x9`
+pub static VOICE_REG_NAMES: [&'static str; 10] = [
+    "VOLL", "VOLR", "PITCHL", "PITCHH", "SRCN", "ADSR1", "ADSR2", "GAIN", "ENVX", "OUTX",
+];
+
+/// Addresses and names of the global registers
+static GLOBAL_REGS: [(u8, &'static str); 15] = [
+    (0x0c, "MVOLL"),
+    (0x1c, "MVOLR"),
+    (0x2c, "EVOLL"),
+    (0x3c, "EVOLR"),
+    (0x4c, "KON"),
+    (0x5c, "KOFF"),
+    (0x6c, "FLG"),
+    (0x7c, "ENDX"),
+    (0x0d, "EFB"),
+    (0x2d, "PMON"),
+    (0x3d, "NON"),
+    (0x4d, "EON"),
+    (0x5d, "DIR"),
+    (0x6d, "ESA"),
+    (0x7d, "EDL"),
+];
+
+// Global registers used by the decoding functions
+pub const KON: u8 = 0x4c;
+pub const KOFF: u8 = 0x5c;
+pub const FLG: u8 = 0x6c;
+pub const ENDX: u8 = 0x7c;
+pub const PMON: u8 = 0x2d;
+pub const NON: u8 = 0x3d;
+pub const EON: u8 = 0x4d;
+pub const DIR: u8 = 0x5d;
+pub const ESA: u8 = 0x6d;
+pub const EDL: u8 = 0x7d;
+
+/// Returns the name of a DSP register
+pub fn reg_name(addr: u8) -> Option<String> {
+    let addr = addr & 0x7f;
+    if let Some(&(_, name)) = GLOBAL_REGS.iter().find(|&&(a, _)| a == addr) {
+        return Some(name.to_string());
+    }
+    match addr & 0x0f {
+        reg @ 0x0...0x9 => Some(format!("V{}{}", addr >> 4, VOICE_REG_NAMES[reg as usize])),
+        0xf => Some(format!("FIR{}", addr >> 4)),
+        _ => None,
+    }
+}
+
+/// Reads a register of a voice
+pub fn voice_reg(dsp: &[u8], voice: usize, reg: u8) -> u8 {
+    dsp[voice << 4 | reg as usize]
+}
+
+/// 14-bit pitch of a voice. `$1000` plays samples at 32 kHz.
+pub fn pitch(dsp: &[u8], voice: usize) -> u16 {
+    (voice_reg(dsp, voice, 2) as u16 | (voice_reg(dsp, voice, 3) as u16) << 8) & 0x3fff
+}
+
+/// Sample rate a voice plays its sample at, in Hz
+pub fn pitch_hz(pitch: u16) -> f64 {
+    pitch as f64 * SAMPLE_RATE as f64 / 0x1000 as f64
+}
+
+/// Describes the envelope settings of a voice (ADSR or GAIN)
+pub fn envelope_string(dsp: &[u8], voice: usize) -> String {
+    let adsr1 = voice_reg(dsp, voice, 5);
+    let adsr2 = voice_reg(dsp, voice, 6);
+    let gain = voice_reg(dsp, voice, 7);
+    if adsr1 & 0x80 != 0 {
+        format!("ADSR: AR={} DR={} SL={} SR={}", adsr1 & 0x0f, (adsr1 >> 4) & 0x07, adsr2 >> 5, adsr2 & 0x1f)
+    } else if gain & 0x80 == 0 {
+        format!("GAIN: direct {}", gain & 0x7f)
+    } else {
+        let mode = match (gain >> 5) & 0x03 {
+            0 => "linear decrease",
+            1 => "exp. decrease",
+            2 => "linear increase",
+            _ => "bent increase",
+        };
+        format!("GAIN: {} rate {}", mode, gain & 0x1f)
+    }
+}
+
+/// Describes the flags in `FLG`
+pub fn flg_string(flg: u8) -> String {
+    let mut flags = Vec::new();
+    if flg & 0x80 != 0 { flags.push("reset"); }
+    if flg & 0x40 != 0 { flags.push("mute"); }
+    if flg & 0x20 != 0 { flags.push("echo writes off"); }
+    format!("{} (noise clock {})", if flags.is_empty() { "-".to_string() } else { flags.join(", ") }, flg & 0x1f)
+}
+
+/// ARAM address of the sample directory
+pub fn dir_addr(dsp: &[u8]) -> u16 {
+    (dsp[DIR as usize] as u16) << 8
+}
+
+/// ARAM address and size of the echo buffer
+pub fn echo_buffer(dsp: &[u8]) -> (u16, usize) {
+    let delay = (dsp[EDL as usize] & 0x0f) as usize;
+    ((dsp[ESA as usize] as u16) << 8, if delay == 0 { 4 } else { delay * 2048 })
+}
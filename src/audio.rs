@@ -0,0 +1,97 @@
+//! Audio playback through the default audio backend of the Breeze frontends, and recording of
+//! the DSP output
+
+use apu::{SAMPLE_RATE, VOICES};
+use wav::WavWriter;
+
+use breeze_frontends::{AUDIO_MAP, DEFAULT_AUDIO};
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Plays stereo samples at the DSP sample rate in the background
+pub fn play(samples: Vec<(i16, i16)>) -> Result<(), String> {
+    let ctor = match DEFAULT_AUDIO.and_then(|name| AUDIO_MAP.get(name).and_then(|ctor| *ctor)) {
+        Some(ctor) => ctor,
+        None => return Err("no audio backend available".to_string()),
+    };
+
+    thread::spawn(move || {
+        let mut sink = ctor();
+        sink.write(&samples);
+        // The sink stops playing when dropped
+        let millis = samples.len() as u64 * 1000 / SAMPLE_RATE as u64;
+        thread::sleep(Duration::from_millis(millis + 200));
+    });
+    Ok(())
+}
+
+/// Returns the path of the track of a single voice, next to the main recording at `path`
+fn voice_track_path(path: &Path, voice: usize) -> PathBuf {
+    let stem = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    path.with_file_name(format!("{}.voice{}.wav", stem, voice))
+}
+
+/// Records the DSP output to stereo WAV files
+pub struct Recorder {
+    path: PathBuf,
+    mix: WavWriter,
+    /// One track per voice, if requested
+    voices: Vec<WavWriter>,
+}
+
+impl Recorder {
+    /// Creates the file of the mixed output at `path`, and the per-voice tracks next to it if
+    /// `per_voice` is set
+    pub fn create(path: &Path, per_voice: bool) -> io::Result<Recorder> {
+        let mut voices = Vec::new();
+        if per_voice {
+            for voice in 0..VOICES {
+                voices.push(try!(WavWriter::create(&voice_track_path(path, voice), 2, SAMPLE_RATE)));
+            }
+        }
+        Ok(Recorder {
+            path: path.to_owned(),
+            mix: try!(WavWriter::create(path, 2, SAMPLE_RATE)),
+            voices: voices,
+        })
+    }
+
+    /// Path of the mixed output
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether each voice is written to its own track
+    pub fn per_voice(&self) -> bool {
+        !self.voices.is_empty()
+    }
+
+    /// Number of recorded sample frames
+    pub fn frames(&self) -> u32 {
+        self.mix.frames()
+    }
+
+    /// Appends the mixed output `mix` and the output of the individual voices `voices`
+    pub fn write(&mut self, mix: &[(i16, i16)], voices: &[[(i16, i16); 8]]) -> io::Result<()> {
+        let interleaved = mix.iter().flat_map(|&(l, r)| vec![l, r]).collect::<Vec<_>>();
+        try!(self.mix.write(&interleaved));
+        for (voice, track) in self.voices.iter_mut().enumerate() {
+            let interleaved = voices.iter().flat_map(|frame| vec![frame[voice].0, frame[voice].1]).collect::<Vec<_>>();
+            try!(track.write(&interleaved));
+        }
+        Ok(())
+    }
+
+    /// Finishes all files, returning the number of recorded sample frames
+    pub fn finish(self) -> io::Result<u32> {
+        let frames = self.frames();
+        try!(self.mix.finish());
+        for track in self.voices {
+            try!(track.finish());
+        }
+        Ok(frames)
+    }
+}
@@ -6,6 +6,11 @@ use std::iter;
 
 /// Loads the default ROM
 pub fn load_blank_rom() -> Rom {
+    Rom::from_bytes(&blank_rom_image()).unwrap()
+}
+
+/// Builds the raw image of the default ROM
+pub fn blank_rom_image() -> Vec<u8> {
     let code = [
         // Enter endless loop
         0xA9, 0x00,         // lda #0
@@ -56,5 +61,5 @@ pub fn load_blank_rom() -> Rom {
     rom[0x7ffd] = 0x80;
     // This should now be a valid, runnable 64K ROM image (minus the checksum)
 
-    Rom::from_bytes(&rom).unwrap()
+    rom
 }
@@ -0,0 +1,193 @@
+//! Execution breakpoints and memory watchpoints
+
+use data::CpuState;
+use disasm::{Access, MemAccess};
+use memmap;
+use util::{parse_hex, parse_number};
+
+/// What a breakpoint reacts to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    /// Execution of an instruction in the address range
+    Exec,
+    /// Data reads from the address range
+    Read,
+    /// Data writes to the address range
+    Write,
+    /// Reads or writes
+    ReadWrite,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Kind::Exec => "Execute",
+            Kind::Read => "Read",
+            Kind::Write => "Write",
+            Kind::ReadWrite => "Read/Write",
+        }
+    }
+
+    fn matches(&self, access: Access) -> bool {
+        match (*self, access) {
+            (Kind::Exec, _) => false,
+            (Kind::ReadWrite, _) | (_, Access::Modify) => true,
+            (Kind::Read, Access::Read) | (Kind::Write, Access::Write) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A CPU register or memory location a condition can test
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    A, X, Y, S, D, Dbr, Pbr, P,
+    /// Byte at a 24-bit address
+    Mem(u32),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum CmpOp { Eq, Ne, Lt, Le, Gt, Ge }
+
+/// A condition that must hold for a breakpoint to trigger, in the form `<operand> <op> <value>`
+///
+/// The operand is a register name (`A`, `X`, `Y`, `S`, `D`, `DB`, `PB`, `P`) or a memory byte
+/// (`[$7E0010]`), the operator one of `==`, `!=`, `<`, `<=`, `>`, `>=`.
+#[derive(Clone, Debug)]
+pub struct Condition {
+    operand: Operand,
+    op: CmpOp,
+    value: u32,
+    /// The string the condition was parsed from
+    text: String,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        // Look for the longest operators first, so `<=` isn't parsed as `<`
+        let ops = [("==", CmpOp::Eq), ("!=", CmpOp::Ne), ("<=", CmpOp::Le), (">=", CmpOp::Ge),
+                   ("<", CmpOp::Lt), (">", CmpOp::Gt)];
+        let (pos, op_str, op) = match ops.iter().filter_map(|&(s, op)| {
+            text.find(s).map(|pos| (pos, s, op))
+        }).next() {
+            Some(found) => found,
+            None => return Err(format!("no comparison operator in condition '{}'", text)),
+        };
+
+        let lhs = text[..pos].trim();
+        let rhs = text[pos + op_str.len()..].trim();
+        let operand = match &*lhs.to_uppercase() {
+            "A" => Operand::A,
+            "X" => Operand::X,
+            "Y" => Operand::Y,
+            "S" => Operand::S,
+            "D" => Operand::D,
+            "DB" => Operand::Dbr,
+            "PB" => Operand::Pbr,
+            "P" => Operand::P,
+            mem if mem.starts_with('[') && mem.ends_with(']') => {
+                match parse_hex(&mem[1..mem.len() - 1]) {
+                    Some(addr) => Operand::Mem(addr),
+                    None => return Err(format!("invalid address '{}'", mem)),
+                }
+            }
+            other => return Err(format!("unknown register '{}'", other)),
+        };
+        let value = match parse_number(rhs) {
+            Some(value) => value,
+            None => return Err(format!("invalid value '{}'", rhs)),
+        };
+
+        Ok(Condition {
+            operand: operand,
+            op: op,
+            value: value,
+            text: text.trim().to_string(),
+        })
+    }
+
+    /// Evaluates the condition. `peek` is used to read memory operands.
+    pub fn eval<F: Fn(u32) -> u8>(&self, cpu: &CpuState, peek: F) -> bool {
+        let lhs = match self.operand {
+            Operand::A => cpu.a as u32,
+            Operand::X => cpu.x as u32,
+            Operand::Y => cpu.y as u32,
+            Operand::S => cpu.s as u32,
+            Operand::D => cpu.d as u32,
+            Operand::Dbr => cpu.dbr as u32,
+            Operand::Pbr => cpu.pbr as u32,
+            Operand::P => cpu.p as u32,
+            Operand::Mem(addr) => peek(addr) as u32,
+        };
+        match self.op {
+            CmpOp::Eq => lhs == self.value,
+            CmpOp::Ne => lhs != self.value,
+            CmpOp::Lt => lhs < self.value,
+            CmpOp::Le => lhs <= self.value,
+            CmpOp::Gt => lhs > self.value,
+            CmpOp::Ge => lhs >= self.value,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Breakpoint {
+    pub kind: Kind,
+    /// First 24-bit address of the range
+    pub start: u32,
+    /// Last 24-bit address of the range (inclusive)
+    pub end: u32,
+    pub enabled: bool,
+    /// Number of hits needed before emulation is halted (0 and 1 halt on the first hit)
+    pub hit_count: u32,
+    /// Number of times the breakpoint was hit so far
+    pub hits: u32,
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    pub fn new(kind: Kind, start: u32, end: u32) -> Self {
+        Breakpoint {
+            kind: kind,
+            start: memmap::canonical(start),
+            end: memmap::canonical(end),
+            enabled: true,
+            hit_count: 0,
+            hits: 0,
+            condition: None,
+        }
+    }
+
+    fn contains(&self, addr: u32) -> bool {
+        let addr = memmap::canonical(addr);
+        addr >= self.start && addr <= self.end
+    }
+
+    /// Checks whether executing the instruction at `pc` (performing `accesses`) hits this
+    /// breakpoint. Does not check the condition or hit count.
+    pub fn matches(&self, pc: u32, accesses: &[MemAccess]) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        match self.kind {
+            Kind::Exec => self.contains(pc),
+            _ => accesses.iter().any(|access| {
+                self.kind.matches(access.kind) &&
+                (0..access.len as u32).any(|i| self.contains((access.addr + i) & 0xffffff))
+            }),
+        }
+    }
+
+    /// Formats the address range for display
+    pub fn range_string(&self) -> String {
+        if self.start == self.end {
+            format!("${:06X}", self.start)
+        } else {
+            format!("${:06X}-${:06X}", self.start, self.end)
+        }
+    }
+}
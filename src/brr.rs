@@ -0,0 +1,227 @@
+//! Decoding of BRR samples stored in audio RAM
+//!
+//! Samples are stored in 9-byte blocks: a header byte followed by 16 4-bit samples. The sample
+//! directory (at `DIR * $100`) holds the start and loop addresses of up to 256 samples.
+
+/// Size of a BRR block in bytes
+pub const BLOCK_LEN: usize = 9;
+/// Samples per BRR block
+pub const BLOCK_SAMPLES: usize = 16;
+
+/// Number of blocks fitting into audio RAM. Samples that don't end before are cut off.
+const MAX_BLOCKS: usize = 0x10000 / BLOCK_LEN;
+
+/// An entry of the sample directory
+#[derive(Clone, Copy, Debug)]
+pub struct DirEntry {
+    /// Source number, as written to `SRCN`
+    pub srcn: u8,
+    pub start: u16,
+    pub loop_addr: u16,
+}
+
+/// Reads all 256 entries of the sample directory at `dir`
+pub fn dir_entries(aram: &[u8], dir: u16) -> Vec<DirEntry> {
+    let word = |addr: u16| aram[addr as usize] as u16 | (aram[addr.wrapping_add(1) as usize] as u16) << 8;
+    (0..256).map(|srcn| {
+        let addr = dir.wrapping_add(srcn * 4);
+        DirEntry {
+            srcn: srcn as u8,
+            start: word(addr),
+            loop_addr: word(addr.wrapping_add(2)),
+        }
+    }).collect()
+}
+
+/// A decoded sample
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub samples: Vec<i16>,
+    /// Number of BRR blocks
+    pub blocks: usize,
+    /// Whether the last block has the loop flag set
+    pub loops: bool,
+    /// Index of the first sample of the loop, if it loops into this sample
+    pub loop_start: Option<usize>,
+    /// Whether a block with the end flag was found
+    pub complete: bool,
+}
+
+impl Sample {
+    /// Returns the sample with the loop repeated until it's at least `min_len` samples long
+    pub fn looped(&self, min_len: usize) -> Vec<i16> {
+        let mut out = self.samples.clone();
+        if let Some(start) = self.loop_start {
+            if start < self.samples.len() {
+                while out.len() < min_len {
+                    out.extend_from_slice(&self.samples[start..]);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Decodes a single 4-bit sample with the filter and shift from the block header
+///
+/// `p1` and `p2` are the last two output samples. This follows the DSP's fixed point arithmetic,
+/// including the clipping of the intermediate result.
+fn decode_nibble(nibble: i32, header: u8, p1: i32, p2: i32) -> i16 {
+    let shift = header >> 4;
+    let mut s = if shift <= 12 {
+        (nibble << shift) >> 1
+    } else {
+        // Invalid shifts behave like 12, but only keep the sign
+        if nibble < 0 { -2048 } else { 0 }
+    };
+
+    let p2 = p2 >> 1;
+    match (header >> 2) & 0x03 {
+        0 => {}
+        1 => s += (p1 >> 1) + ((-p1) >> 5),
+        2 => s += p1 - p2 + (p2 >> 4) + ((p1 * -3) >> 6),
+        _ => s += p1 - p2 + ((p1 * -13) >> 7) + ((p2 * 3) >> 4),
+    }
+
+    // Clamp to 16 bits, then drop the top bit
+    let s = s.max(-0x8000).min(0x7fff);
+    (s << 1) as i16
+}
+
+/// Counts the blocks of the sample starting at `start`, without decoding it. Returns `None` if
+/// there's no end block.
+pub fn block_count(aram: &[u8], start: u16) -> Option<usize> {
+    let mut addr = start;
+    for blocks in 1..MAX_BLOCKS + 1 {
+        if aram[addr as usize] & 0x01 != 0 {
+            return Some(blocks);
+        }
+        addr = addr.wrapping_add(BLOCK_LEN as u16);
+    }
+    None
+}
+
+/// Decodes the sample described by `entry`
+pub fn decode(aram: &[u8], entry: &DirEntry) -> Sample {
+    let mut samples = Vec::new();
+    let (mut p1, mut p2) = (0, 0);
+    let mut addr = entry.start;
+    let mut blocks = 0;
+    let mut last_header = 0;
+    let mut complete = false;
+
+    while blocks < MAX_BLOCKS {
+        let header = aram[addr as usize];
+        for i in 0..BLOCK_SAMPLES as u16 {
+            let byte = aram[addr.wrapping_add(1 + i / 2) as usize];
+            let nibble = if i % 2 == 0 { byte >> 4 } else { byte & 0x0f };
+            // Sign-extend the nibble
+            let nibble = ((nibble as i8) << 4 >> 4) as i32;
+            let s = decode_nibble(nibble, header, p1, p2) as i32;
+            p2 = p1;
+            p1 = s;
+            samples.push(s as i16);
+        }
+        blocks += 1;
+        last_header = header;
+        addr = addr.wrapping_add(BLOCK_LEN as u16);
+        if header & 0x01 != 0 {
+            complete = true;
+            break;
+        }
+    }
+
+    let loops = complete && last_header & 0x02 != 0;
+    let loop_offset = entry.loop_addr.wrapping_sub(entry.start) as usize;
+    let loop_start = if loops && loop_offset % BLOCK_LEN == 0 && loop_offset / BLOCK_LEN < blocks {
+        Some(loop_offset / BLOCK_LEN * BLOCK_SAMPLES)
+    } else {
+        None
+    };
+
+    Sample {
+        samples: samples,
+        blocks: blocks,
+        loops: loops,
+        loop_start: loop_start,
+        complete: complete,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(start: u16, loop_addr: u16) -> DirEntry {
+        DirEntry { srcn: 0, start: start, loop_addr: loop_addr }
+    }
+
+    #[test]
+    fn single_block() {
+        let mut aram = vec![0; 0x10000];
+        // Shift 11, filter 0, end flag
+        aram[0x200..0x209].copy_from_slice(&[0xb1, 0x1f, 0x78, 0, 0, 0, 0, 0, 0]);
+
+        let sample = decode(&aram, &entry(0x200, 0x200));
+        assert_eq!(sample.blocks, 1);
+        assert!(sample.complete);
+        assert!(!sample.loops);
+        assert_eq!(sample.loop_start, None);
+        assert_eq!(&sample.samples[..4], &[2048, -2048, 14336, -16384]);
+        assert!(sample.samples[4..].iter().all(|&s| s == 0));
+        assert_eq!(block_count(&aram, 0x200), Some(1));
+    }
+
+    #[test]
+    fn filter_1() {
+        let mut aram = vec![0; 0x10000];
+        // Shift 11, filter 1, end flag
+        aram[0x200..0x209].copy_from_slice(&[0xb5, 0x10, 0, 0, 0, 0, 0, 0, 0]);
+
+        let sample = decode(&aram, &entry(0x200, 0x200));
+        assert_eq!(&sample.samples[..3], &[2048, 1920, 1800]);
+    }
+
+    #[test]
+    fn invalid_shift() {
+        let mut aram = vec![0; 0x10000];
+        // Shift 13 only keeps the sign
+        aram[0x200..0x209].copy_from_slice(&[0xd1, 0x7f, 0, 0, 0, 0, 0, 0, 0]);
+
+        let sample = decode(&aram, &entry(0x200, 0x200));
+        assert_eq!(&sample.samples[..2], &[0, -4096]);
+    }
+
+    #[test]
+    fn looping() {
+        let mut aram = vec![0; 0x10000];
+        aram[0x200] = 0xb0;
+        // End and loop flags
+        aram[0x209] = 0xb3;
+
+        let sample = decode(&aram, &entry(0x200, 0x209));
+        assert_eq!(sample.blocks, 2);
+        assert!(sample.loops);
+        assert_eq!(sample.loop_start, Some(BLOCK_SAMPLES));
+        assert_eq!(sample.looped(40).len(), 48);
+        assert_eq!(block_count(&aram, 0x200), Some(2));
+    }
+
+    #[test]
+    fn missing_end() {
+        let aram = vec![0; 0x10000];
+        let sample = decode(&aram, &entry(0x200, 0x200));
+        assert!(!sample.complete);
+        assert_eq!(sample.blocks, MAX_BLOCKS);
+        assert_eq!(block_count(&aram, 0x200), None);
+    }
+
+    #[test]
+    fn directory() {
+        let mut aram = vec![0; 0x10000];
+        aram[0x3004..0x3008].copy_from_slice(&[0x00, 0x12, 0x09, 0x12]);
+        let entries = dir_entries(&aram, 0x3000);
+        assert_eq!(entries.len(), 256);
+        assert_eq!((entries[1].srcn, entries[1].start, entries[1].loop_addr), (1, 0x1200, 0x1209));
+    }
+}
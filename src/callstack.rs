@@ -0,0 +1,183 @@
+//! Tracking of subroutine calls and interrupts
+
+use data::CpuState;
+use disasm::Instr;
+use timeline::EventKind;
+
+/// Maximum number of tracked nested calls. Code that never returns (or manipulates the stack) would
+/// otherwise grow the stack forever.
+const MAX_DEPTH: usize = 256;
+
+/// An active subroutine call or interrupt
+#[derive(Clone, Debug)]
+pub struct StackEntry {
+    /// How the subroutine was entered (`JSR`, `JSL`, `NMI`, ...)
+    pub kind: &'static str,
+    /// Address of the called subroutine or interrupt handler
+    pub target: u32,
+    /// Address of the call instruction, or of the interrupted instruction
+    pub call_site: u32,
+    /// Address execution continues at when the subroutine returns
+    pub return_addr: u32,
+    /// Stack pointer before the call. Returning restores it.
+    pub sp: u16,
+}
+
+#[derive(Clone, Default)]
+pub struct CallStack {
+    entries: Vec<StackEntry>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        CallStack::default()
+    }
+
+    /// Creates a call stack with known active calls, outermost first
+    pub fn from_entries(entries: Vec<StackEntry>) -> Self {
+        CallStack { entries: entries }
+    }
+
+    /// Active calls, outermost first
+    pub fn entries(&self) -> &[StackEntry] {
+        &self.entries
+    }
+
+    fn push(&mut self, entry: StackEntry) {
+        if self.entries.len() == MAX_DEPTH {
+            self.entries.remove(0);
+        }
+        self.entries.push(entry);
+    }
+
+    /// Updates the call stack after `instr` was executed
+    ///
+    /// `before` and `after` are the CPU states around the instruction. `interrupt` is the kind of
+    /// interrupt that was taken right after the instruction, if any. `peek` is used to read the
+    /// return address of interrupts from the stack.
+    pub fn update<F>(&mut self,
+                     instr: &Instr,
+                     before: &CpuState,
+                     after: &CpuState,
+                     interrupt: Option<EventKind>,
+                     mut peek: F) where F: FnMut(u32) -> u8 {
+        // Number of bytes an interrupt pushes on the stack
+        let interrupt_frame = if after.emulation { 3 } else { 4 };
+        // Stack pointer and program counter right after the instruction, before the interrupt
+        let (sp, pc) = if interrupt.is_some() {
+            let sp = after.s.wrapping_add(interrupt_frame);
+            (sp, interrupt_return_addr(after, &mut peek))
+        } else {
+            (after.s, after.pc24())
+        };
+
+        if instr.is_call() {
+            self.push(StackEntry {
+                kind: instr.mnemonic,
+                target: pc,
+                call_site: instr.addr,
+                return_addr: instr.next_addr(),
+                sp: before.s,
+            });
+        } else if instr.opcode == 0x00 || instr.opcode == 0x02 {
+            // BRK and COP behave like interrupts, but return past their signature byte
+            self.push(StackEntry {
+                kind: instr.mnemonic,
+                target: pc,
+                call_site: instr.addr,
+                return_addr: instr.addr + 2,
+                sp: before.s,
+            });
+        } else if instr.is_return() {
+            // Also unwinds calls whose return address was discarded
+            while self.entries.last().map_or(false, |entry| entry.sp <= sp) {
+                self.entries.pop();
+            }
+        }
+
+        if let Some(kind) = interrupt {
+            self.push(StackEntry {
+                kind: kind.name(),
+                target: after.pc24(),
+                call_site: pc,
+                return_addr: pc,
+                sp: sp,
+            });
+        }
+    }
+}
+
+/// Reads the return address pushed by an interrupt that was just taken
+pub fn interrupt_return_addr<F>(cpu: &CpuState, mut peek: F) -> u32 where F: FnMut(u32) -> u8 {
+    // The status register is at the top, followed by the return address (and bank, in native mode)
+    let s = cpu.s as u32;
+    let addr = peek(s + 2) as u32 | (peek(s + 3) as u32) << 8;
+    if cpu.emulation {
+        addr
+    } else {
+        (peek(s + 4) as u32) << 16 | addr
+    }
+}
+
+/// Reconstructs the active subroutine calls by scanning the stack for return addresses
+///
+/// This is a heuristic: a value on the stack is taken to be a return address if it points right
+/// behind a `JSR` or `JSL` instruction. Interrupts and indirect calls aren't found, and stale or
+/// unrelated data may be mistaken for return addresses. Returns the calls outermost first.
+pub fn scan_stack<F>(cpu: &CpuState, mut peek: F) -> Vec<StackEntry> where F: FnMut(u32) -> u8 {
+    let end = if cpu.emulation {
+        0x1ff
+    } else {
+        (cpu.s as u32 + 0xff).min(0x1fff)
+    };
+    // Bank of the caller of the innermost call not found yet. `JSR` stays in the bank, `JSL` pushes
+    // the bank of its caller.
+    let mut bank = cpu.pbr as u32;
+    let mut entries = Vec::new();
+    let mut pos = cpu.s as u32 + 1;
+    while pos < end {
+        let word = peek(pos) as u32 | (peek(pos + 1) as u32) << 8;
+
+        // `JSL` pushes the address of its last byte, with the bank on top
+        if pos + 1 < end {
+            let ret_bank = peek(pos + 2) as u32;
+            let ret = ret_bank << 16 | (word + 1) & 0xffff;
+            let call = ret_bank << 16 | word.wrapping_sub(3) & 0xffff;
+            if peek(call) == 0x22 {
+                let target = peek(call + 1) as u32 | (peek(call + 2) as u32) << 8 |
+                    (peek(call + 3) as u32) << 16;
+                entries.push(StackEntry {
+                    kind: "JSL",
+                    target: target,
+                    call_site: call,
+                    return_addr: ret,
+                    sp: (pos + 2) as u16,
+                });
+                bank = ret_bank;
+                pos += 3;
+                continue;
+            }
+        }
+
+        // `JSR` pushes the address of its last byte
+        let ret = bank << 16 | (word + 1) & 0xffff;
+        let call = bank << 16 | word.wrapping_sub(2) & 0xffff;
+        if peek(call) == 0x20 {
+            let target = bank << 16 | peek(call + 1) as u32 | (peek(call + 2) as u32) << 8;
+            entries.push(StackEntry {
+                kind: "JSR",
+                target: target,
+                call_site: call,
+                return_addr: ret,
+                sp: (pos + 1) as u16,
+            });
+            pos += 2;
+            continue;
+        }
+
+        pos += 1;
+    }
+
+    entries.reverse();
+    entries
+}
@@ -0,0 +1,300 @@
+//! Code/data logging: classifies ROM bytes by how they were accessed
+//!
+//! The log has one byte of flags per ROM byte, in the same order as the ROM image. The flags and
+//! the file format are the ones used by Mesen-S: the file starts with `CDLv2` and the CRC32 of the
+//! ROM, followed by the flags. Bits 0-5 have the same meaning as in Mesen-S. `OPCODE` and `DMA` are
+//! additions of breeze-dbg in the bits Mesen-S leaves unused, so other tools will ignore them.
+
+use data::{CpuState, DmaState};
+use disasm::{Instr, MemAccess, Access};
+use patch::crc32;
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// Byte of an executed instruction (opcode or operand)
+pub const CODE: u8 = 0x01;
+/// Read as data by the CPU or a DMA transfer
+pub const DATA: u8 = 0x02;
+/// Target of a jump or branch
+pub const JUMP_TARGET: u8 = 0x04;
+/// Entry point of a subroutine
+pub const SUB_ENTRY: u8 = 0x08;
+/// Set on instructions executed with 8-bit index registers
+pub const X8: u8 = 0x10;
+/// Set on instructions executed with an 8-bit accumulator
+pub const M8: u8 = 0x20;
+/// First byte of an executed instruction. Code bytes without this flag are operands.
+pub const OPCODE: u8 = 0x40;
+/// Source of a DMA transfer (also marked as `DATA`)
+pub const DMA: u8 = 0x80;
+
+/// Signature at the start of log files
+const MAGIC: &'static [u8] = b"CDLv2";
+/// Size of the file header: signature and CRC32 of the ROM
+const HEADER_LEN: usize = 9;
+
+/// Coverage of a single ROM bank
+#[derive(Clone, Copy, Default, Debug)]
+pub struct BankCoverage {
+    /// Bytes logged as opcode or operand
+    pub code: usize,
+    /// Bytes logged as data (CPU reads or DMA), but not as code
+    pub data: usize,
+    /// Size of the bank
+    pub size: usize,
+}
+
+impl BankCoverage {
+    /// Fraction of the bank that was logged, in percent
+    pub fn percent(&self) -> f64 {
+        (self.code + self.data) as f64 * 100.0 / self.size.max(1) as f64
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct CodeDataLog {
+    flags: Vec<u8>,
+    /// Number of ROM bytes in a bank, used for the coverage statistics
+    bank_size: usize,
+}
+
+impl CodeDataLog {
+    /// Creates an empty log for a ROM of `len` bytes
+    pub fn new(len: usize, bank_size: usize) -> Self {
+        CodeDataLog {
+            flags: vec![0; len],
+            bank_size: bank_size,
+        }
+    }
+
+    /// Loads the log of `rom` from a file
+    ///
+    /// The CRC32 stored in the file must match the ROM. Files without header are accepted if they
+    /// have the size of the ROM.
+    pub fn load(path: &Path, rom: &[u8], bank_size: usize) -> io::Result<CodeDataLog> {
+        let mut content = vec![];
+        try!(try!(File::open(path)).read_to_end(&mut content));
+        let flags = if content.starts_with(MAGIC) && content.len() == HEADER_LEN + rom.len() {
+            let crc = content[5] as u32 | (content[6] as u32) << 8 | (content[7] as u32) << 16 |
+                (content[8] as u32) << 24;
+            if crc != crc32(rom) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "log was recorded for a different ROM"));
+            }
+            content.split_off(HEADER_LEN)
+        } else if content.len() == rom.len() {
+            content
+        } else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("log size ({} bytes) doesn't match the ROM size ({} bytes)", content.len(), rom.len())));
+        };
+        Ok(CodeDataLog {
+            flags: flags,
+            bank_size: bank_size,
+        })
+    }
+
+    /// Saves the log of `rom` to a file
+    pub fn save(&self, path: &Path, rom: &[u8]) -> io::Result<()> {
+        let crc = crc32(rom);
+        let mut file = try!(File::create(path));
+        try!(file.write_all(MAGIC));
+        try!(file.write_all(&[crc as u8, (crc >> 8) as u8, (crc >> 16) as u8, (crc >> 24) as u8]));
+        file.write_all(&self.flags)
+    }
+
+    /// Flags of all ROM bytes
+    pub fn flags(&self) -> &[u8] {
+        &self.flags
+    }
+
+    pub fn bank_size(&self) -> usize {
+        self.bank_size
+    }
+
+    fn mark(&mut self, offset: Option<usize>, flags: u8) {
+        if let Some(flag) = offset.and_then(|offset| self.flags.get_mut(offset)) {
+            *flag |= flags;
+        }
+    }
+
+    /// Logs the execution of `instr` in CPU state `cpu`
+    ///
+    /// `rom_offset` translates bus addresses to ROM offsets. `pc_after` is the program counter after
+    /// the instruction, which is the entry point of the subroutine if it was a call.
+    pub fn log_instr<F>(&mut self, instr: &Instr, cpu: &CpuState, pc_after: u32, rom_offset: F)
+            where F: Fn(u32) -> Option<usize> {
+        let mut flags = CODE;
+        if cpu.m8() { flags |= M8; }
+        if cpu.x8() { flags |= X8; }
+        self.mark(rom_offset(instr.addr), flags | OPCODE);
+        for i in 1..instr.len as u32 {
+            self.mark(rom_offset(instr.addr + i), flags);
+        }
+
+        if let Some(target) = instr.target() {
+            self.mark(rom_offset(target), JUMP_TARGET);
+        }
+        if instr.is_call() {
+            self.mark(rom_offset(pc_after), JUMP_TARGET | SUB_ENTRY);
+        }
+    }
+
+    /// Logs the data reads among `accesses`
+    pub fn log_accesses<F>(&mut self, accesses: &[MemAccess], rom_offset: F)
+            where F: Fn(u32) -> Option<usize> {
+        for access in accesses.iter().filter(|access| access.kind != Access::Write) {
+            for i in 0..access.len as u32 {
+                self.mark(rom_offset(access.addr + i), DATA);
+            }
+        }
+    }
+
+    /// Logs the sources of the DMA transfers started by writing `mdmaen` to `$420B`
+    ///
+    /// `dma` is the DMA configuration before the transfers.
+    pub fn log_dma<F>(&mut self, dma: &DmaState, mdmaen: u8, rom_offset: F)
+            where F: Fn(u32) -> Option<usize> {
+        for ch in (0..8).filter(|ch| mdmaen & (1 << ch) != 0) {
+            let regs = &dma.channels[ch];
+            // Only transfers from the A bus (to the PPU etc.) read ROM
+            if regs[0] & 0x80 != 0 {
+                continue;
+            }
+            let bank = (regs[4] as u32) << 16;
+            let start = regs[2] as u32 | (regs[3] as u32) << 8;
+            let size = match regs[5] as u32 | (regs[6] as u32) << 8 {
+                0 => 0x10000,
+                size => size,
+            };
+            // Fixed transfers read the same byte over and over
+            let count = if regs[0] & 0x08 != 0 { 1 } else { size };
+            let decrement = regs[0] & 0x18 == 0x10;
+            for i in 0..count {
+                let offset = if decrement { start.wrapping_sub(i) } else { start.wrapping_add(i) };
+                self.mark(rom_offset(bank | offset & 0xffff), DATA | DMA);
+            }
+        }
+    }
+
+    /// Returns the coverage of each bank
+    pub fn coverage(&self) -> Vec<BankCoverage> {
+        self.flags.chunks(self.bank_size.max(1)).map(|bank| {
+            let code = bank.iter().filter(|&&f| f & CODE != 0).count();
+            let logged = bank.iter().filter(|&&f| f & (CODE | DATA) != 0).count();
+            BankCoverage {
+                code: code,
+                data: logged - code,
+                size: bank.len(),
+            }
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::{CpuState, DmaState};
+    use disasm::Instr;
+
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    /// Maps `$00:8000-$FFFF` to the ROM
+    fn rom_offset(addr: u32) -> Option<usize> {
+        if addr >> 16 == 0 && addr & 0x8000 != 0 { Some(addr as usize & 0x7fff) } else { None }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("breeze-dbg-test-{}.cdl", name))
+    }
+
+    #[test]
+    fn save_and_load() {
+        let rom = (0..0x100).map(|i| i as u8).collect::<Vec<_>>();
+        let mut cdl = CodeDataLog::new(rom.len(), 0x80);
+        cdl.flags[3] = CODE | OPCODE;
+        cdl.flags[0x90] = DATA;
+        let path = temp_path("roundtrip");
+        cdl.save(&path, &rom).unwrap();
+
+        let mut content = vec![];
+        File::open(&path).unwrap().read_to_end(&mut content).unwrap();
+        assert_eq!(&content[..5], b"CDLv2");
+        let crc = crc32(&rom);
+        assert_eq!(&content[5..9], &[crc as u8, (crc >> 8) as u8, (crc >> 16) as u8, (crc >> 24) as u8]);
+        assert_eq!(content.len(), HEADER_LEN + rom.len());
+
+        let loaded = CodeDataLog::load(&path, &rom, 0x80).unwrap();
+        assert_eq!(loaded.flags(), cdl.flags());
+        assert_eq!(loaded.bank_size(), 0x80);
+
+        // A log of another ROM of the same size is rejected
+        let other = vec![0; rom.len()];
+        assert!(CodeDataLog::load(&path, &other, 0x80).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_headerless() {
+        let rom = vec![0; 0x40];
+        let path = temp_path("headerless");
+        let mut flags = vec![0; rom.len()];
+        flags[1] = DATA;
+        File::create(&path).unwrap().write_all(&flags).unwrap();
+        assert_eq!(CodeDataLog::load(&path, &rom, 0x20).unwrap().flags(), &flags[..]);
+
+        // Wrong size
+        File::create(&path).unwrap().write_all(&flags[..0x3f]).unwrap();
+        assert!(CodeDataLog::load(&path, &rom, 0x20).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn instructions() {
+        // JSR $8123
+        let rom = [0x20, 0x23, 0x81];
+        let instr = Instr::decode(0x008000, true, true, |addr| rom[addr as usize & 0x7fff]);
+        let mut cpu = CpuState::default();
+        cpu.p = 0x30;
+        let mut cdl = CodeDataLog::new(0x200, 0x100);
+        cdl.log_instr(&instr, &cpu, 0x008123, rom_offset);
+
+        assert_eq!(cdl.flags()[0], CODE | OPCODE | M8 | X8);
+        assert_eq!(&cdl.flags()[1..3], &[CODE | M8 | X8, CODE | M8 | X8]);
+        assert_eq!(cdl.flags()[0x123], JUMP_TARGET | SUB_ENTRY);
+        assert_eq!(cdl.flags()[3], 0);
+    }
+
+    #[test]
+    fn dma() {
+        let mut dma = DmaState::default();
+        // Channel 0: increment from $00:8010, 4 bytes
+        dma.channels[0] = [0x01, 0x18, 0x10, 0x80, 0x00, 0x04, 0x00, 0, 0, 0, 0];
+        // Channel 1: decrement from $00:8020, 3 bytes
+        dma.channels[1] = [0x10, 0x18, 0x20, 0x80, 0x00, 0x03, 0x00, 0, 0, 0, 0];
+        // Channel 2: fixed source $00:8030, 100 bytes
+        dma.channels[2] = [0x08, 0x18, 0x30, 0x80, 0x00, 100, 0x00, 0, 0, 0, 0];
+        // Channel 3: B bus to A bus, doesn't read ROM
+        dma.channels[3] = [0x80, 0x18, 0x40, 0x80, 0x00, 0x04, 0x00, 0, 0, 0, 0];
+        // Channel 4: not started
+        dma.channels[4] = [0x00, 0x18, 0x50, 0x80, 0x00, 0x04, 0x00, 0, 0, 0, 0];
+
+        let mut cdl = CodeDataLog::new(0x100, 0x100);
+        cdl.log_dma(&dma, 0x0f, rom_offset);
+        let marked = cdl.flags().iter().enumerate()
+            .filter(|&(_, &f)| f != 0)
+            .map(|(offset, &f)| {
+                assert_eq!(f, DATA | DMA);
+                offset
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(marked, [0x10, 0x11, 0x12, 0x13, 0x1e, 0x1f, 0x20, 0x30]);
+
+        let coverage = cdl.coverage();
+        assert_eq!((coverage[0].code, coverage[0].data), (0, 8));
+    }
+}
@@ -0,0 +1,190 @@
+//! Game Genie and Pro Action Replay cheat codes
+//!
+//! Both kinds of codes decode to a single byte that is written to a 24-bit address. Codes
+//! targeting ROM patch the ROM image, codes targeting WRAM are applied every frame.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// Game Genie encodes hex digits with these characters
+const GENIE_DIGITS: &'static str = "DF4709156BC8A23E";
+
+/// Code formats
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// `XXXX-XXXX`, using the Game Genie alphabet and a scrambled address
+    GameGenie,
+    /// `AAAAAAVV` (or `AAAAAA:VV`), a plain address followed by the value
+    ProActionReplay,
+}
+
+impl Format {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Format::GameGenie => "Game Genie",
+            Format::ProActionReplay => "Pro Action Replay",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Cheat {
+    /// The code as entered by the user
+    pub code: String,
+    pub description: String,
+    pub enabled: bool,
+    pub format: Format,
+    /// 24-bit bus address to patch
+    pub addr: u32,
+    pub value: u8,
+}
+
+impl Cheat {
+    /// Decodes a Game Genie or Pro Action Replay code
+    pub fn decode(code: &str, description: &str) -> Result<Cheat, String> {
+        let code = code.trim().to_uppercase();
+        let (format, addr, value) = if code.len() == 9 && code.as_bytes()[4] == b'-' {
+            let (addr, value) = try!(decode_genie(&code));
+            (Format::GameGenie, addr, value)
+        } else {
+            let raw = code.replace(':', "");
+            if raw.len() != 8 {
+                return Err(format!("'{}' is not a Game Genie or Pro Action Replay code", code));
+            }
+            let n = try!(parse_hex_digits(&raw, &code));
+            (Format::ProActionReplay, n >> 8, n as u8)
+        };
+
+        Ok(Cheat {
+            code: code,
+            description: description.trim().to_string(),
+            enabled: true,
+            format: format,
+            addr: addr,
+            value: value,
+        })
+    }
+
+    /// Serializes the cheat as a tab-separated line
+    pub fn to_line(&self) -> String {
+        format!("{}\t{}\t{}",
+            if self.enabled { "on" } else { "off" },
+            self.code,
+            self.description.replace('\t', " "))
+    }
+
+    /// Parses a line created by `to_line`
+    pub fn from_line(line: &str) -> Result<Cheat, String> {
+        let mut fields = line.splitn(3, '\t');
+        let enabled = fields.next().unwrap_or("") == "on";
+        let code = fields.next().unwrap_or("");
+        let description = fields.next().unwrap_or("");
+        let mut cheat = try!(Cheat::decode(code, description));
+        cheat.enabled = enabled;
+        Ok(cheat)
+    }
+}
+
+fn parse_hex_digits(digits: &str, code: &str) -> Result<u32, String> {
+    u32::from_str_radix(digits, 16).map_err(|_| format!("Invalid code '{}'", code))
+}
+
+/// Decodes a Game Genie code into address and value
+fn decode_genie(code: &str) -> Result<(u32, u8), String> {
+    // Translate to regular hex digits first
+    let mut hex = String::with_capacity(8);
+    for c in code.chars().filter(|&c| c != '-') {
+        match GENIE_DIGITS.find(c) {
+            Some(digit) => hex.push_str(&format!("{:X}", digit)),
+            None => return Err(format!("Invalid Game Genie code '{}'", code)),
+        }
+    }
+    let n = try!(parse_hex_digits(&hex, code));
+    let value = (n >> 24) as u8;
+
+    // The address bits are shuffled around
+    let a = n & 0xffffff;
+    let addr = (a & 0x003c00) << 10 |
+               (a & 0x00003c) << 14 |
+               (a & 0xf00000) >> 8 |
+               (a & 0x000003) << 10 |
+               (a & 0x00c000) >> 6 |
+               (a & 0x0f0000) >> 12 |
+               (a & 0x0003c0) >> 6;
+    Ok((addr, value))
+}
+
+/// Loads a cheat list. A missing file results in an empty list.
+pub fn load(path: &Path) -> io::Result<Vec<Cheat>> {
+    let mut content = String::new();
+    match File::open(path) {
+        Ok(mut file) => { try!(file.read_to_string(&mut content)); }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    }
+
+    let mut cheats = Vec::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        match Cheat::from_line(line) {
+            Ok(cheat) => cheats.push(cheat),
+            Err(e) => warn!("{}: skipping cheat: {}", path.display(), e),
+        }
+    }
+    Ok(cheats)
+}
+
+/// Saves a cheat list, replacing the file's contents
+pub fn save(path: &Path, cheats: &[Cheat]) -> io::Result<()> {
+    let mut file = try!(File::create(path));
+    for cheat in cheats {
+        try!(writeln!(file, "{}", cheat.to_line()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_genie() {
+        let cheat = Cheat::decode(" c2a4-0dad ", "").unwrap();
+        assert_eq!(cheat.format, Format::GameGenie);
+        assert_eq!(cheat.code, "C2A4-0DAD");
+        assert_eq!(cheat.addr, 0x00c123);
+        assert_eq!(cheat.value, 0xad);
+    }
+
+    #[test]
+    fn game_genie_invalid_digit() {
+        assert!(Cheat::decode("C2A4-0DAX", "").is_err());
+    }
+
+    #[test]
+    fn pro_action_replay() {
+        for code in &["7E0DBF05", "7E0DBF:05"] {
+            let cheat = Cheat::decode(code, "").unwrap();
+            assert_eq!(cheat.format, Format::ProActionReplay);
+            assert_eq!(cheat.addr, 0x7e0dbf);
+            assert_eq!(cheat.value, 0x05);
+        }
+    }
+
+    #[test]
+    fn invalid_codes() {
+        assert!(Cheat::decode("7E0DBF0", "").is_err());
+        assert!(Cheat::decode("7E0DBFXX", "").is_err());
+    }
+
+    #[test]
+    fn line_roundtrip() {
+        let mut cheat = Cheat::decode("7E0DBF05", "Lives\tinfinite").unwrap();
+        cheat.enabled = false;
+        let parsed = Cheat::from_line(&cheat.to_line()).unwrap();
+        assert!(!parsed.enabled);
+        assert_eq!(parsed.code, "7E0DBF05");
+        assert_eq!(parsed.description, "Lives infinite");
+        assert_eq!((parsed.addr, parsed.value), (0x7e0dbf, 0x05));
+    }
+}
@@ -0,0 +1,70 @@
+//! Persistent per-user data, stored in `$XDG_CONFIG_HOME/breeze-dbg` (`~/.config/breeze-dbg` if
+//! unset)
+//!
+//! Files are simple line-based text files, with tab-separated fields where needed.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+/// Returns the configuration directory, creating it if necessary
+pub fn config_dir() -> io::Result<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => match env::home_dir() {
+            Some(home) => home.join(".config"),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "home directory not found")),
+        },
+    };
+    let dir = base.join("breeze-dbg");
+    try!(fs::create_dir_all(&dir));
+    Ok(dir)
+}
+
+/// Returns the path of a file storing data of the given kind (eg. `"watches"`) for the ROM with
+/// the given title
+pub fn rom_file(kind: &str, rom_title: &str) -> io::Result<PathBuf> {
+    // Titles can contain anything, so only keep characters that are safe in file names
+    let name = rom_title.trim().chars().map(|c| {
+        if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }
+    }).collect::<String>();
+
+    Ok(try!(kind_dir(kind)).join(format!("{}.txt", name)))
+}
+
+/// Returns the path of a file storing data of the given kind for the ROM image with the given
+/// CRC32
+///
+/// Unlike the title, the checksum tells a ROM hack apart from the original game.
+pub fn rom_crc_file(kind: &str, crc: u32) -> io::Result<PathBuf> {
+    Ok(try!(kind_dir(kind)).join(format!("{:08X}.txt", crc)))
+}
+
+/// Returns the directory storing data of the given kind, creating it if necessary
+fn kind_dir(kind: &str) -> io::Result<PathBuf> {
+    let dir = try!(config_dir()).join(kind);
+    try!(fs::create_dir_all(&dir));
+    Ok(dir)
+}
+
+/// Reads all non-empty lines from a file. A missing file is treated like an empty one.
+pub fn read_lines(path: &PathBuf) -> io::Result<Vec<String>> {
+    let mut content = String::new();
+    match File::open(path) {
+        Ok(mut file) => { try!(file.read_to_string(&mut content)); }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    Ok(content.lines().filter(|line| !line.trim().is_empty()).map(|line| line.to_string()).collect())
+}
+
+/// Replaces the contents of a file with the given lines
+pub fn write_lines(path: &PathBuf, lines: &[String]) -> io::Result<()> {
+    let mut file = try!(File::create(path));
+    for line in lines {
+        try!(writeln!(file, "{}", line));
+    }
+    Ok(())
+}
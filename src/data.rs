@@ -3,9 +3,27 @@
 //! Note that the values are collected after the fact, which means they can technically sometimes
 //! change mid-frame and become inconsistent.
 
+use breakpoint::Breakpoint;
+use callstack::StackEntry;
+use cdl::CodeDataLog;
+use cheat::Cheat;
+use hdma::HdmaCapture;
+use header::RomHeader;
+use memmap::MapMode;
+use mmio::MmioAccess;
+use profiler::Profile;
+use romfile::RomSource;
+use symbols::SymbolTable;
+use timeline::TimingEvent;
+
+use breeze_core::apu::Apu;
+use breeze_core::cpu::Cpu;
+use breeze_core::snes::Peripherals;
 use breeze_core::ppu::Ppu;
 use breeze_core::ppu::oam::OamEntry;
 
+use std::path::Path;
+
 /// Created from `OamEntry`s and PPU state
 pub struct Sprite {
     /// Start address of tile data in VRAM. Calculated from `tile` and `name_table` fields of
@@ -47,9 +65,488 @@ impl Sprite {
     }
 }
 
+/// 65816 register contents at the point where emulation was stopped
+#[derive(Clone, Copy, Default)]
+pub struct CpuState {
+    pub a: u16,
+    pub x: u16,
+    pub y: u16,
+    pub s: u16,
+    pub d: u16,
+    pub dbr: u8,
+    pub pbr: u8,
+    pub pc: u16,
+    /// Raw status register (`NVMXDIZC`)
+    pub p: u8,
+    pub emulation: bool,
+}
+
+impl CpuState {
+    pub fn new(cpu: &Cpu) -> Self {
+        CpuState {
+            a: cpu.a,
+            x: cpu.x,
+            y: cpu.y,
+            s: cpu.s,
+            d: cpu.d,
+            dbr: cpu.dbr,
+            pbr: cpu.pbr,
+            pc: cpu.pc,
+            p: cpu.p.0,
+            emulation: cpu.emulation,
+        }
+    }
+
+    /// 24-bit address of the next instruction to execute
+    pub fn pc24(&self) -> u32 {
+        (self.pbr as u32) << 16 | self.pc as u32
+    }
+
+    /// Whether the accumulator is 8 bits wide
+    pub fn m8(&self) -> bool {
+        self.emulation || self.p & 0x20 != 0
+    }
+
+    /// Whether the index registers are 8 bits wide
+    pub fn x8(&self) -> bool {
+        self.emulation || self.p & 0x10 != 0
+    }
+
+    /// Formats the status register in the usual `nvmxdizc` notation (upper case = flag set)
+    pub fn flags_string(&self) -> String {
+        "NVMXDIZC".chars().zip("nvmxdizc".chars()).enumerate().map(|(i, (set, clear))| {
+            if self.p & (0x80 >> i) != 0 { set } else { clear }
+        }).collect()
+    }
+}
+
+/// A disassembled instruction, ready for display
+pub struct DisasmLine {
+    pub addr: u32,
+    /// Raw instruction bytes in hex
+    pub bytes: String,
+    /// Instruction in assembler syntax
+    pub text: String,
+    /// Jump or branch target, if known
+    pub target: Option<u32>,
+}
+
+/// Memory areas that can be viewed and edited as raw bytes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemorySpace {
+    Wram,
+    Vram,
+    Oam,
+    Cgram,
+    /// Cartridge RAM
+    Sram,
+    /// Audio RAM of the SPC700
+    Aram,
+    Rom,
+}
+
+/// All memory spaces, in the order they should be presented to the user
+pub static MEMORY_SPACES: [MemorySpace; 7] = [
+    MemorySpace::Wram,
+    MemorySpace::Vram,
+    MemorySpace::Oam,
+    MemorySpace::Cgram,
+    MemorySpace::Sram,
+    MemorySpace::Aram,
+    MemorySpace::Rom,
+];
+
+impl MemorySpace {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            MemorySpace::Wram => "WRAM",
+            MemorySpace::Vram => "VRAM",
+            MemorySpace::Oam => "OAM",
+            MemorySpace::Cgram => "CGRAM",
+            MemorySpace::Sram => "SRAM",
+            MemorySpace::Aram => "ARAM",
+            MemorySpace::Rom => "ROM",
+        }
+    }
+}
+
+/// Copy of the raw contents of all writable memory spaces
+pub struct MemoryDump {
+    pub wram: Vec<u8>,
+    pub vram: Vec<u8>,
+    pub oam: Vec<u8>,
+    /// CGRAM as 256 little-endian words
+    pub cgram: Vec<u8>,
+    pub sram: Vec<u8>,
+    /// 64 KiB of audio RAM, including the part hidden by the IPL ROM
+    pub aram: Vec<u8>,
+}
+
+impl MemoryDump {
+    pub fn new(p: &Peripherals) -> Self {
+        let mut cgram = Vec::with_capacity(512);
+        for i in 0..256u16 {
+            let raw = p.ppu.cgram.get_color_raw(i as u8);
+            cgram.push(raw as u8);
+            cgram.push((raw >> 8) as u8);
+        }
+
+        MemoryDump {
+            wram: p.wram[..].to_vec(),
+            vram: p.ppu.vram[..].to_vec(),
+            oam: p.ppu.oam[..].to_vec(),
+            cgram: cgram,
+            sram: p.rom.ram().to_vec(),
+            aram: p.apu.mem[..].to_vec(),
+        }
+    }
+
+    /// Returns the contents of a memory space. The ROM isn't part of the dump, so this returns an
+    /// empty slice for `MemorySpace::Rom`.
+    pub fn get(&self, space: MemorySpace) -> &[u8] {
+        match space {
+            MemorySpace::Wram => &self.wram,
+            MemorySpace::Vram => &self.vram,
+            MemorySpace::Oam => &self.oam,
+            MemorySpace::Cgram => &self.cgram,
+            MemorySpace::Sram => &self.sram,
+            MemorySpace::Aram => &self.aram,
+            MemorySpace::Rom => &[],
+        }
+    }
+}
+
+/// Raw contents of the DMA registers
+#[derive(Clone, Copy, Default)]
+pub struct DmaState {
+    /// `$43x0`-`$43xA` of each channel
+    pub channels: [[u8; 11]; 8],
+    /// Channels selected for general purpose DMA (`$420B`)
+    pub mdmaen: u8,
+    /// Channels selected for HDMA (`$420C`)
+    pub hdmaen: u8,
+}
+
+impl DmaState {
+    pub fn new(p: &Peripherals) -> Self {
+        let mut channels = [[0; 11]; 8];
+        for (ch, regs) in channels.iter_mut().enumerate() {
+            for (reg, value) in regs.iter_mut().enumerate() {
+                *value = p.dma[ch].load(reg as u8);
+            }
+        }
+
+        DmaState {
+            channels: channels,
+            mdmaen: p.mdmaen,
+            hdmaen: p.hdmaen,
+        }
+    }
+}
+
+/// State of one of the three SPC700 timers
+#[derive(Clone, Copy, Default)]
+pub struct ApuTimer {
+    pub enabled: bool,
+    /// Divider (`$FA`-`$FC`)
+    pub target: u8,
+    /// 4-bit output counter (`$FD`-`$FF`)
+    pub counter: u8,
+}
+
+/// State of the audio processing unit: SPC700 and S-DSP registers. The audio RAM is part of the
+/// `MemoryDump`.
+#[derive(Clone, Default)]
+pub struct ApuState {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub pc: u16,
+    /// Raw status register (`NVPBHIZC`)
+    pub psw: u8,
+    /// Values written by the CPU to `$2140`-`$2143`, read by the SPC700 at `$F4`-`$F7`
+    pub cpu_to_apu: [u8; 4],
+    /// Values written by the SPC700 to `$F4`-`$F7`, read by the CPU at `$2140`-`$2143`
+    pub apu_to_cpu: [u8; 4],
+    /// Control register (`$F1`)
+    pub control: u8,
+    pub timers: [ApuTimer; 3],
+    /// The 128 S-DSP registers
+    pub dsp: Vec<u8>,
+}
+
+impl ApuState {
+    pub fn new(apu: &Apu) -> Self {
+        let cpu = apu.cpu();
+        let mut timers = [ApuTimer::default(); 3];
+        for (i, timer) in timers.iter_mut().enumerate() {
+            *timer = ApuTimer {
+                enabled: apu.timers[i].enabled(),
+                target: apu.timers[i].target(),
+                counter: apu.timers[i].counter(),
+            };
+        }
+        let mut cpu_to_apu = [0; 4];
+        let mut apu_to_cpu = [0; 4];
+        for port in 0..4 {
+            cpu_to_apu[port] = apu.port_from_cpu(port as u8);
+            apu_to_cpu[port] = apu.port_to_cpu(port as u8);
+        }
+
+        ApuState {
+            a: cpu.a,
+            x: cpu.x,
+            y: cpu.y,
+            sp: cpu.sp,
+            pc: cpu.pc,
+            psw: cpu.psw.0,
+            cpu_to_apu: cpu_to_apu,
+            apu_to_cpu: apu_to_cpu,
+            control: apu.control(),
+            timers: timers,
+            dsp: (0..128).map(|reg| apu.dsp.load(reg)).collect(),
+        }
+    }
+
+    /// Formats the status register in the usual `nvpbhizc` notation (upper case = flag set)
+    pub fn flags_string(&self) -> String {
+        "NVPBHIZC".chars().zip("nvpbhizc".chars()).enumerate().map(|(i, (set, clear))| {
+            if self.psw & (0x80 >> i) != 0 { set } else { clear }
+        }).collect()
+    }
+}
+
+/// Progress of an audio recording
+#[derive(Clone, Copy)]
+pub struct RecordingState<'a> {
+    pub path: &'a Path,
+    /// Whether each voice is written to its own track
+    pub per_voice: bool,
+    /// Number of recorded sample frames
+    pub frames: u32,
+}
+
+/// Interrupt and timer configuration of the CPU
+#[derive(Clone, Copy, Default)]
+pub struct TimingRegs {
+    /// Interrupt enable flags (`$4200`)
+    pub nmitimen: u8,
+    /// H timer target (`$4207`/`$4208`)
+    pub htime: u16,
+    /// V timer target (`$4209`/`$420A`)
+    pub vtime: u16,
+}
+
+impl TimingRegs {
+    pub fn new(p: &Peripherals) -> Self {
+        TimingRegs {
+            nmitimen: p.nmitimen,
+            htime: p.htime,
+            vtime: p.vtime,
+        }
+    }
+}
+
+/// How the value of a PPU register is read from the emulator
+#[derive(Clone, Copy)]
+pub enum PpuReg {
+    Byte(fn(&Ppu) -> u8),
+    /// A write-twice register or one only held in internal state (like `COLDATA`), read as the
+    /// full value latched by the PPU
+    Word(fn(&Ppu) -> u16),
+}
+
+impl PpuReg {
+    pub fn read(&self, ppu: &Ppu) -> u16 {
+        match *self {
+            PpuReg::Byte(fun) => fun(ppu) as u16,
+            PpuReg::Word(fun) => fun(ppu),
+        }
+    }
+
+    /// Formats a value of this register as hex
+    pub fn format(&self, value: u16) -> String {
+        match *self {
+            PpuReg::Byte(_) => format!("${:02X}", value),
+            PpuReg::Word(_) => format!("${:04X}", value),
+        }
+    }
+}
+
+/// PPU registers that can be read back from the emulator, sorted by address
+///
+/// The scroll registers hold 10 bits, the Mode 7 matrix 16 bits and the Mode 7 center 13 bits.
+/// `COLDATA` is the fixed color as BGR555.
+pub static PPU_REGS: &'static [(u16, PpuReg)] = &[
+    (0x2100, PpuReg::Byte(Ppu::inidisp)),
+    (0x2101, PpuReg::Byte(Ppu::obsel)),
+    (0x2105, PpuReg::Byte(Ppu::bgmode)),
+    (0x2106, PpuReg::Byte(Ppu::mosaic)),
+    (0x2107, PpuReg::Byte(Ppu::bg1sc)),
+    (0x2108, PpuReg::Byte(Ppu::bg2sc)),
+    (0x2109, PpuReg::Byte(Ppu::bg3sc)),
+    (0x210a, PpuReg::Byte(Ppu::bg4sc)),
+    (0x210b, PpuReg::Byte(Ppu::bg12nba)),
+    (0x210c, PpuReg::Byte(Ppu::bg34nba)),
+    (0x210d, PpuReg::Word(Ppu::bg1hofs)),
+    (0x210e, PpuReg::Word(Ppu::bg1vofs)),
+    (0x210f, PpuReg::Word(Ppu::bg2hofs)),
+    (0x2110, PpuReg::Word(Ppu::bg2vofs)),
+    (0x2111, PpuReg::Word(Ppu::bg3hofs)),
+    (0x2112, PpuReg::Word(Ppu::bg3vofs)),
+    (0x2113, PpuReg::Word(Ppu::bg4hofs)),
+    (0x2114, PpuReg::Word(Ppu::bg4vofs)),
+    (0x211a, PpuReg::Byte(Ppu::m7sel)),
+    (0x211b, PpuReg::Word(Ppu::m7a)),
+    (0x211c, PpuReg::Word(Ppu::m7b)),
+    (0x211d, PpuReg::Word(Ppu::m7c)),
+    (0x211e, PpuReg::Word(Ppu::m7d)),
+    (0x211f, PpuReg::Word(Ppu::m7x)),
+    (0x2120, PpuReg::Word(Ppu::m7y)),
+    (0x2123, PpuReg::Byte(Ppu::w12sel)),
+    (0x2124, PpuReg::Byte(Ppu::w34sel)),
+    (0x2125, PpuReg::Byte(Ppu::wobjsel)),
+    (0x2126, PpuReg::Byte(Ppu::wh0)),
+    (0x2127, PpuReg::Byte(Ppu::wh1)),
+    (0x2128, PpuReg::Byte(Ppu::wh2)),
+    (0x2129, PpuReg::Byte(Ppu::wh3)),
+    (0x212a, PpuReg::Byte(Ppu::wbglog)),
+    (0x212b, PpuReg::Byte(Ppu::wobjlog)),
+    (0x212c, PpuReg::Byte(Ppu::tm)),
+    (0x212d, PpuReg::Byte(Ppu::ts)),
+    (0x212e, PpuReg::Byte(Ppu::tmw)),
+    (0x212f, PpuReg::Byte(Ppu::tsw)),
+    (0x2130, PpuReg::Byte(Ppu::cgwsel)),
+    (0x2131, PpuReg::Byte(Ppu::cgadsub)),
+    (0x2132, PpuReg::Word(Ppu::coldata)),
+    (0x2133, PpuReg::Byte(Ppu::setini)),
+];
+
+/// Values of the registers in `PPU_REGS`, in the same order
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct PpuRegValues(pub Vec<u16>);
+
+impl PpuRegValues {
+    pub fn new(ppu: &Ppu) -> Self {
+        PpuRegValues(PPU_REGS.iter().map(|&(_, reg)| reg.read(ppu)).collect())
+    }
+
+    /// Returns the value of the 8-bit register at `addr`, which must be listed in `PPU_REGS`
+    pub fn get(&self, addr: u16) -> u8 {
+        self.get_word(addr) as u8
+    }
+
+    /// Returns the value of the register at `addr`, which must be listed in `PPU_REGS`
+    pub fn get_word(&self, addr: u16) -> u16 {
+        let index = PPU_REGS.binary_search_by(|&(a, _)| a.cmp(&addr)).expect("unknown PPU register");
+        self.0[index]
+    }
+}
+
+/// Number of visible scanlines (lines 0-224, including the blank line 0)
+pub const VISIBLE_LINES: u16 = 225;
+
+/// State recorded while emulating the frame following the stop
+///
+/// The per-scanline data covers the visible lines up to the end of the rendered frame. Everything
+/// else covers a whole frame (262 lines), starting at the stop.
+#[derive(Default)]
+pub struct FrameCapture {
+    pub hdma: HdmaCapture,
+    /// PPU registers at the start of each visible scanline, or `None` for lines that were already
+    /// rendered when emulation was stopped
+    pub ppu: Vec<Option<PpuRegValues>>,
+    /// CPU accesses to I/O registers, in order
+    pub mmio: Vec<MmioAccess>,
+    /// Interrupts and other timing events, in order
+    pub events: Vec<TimingEvent>,
+    /// Beam position (H, V) at the stop
+    pub stop_pos: (u16, u16),
+}
+
+/// How the active subroutine calls were determined
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CallSource {
+    /// Reconstructed from the stack contents, since calls aren't tracked
+    Scanned,
+    /// Tracked since tracking was enabled. Calls that were active at that point were reconstructed
+    /// from the stack.
+    TrackedSinceEnable,
+    /// Tracked since the ROM was loaded
+    TrackedSinceReset,
+}
+
+/// A WRAM location that is set to a fixed value before each step
+#[derive(Clone, Debug)]
+pub struct Freeze {
+    /// Offset into WRAM
+    pub offset: usize,
+    /// Value to write, little-endian
+    pub bytes: Vec<u8>,
+}
+
 /// Data reported from the model to the view when the model is updated
 pub struct ModelData<'a> {
     /// 128 sprites (OAM entries)
     pub sprites: &'a [Sprite],
     pub ppu: &'a Ppu,
+    /// CPU registers at the current stop
+    pub cpu: &'a CpuState,
+    /// DMA registers at the current stop
+    pub dma: &'a DmaState,
+    /// APU state at the current stop
+    pub apu: &'a ApuState,
+    /// Per-scanline data from the rendered frame. Only updated while emulation is halted and a
+    /// tool showing it is visible.
+    pub capture: &'a FrameCapture,
+    /// Instructions starting at the current program counter
+    pub disasm: &'a [DisasmLine],
+    pub breakpoints: &'a [Breakpoint],
+    /// Memory contents at the current stop
+    pub memory: &'a MemoryDump,
+    /// Memory contents at the start of the last step, if there was one
+    pub prev_memory: Option<&'a MemoryDump>,
+    /// The raw ROM image
+    pub rom: &'a [u8],
+    /// Cartridge mapping of the ROM
+    pub map_mode: MapMode,
+    pub freezes: &'a [Freeze],
+    pub cheats: &'a [Cheat],
+    /// Results of the last profiling run, if there was one
+    pub profile: Option<&'a Profile>,
+    /// Active subroutine calls at the current stop, outermost first
+    pub calls: &'a [StackEntry],
+    pub call_source: CallSource,
+    /// Symbols for labeling addresses
+    pub symbols: &'a SymbolTable,
+    /// Path of the loaded symbol file, if any
+    pub symbols_path: Option<&'a Path>,
+    pub cdl: &'a CodeDataLog,
+    /// Whether code/data logging is enabled
+    pub cdl_logging: bool,
+    /// Title from the ROM header, used to associate user data with the ROM
+    pub rom_title: &'a str,
+    /// Internal header of the loaded ROM, if it's large enough to have one
+    pub header: Option<&'a RomHeader>,
+    /// How the ROM file was loaded
+    pub rom_source: &'a RomSource,
+    /// Active audio recording, if any
+    pub recording: Option<RecordingState<'a>>,
+    /// Error that stopped the last recording
+    pub recording_error: Option<&'a str>,
+    /// Voices muted by the user, one bit per voice
+    pub muted_voices: u8,
+    /// Voices soloed by the user
+    pub soloed_voices: u8,
+}
+
+impl<'a> ModelData<'a> {
+    /// Returns the raw contents of a memory space
+    pub fn memory(&self, space: MemorySpace) -> &'a [u8] {
+        match space {
+            MemorySpace::Rom => self.rom,
+            space => self.memory.get(space),
+        }
+    }
 }
@@ -0,0 +1,537 @@
+//! 65816 instruction decoder and disassembler
+
+use data::CpuState;
+
+use std::fmt;
+
+/// Addressing modes of the 65816
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    /// Implied (no operand)
+    Imp,
+    /// Accumulator (`ASL A`)
+    Acc,
+    /// Immediate, width depends on the `m` flag
+    ImmM,
+    /// Immediate, width depends on the `x` flag
+    ImmX,
+    /// 8-bit immediate (`REP`, `SEP`, `BRK`, `COP`, `WDM`)
+    Imm8,
+    /// 16-bit operand pushed by `PEA`, written like an absolute address
+    Imm16,
+    Dp,
+    DpX,
+    DpY,
+    /// `(dp)`
+    DpInd,
+    /// `(dp,X)`
+    DpIndX,
+    /// `(dp),Y`
+    DpIndY,
+    /// `[dp]`
+    DpIndLong,
+    /// `[dp],Y`
+    DpIndLongY,
+    Abs,
+    AbsX,
+    AbsY,
+    Long,
+    LongX,
+    /// `(abs)` - only used by `JMP`
+    AbsInd,
+    /// `(abs,X)` - only used by `JMP` and `JSR`
+    AbsIndX,
+    /// `[abs]` - only used by `JML`
+    AbsIndLong,
+    /// `sr,S`
+    Sr,
+    /// `(sr,S),Y`
+    SrIndY,
+    /// 8-bit PC-relative branch target
+    Rel8,
+    /// 16-bit PC-relative branch target (`BRL`, `PER`)
+    Rel16,
+    /// Source and destination bank of `MVN`/`MVP`
+    Move,
+}
+
+use self::Mode::*;
+
+/// Mnemonic and addressing mode of every opcode
+static OPCODES: [(&'static str, Mode); 256] = [
+    // 0x00
+    ("BRK", Imm8), ("ORA", DpIndX), ("COP", Imm8), ("ORA", Sr),
+    ("TSB", Dp), ("ORA", Dp), ("ASL", Dp), ("ORA", DpIndLong),
+    ("PHP", Imp), ("ORA", ImmM), ("ASL", Acc), ("PHD", Imp),
+    ("TSB", Abs), ("ORA", Abs), ("ASL", Abs), ("ORA", Long),
+    // 0x10
+    ("BPL", Rel8), ("ORA", DpIndY), ("ORA", DpInd), ("ORA", SrIndY),
+    ("TRB", Dp), ("ORA", DpX), ("ASL", DpX), ("ORA", DpIndLongY),
+    ("CLC", Imp), ("ORA", AbsY), ("INC", Acc), ("TCS", Imp),
+    ("TRB", Abs), ("ORA", AbsX), ("ASL", AbsX), ("ORA", LongX),
+    // 0x20
+    ("JSR", Abs), ("AND", DpIndX), ("JSL", Long), ("AND", Sr),
+    ("BIT", Dp), ("AND", Dp), ("ROL", Dp), ("AND", DpIndLong),
+    ("PLP", Imp), ("AND", ImmM), ("ROL", Acc), ("PLD", Imp),
+    ("BIT", Abs), ("AND", Abs), ("ROL", Abs), ("AND", Long),
+    // 0x30
+    ("BMI", Rel8), ("AND", DpIndY), ("AND", DpInd), ("AND", SrIndY),
+    ("BIT", DpX), ("AND", DpX), ("ROL", DpX), ("AND", DpIndLongY),
+    ("SEC", Imp), ("AND", AbsY), ("DEC", Acc), ("TSC", Imp),
+    ("BIT", AbsX), ("AND", AbsX), ("ROL", AbsX), ("AND", LongX),
+    // 0x40
+    ("RTI", Imp), ("EOR", DpIndX), ("WDM", Imm8), ("EOR", Sr),
+    ("MVP", Move), ("EOR", Dp), ("LSR", Dp), ("EOR", DpIndLong),
+    ("PHA", Imp), ("EOR", ImmM), ("LSR", Acc), ("PHK", Imp),
+    ("JMP", Abs), ("EOR", Abs), ("LSR", Abs), ("EOR", Long),
+    // 0x50
+    ("BVC", Rel8), ("EOR", DpIndY), ("EOR", DpInd), ("EOR", SrIndY),
+    ("MVN", Move), ("EOR", DpX), ("LSR", DpX), ("EOR", DpIndLongY),
+    ("CLI", Imp), ("EOR", AbsY), ("PHY", Imp), ("TCD", Imp),
+    ("JML", Long), ("EOR", AbsX), ("LSR", AbsX), ("EOR", LongX),
+    // 0x60
+    ("RTS", Imp), ("ADC", DpIndX), ("PER", Rel16), ("ADC", Sr),
+    ("STZ", Dp), ("ADC", Dp), ("ROR", Dp), ("ADC", DpIndLong),
+    ("PLA", Imp), ("ADC", ImmM), ("ROR", Acc), ("RTL", Imp),
+    ("JMP", AbsInd), ("ADC", Abs), ("ROR", Abs), ("ADC", Long),
+    // 0x70
+    ("BVS", Rel8), ("ADC", DpIndY), ("ADC", DpInd), ("ADC", SrIndY),
+    ("STZ", DpX), ("ADC", DpX), ("ROR", DpX), ("ADC", DpIndLongY),
+    ("SEI", Imp), ("ADC", AbsY), ("PLY", Imp), ("TDC", Imp),
+    ("JMP", AbsIndX), ("ADC", AbsX), ("ROR", AbsX), ("ADC", LongX),
+    // 0x80
+    ("BRA", Rel8), ("STA", DpIndX), ("BRL", Rel16), ("STA", Sr),
+    ("STY", Dp), ("STA", Dp), ("STX", Dp), ("STA", DpIndLong),
+    ("DEY", Imp), ("BIT", ImmM), ("TXA", Imp), ("PHB", Imp),
+    ("STY", Abs), ("STA", Abs), ("STX", Abs), ("STA", Long),
+    // 0x90
+    ("BCC", Rel8), ("STA", DpIndY), ("STA", DpInd), ("STA", SrIndY),
+    ("STY", DpX), ("STA", DpX), ("STX", DpY), ("STA", DpIndLongY),
+    ("TYA", Imp), ("STA", AbsY), ("TXS", Imp), ("TXY", Imp),
+    ("STZ", Abs), ("STA", AbsX), ("STZ", AbsX), ("STA", LongX),
+    // 0xA0
+    ("LDY", ImmX), ("LDA", DpIndX), ("LDX", ImmX), ("LDA", Sr),
+    ("LDY", Dp), ("LDA", Dp), ("LDX", Dp), ("LDA", DpIndLong),
+    ("TAY", Imp), ("LDA", ImmM), ("TAX", Imp), ("PLB", Imp),
+    ("LDY", Abs), ("LDA", Abs), ("LDX", Abs), ("LDA", Long),
+    // 0xB0
+    ("BCS", Rel8), ("LDA", DpIndY), ("LDA", DpInd), ("LDA", SrIndY),
+    ("LDY", DpX), ("LDA", DpX), ("LDX", DpY), ("LDA", DpIndLongY),
+    ("CLV", Imp), ("LDA", AbsY), ("TSX", Imp), ("TYX", Imp),
+    ("LDY", AbsX), ("LDA", AbsX), ("LDX", AbsY), ("LDA", LongX),
+    // 0xC0
+    ("CPY", ImmX), ("CMP", DpIndX), ("REP", Imm8), ("CMP", Sr),
+    ("CPY", Dp), ("CMP", Dp), ("DEC", Dp), ("CMP", DpIndLong),
+    ("INY", Imp), ("CMP", ImmM), ("DEX", Imp), ("WAI", Imp),
+    ("CPY", Abs), ("CMP", Abs), ("DEC", Abs), ("CMP", Long),
+    // 0xD0
+    ("BNE", Rel8), ("CMP", DpIndY), ("CMP", DpInd), ("CMP", SrIndY),
+    ("PEI", DpInd), ("CMP", DpX), ("DEC", DpX), ("CMP", DpIndLongY),
+    ("CLD", Imp), ("CMP", AbsY), ("PHX", Imp), ("STP", Imp),
+    ("JML", AbsIndLong), ("CMP", AbsX), ("DEC", AbsX), ("CMP", LongX),
+    // 0xE0
+    ("CPX", ImmX), ("SBC", DpIndX), ("SEP", Imm8), ("SBC", Sr),
+    ("CPX", Dp), ("SBC", Dp), ("INC", Dp), ("SBC", DpIndLong),
+    ("INX", Imp), ("SBC", ImmM), ("NOP", Imp), ("XBA", Imp),
+    ("CPX", Abs), ("SBC", Abs), ("INC", Abs), ("SBC", Long),
+    // 0xF0
+    ("BEQ", Rel8), ("SBC", DpIndY), ("SBC", DpInd), ("SBC", SrIndY),
+    ("PEA", Imm16), ("SBC", DpX), ("INC", DpX), ("SBC", DpIndLongY),
+    ("SED", Imp), ("SBC", AbsY), ("PLX", Imp), ("XCE", Imp),
+    ("JSR", AbsIndX), ("SBC", AbsX), ("INC", AbsX), ("SBC", LongX),
+];
+
+/// Kind of data memory access performed by an instruction
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+    /// Read-modify-write (`INC`, `ASL`, `TSB`, ...)
+    Modify,
+}
+
+/// A data memory access performed by an instruction
+#[derive(Clone, Copy, Debug)]
+pub struct MemAccess {
+    /// 24-bit address of the first byte accessed
+    pub addr: u32,
+    /// Number of bytes accessed (1 or 2)
+    pub len: u8,
+    pub kind: Access,
+}
+
+/// A decoded instruction
+#[derive(Clone, Copy, Debug)]
+pub struct Instr {
+    /// 24-bit address the instruction was decoded at
+    pub addr: u32,
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub mode: Mode,
+    /// Raw operand, little-endian, `len - 1` bytes wide
+    pub operand: u32,
+    /// Length in bytes, including the opcode
+    pub len: u8,
+}
+
+impl Instr {
+    /// Decodes the instruction at `addr`.
+    ///
+    /// `m8` and `x8` are the widths of accumulator and index registers (`true` means 8 bits), which
+    /// determine the size of immediate operands. `read` is used to fetch the bytes making up the
+    /// instruction.
+    pub fn decode<F: FnMut(u32) -> u8>(addr: u32, m8: bool, x8: bool, mut read: F) -> Instr {
+        let opcode = read(addr);
+        let (mnemonic, mode) = OPCODES[opcode as usize];
+        let operand_len = match mode {
+            Imp | Acc => 0,
+            ImmM => if m8 { 1 } else { 2 },
+            ImmX => if x8 { 1 } else { 2 },
+            Imm8 | Dp | DpX | DpY | DpInd | DpIndX | DpIndY | DpIndLong | DpIndLongY | Sr |
+            SrIndY | Rel8 => 1,
+            Imm16 | Abs | AbsX | AbsY | AbsInd | AbsIndX | AbsIndLong | Rel16 | Move => 2,
+            Long | LongX => 3,
+        };
+
+        let mut operand = 0;
+        for i in 0..operand_len {
+            operand |= (read(offset_pc(addr, 1 + i)) as u32) << (8 * i);
+        }
+
+        Instr {
+            addr: addr,
+            opcode: opcode,
+            mnemonic: mnemonic,
+            mode: mode,
+            operand: operand,
+            len: 1 + operand_len as u8,
+        }
+    }
+
+    /// 24-bit address of the instruction following this one (the PC only wraps inside the bank)
+    pub fn next_addr(&self) -> u32 {
+        offset_pc(self.addr, self.len as u16)
+    }
+
+    /// Target of a branch, jump or call, if it can be determined statically
+    pub fn target(&self) -> Option<u32> {
+        let bank = self.addr & 0xff0000;
+        match (self.opcode, self.mode) {
+            (_, Rel8) => Some(offset_pc(self.next_addr(), self.operand as u8 as i8 as u16)),
+            (0x82, Rel16) => Some(offset_pc(self.next_addr(), self.operand as u16)),
+            (0x20, Abs) | (0x4c, Abs) => Some(bank | self.operand),
+            (0x22, Long) | (0x5c, Long) => Some(self.operand),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a subroutine call (`JSR` or `JSL`)
+    pub fn is_call(&self) -> bool {
+        match self.opcode {
+            0x20 | 0x22 | 0xfc => true,
+            _ => false,
+        }
+    }
+
+    /// Whether this is a return from subroutine or interrupt (`RTS`, `RTL` or `RTI`)
+    pub fn is_return(&self) -> bool {
+        match self.opcode {
+            0x60 | 0x6b | 0x40 => true,
+            _ => false,
+        }
+    }
+
+    /// Computes the data memory accesses this instruction will perform when executed with the given
+    /// register contents.
+    ///
+    /// `read` is used to fetch indirect pointers. Stack accesses, pointer fetches and code fetches
+    /// are not included.
+    pub fn accesses<F: FnMut(u32) -> u8>(&self, cpu: &CpuState, mut read: F) -> Vec<MemAccess> {
+        let kind = match self.mnemonic {
+            "STA" | "STX" | "STY" | "STZ" => Access::Write,
+            "ASL" | "LSR" | "ROL" | "ROR" | "INC" | "DEC" | "TSB" | "TRB" => Access::Modify,
+            "JMP" | "JML" | "JSR" | "JSL" | "PEI" => return vec![],
+            _ => Access::Read,
+        };
+        let len = match self.mnemonic {
+            "LDX" | "LDY" | "STX" | "STY" | "CPX" | "CPY" => if cpu.x8() { 1 } else { 2 },
+            _ => if cpu.m8() { 1 } else { 2 },
+        };
+
+        let op = self.operand;
+        let (x, y) = (cpu.x as u32, cpu.y as u32);
+        let dbr = (cpu.dbr as u32) << 16;
+        let dp = |offset: u32| cpu.d.wrapping_add(offset as u16) as u32;
+
+        let addr = match self.mode {
+            Dp => dp(op),
+            DpX => dp(op + x),
+            DpY => dp(op + y),
+            DpInd => dbr | read_ptr16(&mut read, dp(op)),
+            DpIndX => dbr | read_ptr16(&mut read, dp(op + x)),
+            DpIndY => (dbr | read_ptr16(&mut read, dp(op))) + y,
+            DpIndLong => read_ptr24(&mut read, dp(op)),
+            DpIndLongY => read_ptr24(&mut read, dp(op)) + y,
+            Abs => dbr | op,
+            AbsX => (dbr | op) + x,
+            AbsY => (dbr | op) + y,
+            Long => op,
+            LongX => op + x,
+            Sr => cpu.s.wrapping_add(op as u16) as u32,
+            SrIndY => (dbr | read_ptr16(&mut read, cpu.s.wrapping_add(op as u16) as u32)) + y,
+            Move => {
+                // One byte is moved from `src:X` to `dst:Y` per execution
+                let (src, dst) = (op >> 8, op & 0xff);
+                return vec![
+                    MemAccess { addr: src << 16 | x, len: 1, kind: Access::Read },
+                    MemAccess { addr: dst << 16 | y, len: 1, kind: Access::Write },
+                ];
+            }
+            _ => return vec![],
+        };
+
+        vec![MemAccess { addr: addr & 0xffffff, len: len, kind: kind }]
+    }
+
+    /// Formats the raw bytes of the instruction as hex, separated by spaces
+    pub fn bytes_string(&self) -> String {
+        let mut s = format!("{:02X}", self.opcode);
+        for i in 0..self.len - 1 {
+            s.push_str(&format!(" {:02X}", (self.operand >> (8 * i)) & 0xff));
+        }
+        s
+    }
+
+    /// Formats the operand in assembler syntax
+    pub fn operand_string(&self) -> String {
+        let op = self.operand;
+        match self.mode {
+            Imp => String::new(),
+            Acc => "A".to_string(),
+            ImmM | ImmX | Imm8 => {
+                if self.len == 2 { format!("#${:02X}", op) } else { format!("#${:04X}", op) }
+            }
+            Imm16 => format!("${:04X}", op),
+            Dp => format!("${:02X}", op),
+            DpX => format!("${:02X},X", op),
+            DpY => format!("${:02X},Y", op),
+            DpInd => format!("(${:02X})", op),
+            DpIndX => format!("(${:02X},X)", op),
+            DpIndY => format!("(${:02X}),Y", op),
+            DpIndLong => format!("[${:02X}]", op),
+            DpIndLongY => format!("[${:02X}],Y", op),
+            Abs => format!("${:04X}", op),
+            AbsX => format!("${:04X},X", op),
+            AbsY => format!("${:04X},Y", op),
+            Long => format!("${:06X}", op),
+            LongX => format!("${:06X},X", op),
+            AbsInd => format!("(${:04X})", op),
+            AbsIndX => format!("(${:04X},X)", op),
+            AbsIndLong => format!("[${:04X}]", op),
+            Sr => format!("${:02X},S", op),
+            SrIndY => format!("(${:02X},S),Y", op),
+            Rel8 | Rel16 => match self.target() {
+                Some(target) => format!("${:06X}", target),
+                // `PER` pushes the address instead of jumping there
+                None => format!("${:04X}", offset_pc(self.next_addr(), op as u16) & 0xffff),
+            },
+            // The destination bank is the first operand byte
+            Move => format!("${:02X},${:02X}", op >> 8, op & 0xff),
+        }
+    }
+
+    /// Applies the effect of this instruction on the `m` and `x` flags (`REP`, `SEP`), returning the
+    /// new register widths.
+    ///
+    /// Used to keep immediate operand sizes right while linearly disassembling code.
+    pub fn update_widths(&self, m8: bool, x8: bool) -> (bool, bool) {
+        match self.opcode {
+            0xc2 => (m8 && self.operand & 0x20 == 0, x8 && self.operand & 0x10 == 0),
+            0xe2 => (m8 || self.operand & 0x20 != 0, x8 || self.operand & 0x10 != 0),
+            _ => (m8, x8),
+        }
+    }
+}
+
+/// Formats the whole instruction in assembler syntax
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = self.operand_string();
+        if operand.is_empty() {
+            write!(f, "{}", self.mnemonic)
+        } else {
+            write!(f, "{} {}", self.mnemonic, operand)
+        }
+    }
+}
+
+/// Reads a 16-bit pointer from bank 0
+fn read_ptr16<F: FnMut(u32) -> u8>(read: &mut F, addr: u32) -> u32 {
+    read(addr) as u32 | (read((addr + 1) & 0xffff) as u32) << 8
+}
+
+/// Reads a 24-bit pointer from bank 0
+fn read_ptr24<F: FnMut(u32) -> u8>(read: &mut F, addr: u32) -> u32 {
+    read_ptr16(read, addr) | (read((addr + 2) & 0xffff) as u32) << 16
+}
+
+/// Adds `offset` to the 16-bit part of a 24-bit program address, keeping the bank
+pub fn offset_pc(addr: u32, offset: u16) -> u32 {
+    (addr & 0xff0000) | ((addr as u16).wrapping_add(offset) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::CpuState;
+
+    /// Decodes an instruction from `bytes`, placed at `addr`
+    fn decode_bytes(addr: u32, m8: bool, x8: bool, bytes: &[u8]) -> Instr {
+        Instr::decode(addr, m8, x8, |a| bytes[(a as u16).wrapping_sub(addr as u16) as usize])
+    }
+
+    /// Returns address, length and kind of the accesses of `bytes` executed with `cpu`, reading
+    /// pointers from the bank 0 memory `ram`
+    fn accesses(cpu: &CpuState, bytes: &[u8], ram: &[u8]) -> Vec<(u32, u8, Access)> {
+        let instr = decode_bytes(cpu.pc24(), cpu.m8(), cpu.x8(), bytes);
+        instr.accesses(cpu, |a| ram[a as usize]).iter()
+            .map(|access| (access.addr, access.len, access.kind))
+            .collect()
+    }
+
+    #[test]
+    fn opcodes() {
+        let nop = decode_bytes(0x8000, true, true, &[0xea]);
+        assert_eq!((nop.mnemonic, nop.mode, nop.len), ("NOP", Mode::Imp, 1));
+        assert_eq!(nop.to_string(), "NOP");
+
+        let jml = decode_bytes(0x8000, true, true, &[0x5c, 0x56, 0x34, 0x12]);
+        assert_eq!((jml.mode, jml.len, jml.operand), (Mode::Long, 4, 0x123456));
+        assert_eq!(jml.to_string(), "JML $123456");
+        assert_eq!(jml.bytes_string(), "5C 56 34 12");
+
+        let sta = decode_bytes(0x8000, true, true, &[0x9d, 0x00, 0x21]);
+        assert_eq!(sta.to_string(), "STA $2100,X");
+
+        let pea = decode_bytes(0x8000, true, true, &[0xf4, 0x34, 0x12]);
+        assert_eq!((pea.mode, pea.len), (Mode::Imm16, 3));
+        assert_eq!(pea.to_string(), "PEA $1234");
+
+        let rep = decode_bytes(0x8000, false, false, &[0xc2, 0x30]);
+        assert_eq!((rep.len, rep.to_string()), (2, "REP #$30".to_string()));
+    }
+
+    #[test]
+    fn immediate_widths() {
+        let lda = [0xa9, 0x34, 0x12];
+        assert_eq!(decode_bytes(0x8000, true, false, &lda).to_string(), "LDA #$34");
+        assert_eq!(decode_bytes(0x8000, false, true, &lda).to_string(), "LDA #$1234");
+        assert_eq!(decode_bytes(0x8000, false, true, &lda).len, 3);
+
+        let ldx = [0xa2, 0x34, 0x12];
+        assert_eq!(decode_bytes(0x8000, false, true, &ldx).to_string(), "LDX #$34");
+        assert_eq!(decode_bytes(0x8000, true, false, &ldx).to_string(), "LDX #$1234");
+    }
+
+    #[test]
+    fn block_moves() {
+        // Machine code has the destination bank first, assembler syntax has the source first
+        let mvn = decode_bytes(0x8000, true, true, &[0x54, 0x7e, 0x7f]);
+        assert_eq!((mvn.mnemonic, mvn.len), ("MVN", 3));
+        assert_eq!(mvn.to_string(), "MVN $7F,$7E");
+
+        let mvp = decode_bytes(0x8000, true, true, &[0x44, 0x00, 0xc0]);
+        assert_eq!(mvp.to_string(), "MVP $C0,$00");
+
+        let cpu = CpuState { x: 0x1000, y: 0x2000, p: 0x30, ..CpuState::default() };
+        assert_eq!(accesses(&cpu, &[0x54, 0x7e, 0x7f], &[]), vec![
+            (0x7f1000, 1, Access::Read),
+            (0x7e2000, 1, Access::Write),
+        ]);
+    }
+
+    #[test]
+    fn relative_targets() {
+        let bra = decode_bytes(0x808010, true, true, &[0x80, 0xfe]);
+        assert_eq!(bra.target(), Some(0x808010));
+        assert_eq!(bra.to_string(), "BRA $808010");
+
+        let bne = decode_bytes(0x808010, true, true, &[0xd0, 0x10]);
+        assert_eq!(bne.next_addr(), 0x808012);
+        assert_eq!(bne.target(), Some(0x808022));
+
+        let bcc = decode_bytes(0x008000, true, true, &[0x90, 0x80]);
+        assert_eq!(bcc.target(), Some(0x007f82));
+
+        // The PC wraps inside the bank
+        let brl = decode_bytes(0x80fff0, true, true, &[0x82, 0x20, 0x00]);
+        assert_eq!(brl.next_addr(), 0x80fff3);
+        assert_eq!(brl.target(), Some(0x800013));
+        assert_eq!(offset_pc(0x12ffff, 1), 0x120000);
+        assert_eq!(offset_pc(0x120000, 0xffff), 0x12ffff);
+
+        // `PER` computes an address without jumping
+        let per = decode_bytes(0x018000, true, true, &[0x62, 0x10, 0x00]);
+        assert_eq!(per.target(), None);
+        assert_eq!(per.to_string(), "PER $8013");
+    }
+
+    #[test]
+    fn jumps_and_calls() {
+        let jsr = decode_bytes(0x038000, true, true, &[0x20, 0x34, 0x12]);
+        assert_eq!(jsr.target(), Some(0x031234));
+        assert!(jsr.is_call() && !jsr.is_return());
+
+        let jsl = decode_bytes(0x038000, true, true, &[0x22, 0x56, 0x34, 0x12]);
+        assert_eq!(jsl.target(), Some(0x123456));
+        assert!(jsl.is_call());
+
+        // Indirect jumps can't be resolved statically
+        let jmp = decode_bytes(0x038000, true, true, &[0x6c, 0x00, 0x10]);
+        assert_eq!(jmp.target(), None);
+        assert!(!jmp.is_call());
+
+        assert!(decode_bytes(0x038000, true, true, &[0x6b]).is_return());
+        assert!(decode_bytes(0x038000, true, true, &[0x40]).is_return());
+    }
+
+    #[test]
+    fn width_updates() {
+        let rep = decode_bytes(0x8000, true, true, &[0xc2, 0x30]);
+        assert_eq!(rep.update_widths(true, true), (false, false));
+        let rep = decode_bytes(0x8000, true, true, &[0xc2, 0x20]);
+        assert_eq!(rep.update_widths(true, true), (false, true));
+
+        let sep = decode_bytes(0x8000, true, true, &[0xe2, 0x10]);
+        assert_eq!(sep.update_widths(false, false), (false, true));
+        let sep = decode_bytes(0x8000, true, true, &[0xe2, 0x30]);
+        assert_eq!(sep.update_widths(false, false), (true, true));
+
+        let nop = decode_bytes(0x8000, true, true, &[0xea]);
+        assert_eq!(nop.update_widths(false, true), (false, true));
+    }
+
+    #[test]
+    fn memory_accesses() {
+        let mut ram = vec![0; 0x200];
+        ram[0x120..0x123].copy_from_slice(&[0x00, 0x80, 0x7f]);
+        let cpu = CpuState { x: 2, y: 4, d: 0x100, dbr: 0x7e, s: 0x1f0, p: 0x30, ..CpuState::default() };
+
+        assert_eq!(accesses(&cpu, &[0xad, 0x34, 0x12], &ram), vec![(0x7e1234, 1, Access::Read)]);
+        assert_eq!(accesses(&cpu, &[0x85, 0x10], &ram), vec![(0x000110, 1, Access::Write)]);
+        assert_eq!(accesses(&cpu, &[0xfe, 0x34, 0x12], &ram), vec![(0x7e1236, 1, Access::Modify)]);
+        assert_eq!(accesses(&cpu, &[0xbf, 0xff, 0xff, 0xff], &ram), vec![(0x000001, 1, Access::Read)]);
+        assert_eq!(accesses(&cpu, &[0xb1, 0x20], &ram), vec![(0x7e8004, 1, Access::Read)]);
+        assert_eq!(accesses(&cpu, &[0xb7, 0x20], &ram), vec![(0x7f8004, 1, Access::Read)]);
+        assert_eq!(accesses(&cpu, &[0xa3, 0x03], &ram), vec![(0x0001f3, 1, Access::Read)]);
+        assert_eq!(accesses(&cpu, &[0x4c, 0x00, 0x80], &ram), vec![]);
+        assert_eq!(accesses(&cpu, &[0xea], &ram), vec![]);
+
+        // Access width follows the register being loaded or stored
+        let cpu = CpuState { p: 0x20, ..cpu };
+        assert_eq!(accesses(&cpu, &[0xad, 0x34, 0x12], &ram), vec![(0x7e1234, 1, Access::Read)]);
+        assert_eq!(accesses(&cpu, &[0x8e, 0x34, 0x12], &ram), vec![(0x7e1234, 2, Access::Write)]);
+        let cpu = CpuState { p: 0x10, ..cpu };
+        assert_eq!(accesses(&cpu, &[0xad, 0x34, 0x12], &ram), vec![(0x7e1234, 2, Access::Read)]);
+    }
+}
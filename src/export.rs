@@ -0,0 +1,287 @@
+//! Export of the whole ROM as annotated assembler source
+//!
+//! Code is found by following the control flow from the interrupt vectors and from all
+//! instructions recorded in the code/data log, tracking the register widths set by `REP` and `SEP`.
+//! Everything else is emitted as `.db` blocks. The output uses WLA-DX syntax and includes the memory
+//! map, so it can be assembled as-is.
+
+use cdl::{self, CodeDataLog};
+use disasm::{self, Instr, Mode};
+use memmap::MapMode;
+use symbols::SymbolTable;
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Maximum number of bytes per `.db` line
+const DB_LINE: usize = 16;
+
+/// Interrupt vectors in bank `$00`, and the labels of their handlers
+static VECTORS: [(u16, &'static str); 7] = [
+    (0xffe4, "cop_native"),
+    (0xffe6, "brk_native"),
+    (0xffea, "nmi_native"),
+    (0xffee, "irq_native"),
+    (0xfffa, "nmi"),
+    (0xfffc, "reset"),
+    (0xfffe, "irq"),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Byte {
+    Unknown,
+    /// First byte of an instruction, with the widths of accumulator and index registers
+    Opcode(bool, bool),
+    Operand,
+}
+
+/// Numbers of exported ROM bytes
+#[derive(Clone, Copy, Debug)]
+pub struct ExportSummary {
+    pub instructions: usize,
+    pub code_bytes: usize,
+    pub data_bytes: usize,
+}
+
+/// Classifies the ROM bytes and names all jump targets
+fn analyze(rom: &[u8], map_mode: MapMode, cdl: &CodeDataLog, symbols: &SymbolTable)
+           -> (Vec<Byte>, HashMap<usize, String>) {
+    let flags = cdl.flags();
+    let flag = |offset: usize| flags.get(offset).cloned().unwrap_or(0);
+    let rom_offset = |addr: u32| map_mode.rom_offset(addr, rom.len());
+    let read = |addr: u32| rom_offset(addr).map_or(0, |offset| rom[offset]);
+
+    let mut bytes = vec![Byte::Unknown; rom.len()];
+    // Jump targets by ROM offset, `true` for subroutine entry points
+    let mut targets = HashMap::new();
+    // Addresses still to disassemble, with the register widths at that point
+    let mut pending = Vec::new();
+
+    let mut vector_labels = HashMap::new();
+    let header = map_mode.header_offset();
+    for &(vector, name) in VECTORS.iter() {
+        let offset = header + (vector - 0xffc0) as usize;
+        if offset + 1 >= rom.len() {
+            continue;
+        }
+        let handler = rom[offset] as u32 | (rom[offset + 1] as u32) << 8;
+        if let Some(handler_offset) = rom_offset(handler) {
+            // Interrupts switch to 8-bit registers in emulation mode only, the native mode widths
+            // are unknown without a code/data log
+            pending.push((handler, true, true));
+            vector_labels.entry(handler_offset).or_insert(name);
+        }
+    }
+
+    for (offset, &f) in flags.iter().enumerate() {
+        if f & cdl::OPCODE != 0 {
+            pending.push((map_mode.bus_addr(offset), f & cdl::M8 != 0, f & cdl::X8 != 0));
+        }
+        if f & (cdl::JUMP_TARGET | cdl::SUB_ENTRY) != 0 {
+            targets.insert(offset, f & cdl::SUB_ENTRY != 0);
+        }
+    }
+
+    while let Some((addr, m8, x8)) = pending.pop() {
+        let offset = match rom_offset(addr) {
+            Some(offset) if bytes[offset] == Byte::Unknown => offset,
+            _ => continue,
+        };
+        // Don't run into bytes known to be data, and prefer the logged register widths
+        let f = flag(offset);
+        let (m8, x8) = if f & cdl::OPCODE != 0 {
+            (f & cdl::M8 != 0, f & cdl::X8 != 0)
+        } else if f & (cdl::DATA | cdl::CODE) != 0 {
+            continue;
+        } else {
+            (m8, x8)
+        };
+
+        let instr = Instr::decode(addr, m8, x8, |addr| read(addr));
+        let operands = (1..instr.len as u16).map(|i| rom_offset(disasm::offset_pc(addr, i))).collect::<Vec<_>>();
+        if operands.iter().any(|offset| offset.map_or(true, |offset| bytes[offset] != Byte::Unknown)) {
+            // Overlaps another instruction, or leaves the ROM
+            continue;
+        }
+        bytes[offset] = Byte::Opcode(m8, x8);
+        for operand in operands {
+            bytes[operand.unwrap()] = Byte::Operand;
+        }
+
+        let (m8, x8) = instr.update_widths(m8, x8);
+        if let Some(target) = instr.target() {
+            pending.push((target, m8, x8));
+            if let Some(target_offset) = rom_offset(target) {
+                let sub = targets.get(&target_offset).cloned().unwrap_or(false) || instr.is_call();
+                targets.insert(target_offset, sub);
+            }
+        }
+
+        // Execution doesn't continue after returns, jumps, unconditional branches, `STP`, `BRK`
+        // and `COP`
+        let ends = instr.is_return() || match instr.opcode {
+            0x4c | 0x5c | 0x6c | 0x7c | 0xdc | 0x80 | 0x82 | 0xdb | 0x00 | 0x02 => true,
+            _ => false,
+        };
+        if !ends {
+            pending.push((instr.next_addr(), m8, x8));
+        }
+    }
+
+    // Labels can't be put inside of instructions
+    let mut labels = HashMap::new();
+    for symbol in symbols.symbols() {
+        if let Some(offset) = rom_offset(symbol.addr) {
+            if bytes[offset] != Byte::Operand {
+                labels.entry(offset).or_insert(symbol.name.clone());
+            }
+        }
+    }
+    for (&offset, &name) in &vector_labels {
+        if bytes[offset] != Byte::Operand {
+            labels.entry(offset).or_insert(name.to_string());
+        }
+    }
+    for (&offset, &sub) in &targets {
+        if bytes[offset] != Byte::Operand {
+            let addr = map_mode.bus_addr(offset);
+            labels.entry(offset).or_insert(format!("{}_{:06X}", if sub { "sub" } else { "L" }, addr));
+        }
+    }
+
+    (bytes, labels)
+}
+
+/// Formats an instruction in WLA-DX syntax, with `label` replacing the target address
+///
+/// Absolute and long operands get a `.w` or `.l` suffix, since WLA-DX would otherwise pick a
+/// shorter addressing mode for small values.
+fn wla_instr(instr: &Instr, label: Option<&String>) -> String {
+    let (suffix, index) = match instr.mode {
+        Mode::Abs => (".w", ""),
+        Mode::AbsX => (".w", ",X"),
+        Mode::AbsY => (".w", ",Y"),
+        Mode::Long => (".l", ""),
+        Mode::LongX => (".l", ",X"),
+        _ => ("", ""),
+    };
+    let operand = match (label, instr.mode) {
+        (Some(label), _) => format!("{}{}{}", label, suffix, index),
+        (None, Mode::Abs) | (None, Mode::AbsX) | (None, Mode::AbsY) => {
+            format!("${:04X}{}{}", instr.operand, suffix, index)
+        }
+        (None, Mode::Long) | (None, Mode::LongX) => format!("${:06X}{}{}", instr.operand, suffix, index),
+        _ => instr.operand_string(),
+    };
+    if operand.is_empty() {
+        instr.mnemonic.to_string()
+    } else {
+        format!("{} {}", instr.mnemonic, operand)
+    }
+}
+
+/// Writes the WLA-DX memory map and ROM bank directives matching `map_mode`
+fn write_memory_map<W: Write>(out: &mut W, map_mode: MapMode, rom_len: usize) -> io::Result<()> {
+    let bank_size = map_mode.bank_size();
+    let banks = (rom_len + bank_size - 1) / bank_size;
+    // `.EXHIROM` places the banks at `$C0` and `$40` by itself, the other modes need a `.BASE`
+    // matching `MapMode::bus_addr`
+    let (slot, mode, base) = match map_mode {
+        MapMode::LoRom => (0x8000, ".LOROM", 0x00),
+        MapMode::HiRom => (0x0000, ".HIROM", 0xc0),
+        MapMode::ExHiRom => (0x0000, ".EXHIROM", 0x00),
+    };
+    try!(writeln!(out, ".MEMORYMAP"));
+    try!(writeln!(out, "    SLOTSIZE ${:04X}", bank_size));
+    try!(writeln!(out, "    DEFAULTSLOT 0"));
+    try!(writeln!(out, "    SLOT 0 ${:04X}", slot));
+    try!(writeln!(out, ".ENDME"));
+    try!(writeln!(out, ""));
+    try!(writeln!(out, ".ROMBANKMAP"));
+    try!(writeln!(out, "    BANKSTOTAL {}", banks));
+    try!(writeln!(out, "    BANKSIZE ${:04X}", bank_size));
+    try!(writeln!(out, "    BANKS {}", banks));
+    try!(writeln!(out, ".ENDRO"));
+    try!(writeln!(out, ""));
+    try!(writeln!(out, "{}", mode));
+    if base != 0 {
+        try!(writeln!(out, ".BASE ${:02X}", base));
+    }
+    Ok(())
+}
+
+/// Writes the disassembly of `rom` to `out`
+///
+/// `cdl` is used to tell code apart from data (it can be empty), and `symbols` to name labels.
+pub fn write_disassembly<W: Write>(out: &mut W,
+                                   rom: &[u8],
+                                   map_mode: MapMode,
+                                   cdl: &CodeDataLog,
+                                   symbols: &SymbolTable,
+                                   title: &str)
+                                   -> io::Result<ExportSummary> {
+    let (bytes, labels) = analyze(rom, map_mode, cdl, symbols);
+    let rom_offset = |addr: u32| map_mode.rom_offset(addr, rom.len());
+    let read = |addr: u32| rom_offset(addr).map_or(0, |offset| rom[offset]);
+    let mut summary = ExportSummary {
+        instructions: 0,
+        code_bytes: bytes.iter().filter(|&&b| b != Byte::Unknown).count(),
+        data_bytes: bytes.iter().filter(|&&b| b == Byte::Unknown).count(),
+    };
+
+    try!(writeln!(out, "; {} - disassembled by breeze-dbg", title));
+    try!(writeln!(out, "; {:?}, {} bytes ({} bytes code, {} bytes data)",
+        map_mode, rom.len(), summary.code_bytes, summary.data_bytes));
+    try!(writeln!(out, ""));
+    try!(write_memory_map(out, map_mode, rom.len()));
+
+    let bank_size = map_mode.bank_size();
+    for bank_start in (0..rom.len()).filter(|offset| offset % bank_size == 0) {
+        let bank_end = (bank_start + bank_size).min(rom.len());
+        try!(writeln!(out, ""));
+        try!(writeln!(out, ".BANK {} SLOT 0", bank_start / bank_size));
+        try!(writeln!(out, ".ORG $0000"));
+        try!(writeln!(out, ""));
+
+        // Register widths the assembler currently assumes, unknown at the start of each bank
+        let mut widths = None;
+        let mut offset = bank_start;
+        while offset < bank_end {
+            if let Some(label) = labels.get(&offset) {
+                try!(writeln!(out, "{}:", label));
+            }
+            let addr = map_mode.bus_addr(offset);
+
+            if let Byte::Opcode(m8, x8) = bytes[offset] {
+                if widths.map_or(true, |(m, _)| m != m8) {
+                    try!(writeln!(out, "    .ACCU {}", if m8 { 8 } else { 16 }));
+                }
+                if widths.map_or(true, |(_, x)| x != x8) {
+                    try!(writeln!(out, "    .INDEX {}", if x8 { 8 } else { 16 }));
+                }
+                widths = Some((m8, x8));
+
+                let instr = Instr::decode(addr, m8, x8, |addr| read(addr));
+                let label = instr.target().and_then(|target| rom_offset(target)).and_then(|target| labels.get(&target));
+                let text = wla_instr(&instr, label);
+                try!(writeln!(out, "    {:<32}; ${:02X}:{:04X}  {}",
+                    text, addr >> 16, addr & 0xffff, instr.bytes_string()));
+                summary.instructions += 1;
+                offset += instr.len as usize;
+            } else {
+                // Data runs up to the next instruction or label
+                let mut end = offset + 1;
+                while end < bank_end && end - offset < DB_LINE && bytes[end] == Byte::Unknown &&
+                        !labels.contains_key(&end) {
+                    end += 1;
+                }
+                let values = rom[offset..end].iter().map(|b| format!("${:02X}", b)).collect::<Vec<_>>();
+                try!(writeln!(out, "    {:<32}; ${:02X}:{:04X}",
+                    format!(".db {}", values.join(", ")), addr >> 16, addr & 0xffff));
+                offset = end;
+            }
+        }
+    }
+
+    Ok(summary)
+}
@@ -0,0 +1,213 @@
+//! Recording of the register writes done by HDMA over a frame
+//!
+//! The emulator doesn't report HDMA transfers, so they're detected while the frame is executed
+//! instruction by instruction: when HDMA advances a channel's table pointers, the bytes it just
+//! transferred are read from the addresses the pointers held before.
+
+use data::{DmaState, VISIBLE_LINES};
+
+/// Byte offsets (relative to the B-bus address) written by each transfer mode, in transfer order
+pub static MODE_PATTERNS: [&'static [u16]; 8] = [
+    &[0],
+    &[0, 1],
+    &[0, 0],
+    &[0, 0, 1, 1],
+    &[0, 1, 2, 3],
+    &[0, 1, 0, 1],
+    &[0, 0],
+    &[0, 0, 1, 1],
+];
+
+/// A register written by an HDMA channel
+#[derive(Clone, Copy, Debug)]
+pub struct HdmaTarget {
+    pub channel: u8,
+    /// B-bus address (`$21xx`)
+    pub reg: u16,
+    /// Number of bytes written to the register per transfer (2 for write-twice registers)
+    pub bytes: u8,
+}
+
+/// Values written by HDMA during a frame
+#[derive(Clone, Default)]
+pub struct HdmaCapture {
+    pub targets: Vec<HdmaTarget>,
+    /// For each visible scanline, the value written to each target (in the order of `targets`),
+    /// or `None` if the target wasn't written on that line
+    pub lines: Vec<Vec<Option<u16>>>,
+    /// For each visible scanline, the channels (one bit per channel) whose transfers on that line
+    /// couldn't be recorded
+    pub unknown: Vec<u8>,
+}
+
+impl HdmaCapture {
+    /// Whether the value written to `target` on `line` is unknown
+    pub fn is_unknown(&self, line: usize, target: usize) -> bool {
+        self.unknown.get(line).map_or(false, |&unknown| unknown & (1 << self.targets[target].channel) != 0)
+    }
+
+    /// Returns the value held by each target at each scanline. Targets keep their last written
+    /// value, and are `None` before they're first written or after a line with unknown writes.
+    pub fn held_values(&self) -> Vec<Vec<Option<u16>>> {
+        let mut current = vec![None; self.targets.len()];
+        self.lines.iter().enumerate().map(|(line, written)| {
+            for (target, (held, &value)) in current.iter_mut().zip(written).enumerate() {
+                if value.is_some() {
+                    *held = value;
+                } else if self.is_unknown(line, target) {
+                    *held = None;
+                }
+            }
+            current.clone()
+        }).collect()
+    }
+}
+
+/// Records the register writes done by HDMA while a frame is executed
+pub struct HdmaRecorder {
+    capture: HdmaCapture,
+    /// Channel, B-bus address and transfer mode of each group of targets, and the index of the
+    /// group's first target
+    groups: Vec<(usize, u8, u8, usize)>,
+    /// Whether each channel transfers data on its next line, if known
+    do_transfer: [Option<bool>; 8],
+}
+
+impl HdmaRecorder {
+    pub fn new() -> Self {
+        HdmaRecorder {
+            capture: HdmaCapture {
+                targets: Vec::new(),
+                lines: vec![Vec::new(); VISIBLE_LINES as usize],
+                unknown: vec![0; VISIBLE_LINES as usize],
+            },
+            groups: Vec::new(),
+            do_transfer: [None; 8],
+        }
+    }
+
+    /// Notes the HDMA initialization at the start of a frame, after which every channel
+    /// transfers data on its first line
+    pub fn init(&mut self) {
+        self.do_transfer = [Some(true); 8];
+    }
+
+    /// Records the transfers HDMA did on the lines `first_line` to `last_line`, given the channel
+    /// registers before and after
+    ///
+    /// Only the transfer of a single line can be recovered from the registers. If an instruction
+    /// spans several HDMA lines (eg. a long DMA or a `WAI`), the lines are marked as unknown.
+    ///
+    /// Channels whose bit is set in `cpu_writes` had their registers written by the CPU in the
+    /// meantime and are skipped. `peek` reads the transferred bytes from the 24-bit address
+    /// space, so this has to be called right after the transfer.
+    pub fn record<F>(&mut self, first_line: u16, last_line: u16, before: &DmaState, after: &DmaState,
+                     cpu_writes: u8, mut peek: F)
+        where F: FnMut(u32) -> u8 {
+        for ch in 0..8 {
+            if before.hdmaen & (1 << ch) == 0 || cpu_writes & (1 << ch) != 0 {
+                continue;
+            }
+            let (regs, new) = (&before.channels[ch], &after.channels[ch]);
+            // An HDMA line always changes the line counter or one of the table addresses
+            if regs[5..11] == new[5..11] {
+                continue;
+            }
+            let mode = regs[0] & 0x07;
+            if last_line > first_line {
+                // The line counter can't be followed over several lines either
+                self.do_transfer[ch] = None;
+                self.mark_unknown(ch, regs[1], mode, first_line, last_line);
+                continue;
+            }
+            let line = first_line;
+
+            // The line counter is decremented after the transfer, and the next entry is loaded
+            // when it runs out. Without knowing the previous line (when the recording started in
+            // the middle of the frame or after an unknown line), only entries with the repeat flag
+            // are known to transfer.
+            let nltr = regs[10];
+            let transfer = self.do_transfer[ch].or(if nltr & 0x80 != 0 { Some(true) } else { None });
+            let counter = nltr.wrapping_sub(1);
+            self.do_transfer[ch] = Some(counter & 0x7f == 0 || counter & 0x80 != 0);
+            match transfer {
+                Some(true) if line < VISIBLE_LINES => {}
+                None => {
+                    self.mark_unknown(ch, regs[1], mode, line, line);
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let (bank, mut addr) = if regs[0] & 0x40 != 0 {
+                (regs[7], (regs[6] as u16) << 8 | regs[5] as u16)
+            } else {
+                (regs[4], (regs[9] as u16) << 8 | regs[8] as u16)
+            };
+            let first = self.targets(ch, regs[1], mode);
+            let pattern = MODE_PATTERNS[mode as usize];
+            let offsets = distinct_offsets(pattern);
+            let mut values = vec![0u16; offsets.len()];
+            let mut written = vec![0; offsets.len()];
+            for &offset in pattern {
+                let byte = peek((bank as u32) << 16 | addr as u32);
+                addr = addr.wrapping_add(1);
+                let t = offsets.iter().position(|&o| o == offset).unwrap();
+                values[t] |= (byte as u16) << (8 * written[t]);
+                written[t] += 1;
+            }
+            for (t, &value) in values.iter().enumerate() {
+                self.capture.lines[line as usize][first + t] = Some(value);
+            }
+        }
+    }
+
+    pub fn finish(self) -> HdmaCapture {
+        self.capture
+    }
+
+    /// Marks the transfers of channel `ch` on the lines `first_line` to `last_line` as unknown
+    fn mark_unknown(&mut self, ch: usize, b_addr: u8, mode: u8, first_line: u16, last_line: u16) {
+        // The targets are added so that the unknown lines show up
+        self.targets(ch, b_addr, mode);
+        for line in first_line..last_line.saturating_add(1).min(VISIBLE_LINES) {
+            self.capture.unknown[line as usize] |= 1 << ch;
+        }
+    }
+
+    /// Returns the index of the first target written by channel `ch` with the given B-bus address
+    /// and mode, adding the targets if the channel wasn't configured like this before
+    fn targets(&mut self, ch: usize, b_addr: u8, mode: u8) -> usize {
+        if let Some(&(_, _, _, first)) = self.groups.iter().find(|g| (g.0, g.1, g.2) == (ch, b_addr, mode)) {
+            return first;
+        }
+
+        let first = self.capture.targets.len();
+        let pattern = MODE_PATTERNS[mode as usize];
+        for offset in distinct_offsets(pattern) {
+            self.capture.targets.push(HdmaTarget {
+                channel: ch as u8,
+                reg: 0x2100 | (b_addr as u16 + offset) & 0xff,
+                bytes: pattern.iter().filter(|&&o| o == offset).count() as u8,
+            });
+        }
+        let count = self.capture.targets.len() - first;
+        for line in &mut self.capture.lines {
+            line.extend((0..count).map(|_| None));
+        }
+        self.groups.push((ch, b_addr, mode, first));
+        first
+    }
+}
+
+/// Returns the registers written by a transfer mode (as offsets from the B-bus address), in the
+/// order they're first written
+fn distinct_offsets(pattern: &[u16]) -> Vec<u16> {
+    let mut offsets = Vec::new();
+    for &offset in pattern {
+        if !offsets.contains(&offset) {
+            offsets.push(offset);
+        }
+    }
+    offsets
+}
@@ -0,0 +1,192 @@
+//! Parsing of the internal cartridge header
+//!
+//! The header occupies `$FFC0`-`$FFDF` of bank `$00`, followed by the interrupt vectors. Games
+//! with developer ID `$33` also have an extended header at `$FFB0`-`$FFBF`.
+
+/// Names of the native mode vectors at `$FFE4`-`$FFEF`
+pub static NATIVE_VECTORS: [&'static str; 6] = ["COP", "BRK", "ABORT", "NMI", "(unused)", "IRQ"];
+/// Names of the emulation mode vectors at `$FFF4`-`$FFFF`
+pub static EMULATION_VECTORS: [&'static str; 6] = ["COP", "(unused)", "ABORT", "NMI", "RESET", "IRQ/BRK"];
+
+/// Region names, indexed by the destination code
+static REGIONS: [&'static str; 18] = [
+    "Japan", "North America", "Europe", "Scandinavia", "Finland", "Denmark", "France", "Netherlands",
+    "Spain", "Germany", "Italy", "China", "Indonesia", "South Korea", "International", "Canada",
+    "Brazil", "Australia",
+];
+
+/// The parsed internal header
+#[derive(Clone, Debug)]
+pub struct RomHeader {
+    /// Offset of the header (`$FFC0`) in the ROM image
+    pub offset: usize,
+    pub title: String,
+    /// Map mode byte (`$FFD5`)
+    pub map_mode: u8,
+    /// Cartridge type (`$FFD6`)
+    pub chipset: u8,
+    /// ROM size exponent: the ROM has `1 << rom_size` KiB
+    pub rom_size: u8,
+    /// RAM size exponent, 0 if the cartridge has no RAM
+    pub ram_size: u8,
+    /// Destination code
+    pub region: u8,
+    pub developer: u8,
+    pub version: u8,
+    pub complement: u16,
+    pub checksum: u16,
+    /// Checksum calculated from the ROM data
+    pub actual_checksum: u16,
+    /// Maker and game code from the extended header
+    pub maker_code: Option<String>,
+    pub game_code: Option<String>,
+    /// Native mode vectors, in the order of `NATIVE_VECTORS`
+    pub native_vectors: [u16; 6],
+    /// Emulation mode vectors, in the order of `EMULATION_VECTORS`
+    pub emulation_vectors: [u16; 6],
+}
+
+/// Reads a string from the header, replacing non-ASCII bytes
+fn ascii_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| if b >= 0x20 && b < 0x7f { b as char } else { '.' }).collect::<String>()
+}
+
+/// Calculates the checksum of a ROM image
+///
+/// The checksum is the 16-bit sum of all bytes. If the size isn't a power of two, the part after
+/// the largest power of two is repeated until it's as large as the first part, which is how it is
+/// mirrored on the bus.
+pub fn checksum(rom: &[u8]) -> u16 {
+    if rom.is_empty() {
+        return 0;
+    }
+    let mut base = 1;
+    while base * 2 <= rom.len() {
+        base *= 2;
+    }
+    let sum = |bytes: &[u8]| bytes.iter().fold(0u32, |sum, &b| sum.wrapping_add(b as u32));
+
+    let rest = &rom[base..];
+    let mut total = sum(&rom[..base]);
+    if !rest.is_empty() {
+        let rest_sum = sum(rest);
+        let mut len = 0;
+        while len < base {
+            total = total.wrapping_add(rest_sum);
+            len += rest.len();
+        }
+    }
+    total as u16
+}
+
+impl RomHeader {
+    /// Parses the header at `offset` in the ROM image. Returns `None` if the image is too small.
+    pub fn parse(rom: &[u8], offset: usize) -> Option<RomHeader> {
+        if rom.len() < offset + 0x40 {
+            return None;
+        }
+        let header = &rom[offset..offset + 0x40];
+        let word = |i: usize| header[i] as u16 | (header[i + 1] as u16) << 8;
+
+        let mut native_vectors = [0; 6];
+        let mut emulation_vectors = [0; 6];
+        for i in 0..6 {
+            native_vectors[i] = word(0x24 + i * 2);
+            emulation_vectors[i] = word(0x34 + i * 2);
+        }
+
+        let extended = header[0x1a] == 0x33 && offset >= 0x10;
+        let ext = if extended { &rom[offset - 0x10..offset] } else { &[][..] };
+
+        Some(RomHeader {
+            offset: offset,
+            title: ascii_string(&header[..21]).trim_right().to_string(),
+            map_mode: header[0x15],
+            chipset: header[0x16],
+            rom_size: header[0x17],
+            ram_size: header[0x18],
+            region: header[0x19],
+            developer: header[0x1a],
+            version: header[0x1b],
+            complement: word(0x1c),
+            checksum: word(0x1e),
+            actual_checksum: checksum(rom),
+            maker_code: if extended { Some(ascii_string(&ext[0..2])) } else { None },
+            game_code: if extended { Some(ascii_string(&ext[2..6])) } else { None },
+            native_vectors: native_vectors,
+            emulation_vectors: emulation_vectors,
+        })
+    }
+
+    /// Describes the map mode byte
+    pub fn map_mode_string(&self) -> String {
+        let layout = match self.map_mode & 0x0f {
+            0x0 => "LoROM",
+            0x1 => "HiROM",
+            0x2 => "LoROM (S-DD1)",
+            0x3 => "LoROM (SA-1)",
+            0x5 => "ExHiROM",
+            0xa => "HiROM (SPC7110)",
+            _ => "Unknown",
+        };
+        let speed = if self.map_mode & 0x10 != 0 { "FastROM" } else { "SlowROM" };
+        format!("{}, {} (${:02X})", layout, speed, self.map_mode)
+    }
+
+    /// Describes the cartridge type
+    pub fn chipset_string(&self) -> String {
+        let coprocessor = match self.chipset >> 4 {
+            0x0 => "DSP",
+            0x1 => "GSU (SuperFX)",
+            0x2 => "OBC1",
+            0x3 => "SA-1",
+            0x4 => "S-DD1",
+            0x5 => "S-RTC",
+            0xe => "Other",
+            _ => "Custom",
+        };
+        let parts = match self.chipset & 0x0f {
+            0x0 => "ROM".to_string(),
+            0x1 => "ROM + RAM".to_string(),
+            0x2 => "ROM + RAM + Battery".to_string(),
+            0x3 => format!("ROM + {}", coprocessor),
+            0x4 => format!("ROM + {} + RAM", coprocessor),
+            0x5 => format!("ROM + {} + RAM + Battery", coprocessor),
+            0x6 => format!("ROM + {} + Battery", coprocessor),
+            _ => "Unknown".to_string(),
+        };
+        format!("{} (${:02X})", parts, self.chipset)
+    }
+
+    /// ROM size given in the header, in bytes
+    pub fn rom_bytes(&self) -> usize {
+        if self.rom_size > 24 { 0 } else { 0x400 << self.rom_size }
+    }
+
+    /// Cartridge RAM size given in the header, in bytes
+    pub fn ram_bytes(&self) -> usize {
+        if self.ram_size == 0 || self.ram_size > 20 { 0 } else { 0x400 << self.ram_size }
+    }
+
+    /// Whether the cartridge RAM is battery-backed
+    pub fn has_battery(&self) -> bool {
+        match self.chipset & 0x0f {
+            0x2 | 0x5 | 0x6 => true,
+            _ => false,
+        }
+    }
+
+    pub fn region_string(&self) -> &'static str {
+        REGIONS.get(self.region as usize).cloned().unwrap_or("Unknown")
+    }
+
+    /// Whether checksum and complement add up to `$FFFF`
+    pub fn complement_valid(&self) -> bool {
+        self.checksum.wrapping_add(self.complement) == 0xffff
+    }
+
+    /// Whether the checksum matches the ROM data
+    pub fn checksum_valid(&self) -> bool {
+        self.checksum == self.actual_checksum
+    }
+}
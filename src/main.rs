@@ -5,14 +5,41 @@ extern crate clap;
 extern crate gtk;
 extern crate gdk;
 extern crate gdk_pixbuf;
+extern crate flate2;
+extern crate zip;
 extern crate breeze_core;
 extern crate breeze_frontends;
 
 #[macro_use]
 mod clone;
+mod apu;
+mod audio;
 mod blank_rom;
+mod breakpoint;
+mod brr;
+mod callstack;
+mod cdl;
+mod cheat;
+mod config;
 mod data;
+mod disasm;
+mod export;
+mod hdma;
+mod header;
+mod memmap;
+mod mmio;
+mod patch;
+mod profiler;
+mod ramsearch;
+mod regs;
+mod romfile;
+mod spc;
+mod symbols;
+mod timeline;
+mod trace;
 mod view;
+mod watch;
+mod wav;
 mod model;
 mod util;
 mod tools;
@@ -21,7 +48,8 @@ use clap::{App, Arg};
 
 use std::rc::Rc;
 use std::cell::RefCell;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process;
 
 fn main() {
     env_logger::init().unwrap();
@@ -33,6 +61,24 @@ fn main() {
                       .about("GUI tool for introspection of Breeze save states")
                       .arg(Arg::with_name("rom").takes_value(true))
                       .arg(Arg::with_name("state").takes_value(true))
+                      .arg(Arg::with_name("patch")
+                          .long("patch")
+                          .takes_value(true)
+                          .value_name("FILE")
+                          .help("Apply an IPS, UPS or BPS patch to the ROM when loading it"))
+                      .arg(Arg::with_name("export-disasm")
+                          .long("export-disasm")
+                          .takes_value(true)
+                          .value_name("FILE")
+                          .help("Write a disassembly of the ROM to FILE and exit"))
+                      .arg(Arg::with_name("export-spc")
+                          .long("export-spc")
+                          .takes_value(true)
+                          .value_name("FILE")
+                          .help("Write the APU state (after loading the save state) to an SPC file and exit"))
+                      .arg(Arg::with_name("track-calls")
+                          .long("track-calls")
+                          .help("Track subroutine calls from reset for the call stack view"))
                       .get_matches();
 
     let model = Rc::new(RefCell::new(model::Model::new()));
@@ -42,12 +88,35 @@ fn main() {
     {
         let mut model = model.borrow_mut();
         model.set_view(weak_view);
+        if matches.is_present("track-calls") {
+            model.set_call_tracking(true);
+        }
         if let Some(rom) = matches.value_of("rom") {
-            model.load_rom(PathBuf::from(rom)).unwrap();    // FIXME dont unwrap
+            let patch = matches.value_of("patch").map(PathBuf::from);
+            model.load_rom(PathBuf::from(rom), patch).unwrap();    // FIXME dont unwrap
         }
         if let Some(state) = matches.value_of("state") {
             model.load_save_state(PathBuf::from(state)).unwrap();
         }
+        if let Some(path) = matches.value_of("export-disasm") {
+            match model.export_disassembly(Path::new(path)) {
+                Ok(summary) => println!("Wrote {} instructions to {}", summary.instructions, path),
+                Err(e) => {
+                    println!("Error while writing disassembly: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        if let Some(path) = matches.value_of("export-spc") {
+            if let Err(e) = model.export_spc(Path::new(path)) {
+                println!("Error while writing SPC file: {}", e);
+                process::exit(1);
+            }
+            println!("Wrote APU state to {}", path);
+        }
+        if matches.is_present("export-disasm") || matches.is_present("export-spc") {
+            return;
+        }
     }
     view.main_loop();
 }
@@ -0,0 +1,398 @@
+//! SNES bus address decoding
+//!
+//! This mirrors the cartridge mapping done by Breeze, so that the debugger can read ROM and WRAM
+//! without going through the emulated bus (which would cause side effects when touching I/O
+//! registers).
+
+/// Cartridge mapping modes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MapMode {
+    LoRom,
+    HiRom,
+    /// HiROM extended to 8 MiB, with the second 4 MiB mapped to banks `$40`-`$7D` and `$00`-`$3F`
+    ExHiRom,
+}
+
+impl MapMode {
+    /// Guesses the mapping mode of a ROM image by checking which of the possible header locations
+    /// contains the more plausible header.
+    ///
+    /// ExHiROM images are larger than 4 MiB and have their header in the second 4 MiB.
+    pub fn detect(rom: &[u8]) -> MapMode {
+        let hirom = header_score(rom, 0xffc0);
+        if rom.len() > 0x400000 && header_score(rom, 0x40ffc0) > hirom.max(header_score(rom, 0x7fc0)) {
+            MapMode::ExHiRom
+        } else if hirom > header_score(rom, 0x7fc0) {
+            MapMode::HiRom
+        } else {
+            MapMode::LoRom
+        }
+    }
+
+    /// Offset of the internal header in the ROM image
+    pub fn header_offset(&self) -> usize {
+        match *self {
+            MapMode::LoRom => 0x7fc0,
+            MapMode::HiRom => 0xffc0,
+            MapMode::ExHiRom => 0x40ffc0,
+        }
+    }
+
+    /// Number of ROM bytes mapped into a bank
+    pub fn bank_size(&self) -> usize {
+        match *self {
+            MapMode::LoRom => 0x8000,
+            MapMode::HiRom | MapMode::ExHiRom => 0x10000,
+        }
+    }
+
+    /// Translates a 24-bit bus address to an offset into a ROM image of size `rom_len`.
+    ///
+    /// Returns `None` if `addr` doesn't map to ROM. Addresses past the end of the image are
+    /// mirrored.
+    pub fn rom_offset(&self, addr: u32, rom_len: usize) -> Option<usize> {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+        if rom_len == 0 || bank & 0xfe == 0x7e {
+            return None;
+        }
+
+        let raw = match *self {
+            MapMode::LoRom => {
+                if offset < 0x8000 { return None; }
+                ((bank as usize & 0x7f) << 15) | (offset as usize & 0x7fff)
+            }
+            MapMode::HiRom => {
+                if bank & 0x40 == 0 && offset < 0x8000 { return None; }
+                ((bank as usize & 0x3f) << 16) | offset as usize
+            }
+            MapMode::ExHiRom => {
+                if bank & 0x40 == 0 && offset < 0x8000 { return None; }
+                // Banks `$00`-`$7D` map the second 4 MiB
+                let high = if bank & 0x80 == 0 { 0x400000 } else { 0 };
+                high | ((bank as usize & 0x3f) << 16) | offset as usize
+            }
+        };
+
+        Some(raw % rom_len)
+    }
+
+    /// Translates an offset into the ROM image to the 24-bit bus address it's mapped at
+    ///
+    /// LoROM banks start at `$00` (using the `$FE`/`$FF` mirrors where WRAM is mapped) and HiROM
+    /// banks at `$C0`. The second 4 MiB of ExHiROM images start at `$40`, using banks `$3E`/`$3F`
+    /// where WRAM is mapped.
+    pub fn bus_addr(&self, offset: usize) -> u32 {
+        match *self {
+            MapMode::LoRom => {
+                let bank = (offset as u32 >> 15) & 0x7f;
+                let bank = if bank >= 0x7e { bank | 0x80 } else { bank };
+                bank << 16 | 0x8000 | (offset as u32 & 0x7fff)
+            }
+            MapMode::HiRom => (0xc0 | ((offset as u32 >> 16) & 0x3f)) << 16 | (offset as u32 & 0xffff),
+            MapMode::ExHiRom => {
+                let bank = (offset as u32 >> 16) & 0x7f;
+                let bank = match bank {
+                    0x00...0x3f => bank | 0xc0,
+                    0x7e | 0x7f => bank & 0x3f,
+                    _ => bank,
+                };
+                bank << 16 | (offset as u32 & 0xffff)
+            }
+        }
+    }
+
+    /// Returns all bus addresses the ROM offset `offset` is mapped at, one per bank
+    pub fn rom_mirrors(&self, offset: usize, rom_len: usize) -> Vec<u32> {
+        let low = self.bus_addr(offset) & 0xffff;
+        (0..0x100u32).map(|bank| bank << 16 | low)
+                     .filter(|&addr| self.rom_offset(addr, rom_len) == Some(offset))
+                     .collect()
+    }
+
+    /// Translates a 24-bit bus address to an offset into cartridge RAM of size `sram_len`
+    ///
+    /// LoROM maps SRAM to `$0000`-`$7FFF` of banks `$70`-`$7D` and `$F0`-`$FF`, HiROM and ExHiROM to
+    /// `$6000`-`$7FFF` of banks `$20`-`$3F` and `$A0`-`$BF`. The RAM is mirrored if it's smaller.
+    pub fn sram_offset(&self, addr: u32, sram_len: usize) -> Option<usize> {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+        if sram_len == 0 {
+            return None;
+        }
+
+        let raw = match (*self, bank) {
+            (MapMode::LoRom, 0x70...0x7d) | (MapMode::LoRom, 0xf0...0xff) if offset < 0x8000 => {
+                ((bank as usize & 0x0f) << 15) | offset as usize
+            }
+            (MapMode::HiRom, 0x20...0x3f) | (MapMode::HiRom, 0xa0...0xbf) |
+            (MapMode::ExHiRom, 0x20...0x3f) | (MapMode::ExHiRom, 0xa0...0xbf)
+                    if offset >= 0x6000 && offset < 0x8000 => {
+                ((bank as usize & 0x1f) << 13) | (offset as usize - 0x6000)
+            }
+            _ => return None,
+        };
+        Some(raw % sram_len)
+    }
+
+    /// Returns the kind of memory at a bus address
+    pub fn area(&self, addr: u32, rom_len: usize, sram_len: usize) -> Area {
+        let bank = (addr >> 16) as u8;
+        let offset = addr as u16;
+        if bank & 0xfe == 0x7e {
+            return Area::Wram;
+        }
+        if bank & 0x40 == 0 {
+            match offset {
+                0x0000...0x1fff => return Area::WramMirror,
+                0x2100...0x21ff => return Area::BBus,
+                0x4000...0x41ff => return Area::Joypad,
+                0x4200...0x5fff => return Area::CpuRegs,
+                _ => {}
+            }
+        }
+        if self.sram_offset(addr, sram_len).is_some() {
+            Area::Sram
+        } else if self.rom_offset(addr, rom_len).is_some() {
+            Area::Rom
+        } else {
+            Area::Unmapped
+        }
+    }
+
+    /// Describes the whole bus, as ranges of addresses with the same kind of memory
+    ///
+    /// Banks with the same layout are combined into one range.
+    pub fn bus_map(&self, rom_len: usize, sram_len: usize) -> Vec<BusRange> {
+        // Layout of each bank: start address, end address and area
+        let layouts = (0..0x100u32).map(|bank| {
+            let mut ranges: Vec<(u16, u16, Area)> = Vec::new();
+            for addr in (0..0x10000 / MAP_STEP).map(|i| i * MAP_STEP) {
+                let area = self.area(bank << 16 | addr, rom_len, sram_len);
+                let end = (addr + MAP_STEP - 1) as u16;
+                match ranges.last_mut() {
+                    Some(last) if last.2 == area => {
+                        last.1 = end;
+                        continue;
+                    }
+                    _ => {}
+                }
+                ranges.push((addr as u16, end, area));
+            }
+            ranges
+        }).collect::<Vec<_>>();
+
+        let mut map = Vec::new();
+        let mut first = 0;
+        for bank in 1..0x101 {
+            if bank < 0x100 && layouts[bank] == layouts[first] {
+                continue;
+            }
+            for &(start, end, area) in &layouts[first] {
+                map.push(BusRange {
+                    first_bank: first as u8,
+                    last_bank: (bank - 1) as u8,
+                    start: start,
+                    end: end,
+                    area: area,
+                });
+            }
+            first = bank;
+        }
+        map
+    }
+}
+
+/// Kinds of memory mapped on the bus
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Area {
+    Wram,
+    /// The first 8 KiB of WRAM, mirrored in the system banks
+    WramMirror,
+    /// PPU, APU and WRAM port registers on the B bus
+    BBus,
+    /// Legacy joypad registers
+    Joypad,
+    /// CPU registers and DMA
+    CpuRegs,
+    Rom,
+    Sram,
+    /// Nothing mapped (reads return open bus) or expansion ports
+    Unmapped,
+}
+
+impl Area {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Area::Wram => "WRAM",
+            Area::WramMirror => "WRAM (mirror of $7E:0000-$1FFF)",
+            Area::BBus => "PPU/APU registers (B bus)",
+            Area::Joypad => "Joypad registers",
+            Area::CpuRegs => "CPU registers and DMA",
+            Area::Rom => "ROM",
+            Area::Sram => "SRAM",
+            Area::Unmapped => "Unmapped / expansion",
+        }
+    }
+}
+
+/// A range of addresses with the same kind of memory, repeated in consecutive banks
+#[derive(Clone, Copy, Debug)]
+pub struct BusRange {
+    pub first_bank: u8,
+    pub last_bank: u8,
+    pub start: u16,
+    pub end: u16,
+    pub area: Area,
+}
+
+/// Granularity of the bus map
+const MAP_STEP: u32 = 0x100;
+
+/// Rates how much the 64 bytes at `offset` (the header and the interrupt vectors) look like a
+/// valid internal cartridge header
+fn header_score(rom: &[u8], offset: usize) -> i32 {
+    if rom.len() < offset + 0x40 {
+        return -1;
+    }
+    let header = &rom[offset..offset + 0x40];
+
+    let mut score = 0;
+    // Checksum and complement should add up to $FFFF
+    let complement = header[0x1c] as u16 | (header[0x1d] as u16) << 8;
+    let checksum = header[0x1e] as u16 | (header[0x1f] as u16) << 8;
+    if checksum.wrapping_add(complement) == 0xffff { score += 4; }
+    // Printable title
+    if header[..21].iter().all(|&b| b >= 0x20 && b < 0x7f) { score += 2; }
+    // Reset vector should point into ROM
+    let reset = header[0x3c] as u16 | (header[0x3d] as u16) << 8;
+    if reset >= 0x8000 { score += 1; }
+
+    score
+}
+
+/// Translates a 24-bit bus address to an offset into the 128 KiB of WRAM, if it maps there
+pub fn wram_offset(addr: u32) -> Option<usize> {
+    let bank = (addr >> 16) as u8;
+    let offset = addr as u16;
+    match bank {
+        0x7e | 0x7f => Some(addr as usize & 0x1ffff),
+        0x00...0x3f | 0x80...0xbf if offset < 0x2000 => Some(offset as usize),
+        _ => None,
+    }
+}
+
+/// Maps mirrored addresses to a single canonical address
+///
+/// WRAM is mapped to banks `$7E`/`$7F` and the I/O area (`$2000`-`$5FFF` of the system banks) is
+/// mapped to bank `$00`. All other addresses are returned unchanged.
+pub fn canonical(addr: u32) -> u32 {
+    let bank = (addr >> 16) as u8;
+    let offset = addr as u16;
+    if let Some(offset) = wram_offset(addr) {
+        return 0x7e0000 + offset as u32;
+    }
+    match bank {
+        0x00...0x3f | 0x80...0xbf if offset >= 0x2000 && offset < 0x6000 => offset as u32,
+        _ => addr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a ROM image of `len` bytes with a plausible header at `offset`
+    fn rom_with_header(len: usize, offset: usize) -> Vec<u8> {
+        let mut rom = vec![0; len];
+        let title = b"BREEZE TEST          ";
+        rom[offset..offset + 21].copy_from_slice(title);
+        // Complement $FFFF, checksum $0000
+        rom[offset + 0x1c..offset + 0x20].copy_from_slice(&[0xff, 0xff, 0x00, 0x00]);
+        // Reset vector $8000
+        rom[offset + 0x3c..offset + 0x3e].copy_from_slice(&[0x00, 0x80]);
+        rom
+    }
+
+    /// Checks that every `step`th offset of a ROM of `len` bytes maps back to itself
+    fn assert_round_trip(mode: MapMode, len: usize, step: usize) {
+        for offset in (0..len).filter(|offset| offset % step == 0) {
+            let addr = mode.bus_addr(offset);
+            assert_eq!(mode.rom_offset(addr, len), Some(offset), "{:?} offset ${:06X}", mode, offset);
+        }
+    }
+
+    #[test]
+    fn detect() {
+        assert_eq!(MapMode::detect(&rom_with_header(0x100000, 0x7fc0)), MapMode::LoRom);
+        assert_eq!(MapMode::detect(&rom_with_header(0x100000, 0xffc0)), MapMode::HiRom);
+        assert_eq!(MapMode::detect(&rom_with_header(0x600000, 0x40ffc0)), MapMode::ExHiRom);
+        // The ExHiROM header location is only checked for large images
+        assert_eq!(MapMode::detect(&rom_with_header(0x600000, 0xffc0)), MapMode::HiRom);
+        assert_eq!(MapMode::detect(&[]), MapMode::LoRom);
+    }
+
+    #[test]
+    fn lorom() {
+        let mode = MapMode::LoRom;
+        assert_eq!(mode.bus_addr(0), 0x008000);
+        assert_eq!(mode.bus_addr(0x8000), 0x018000);
+        // Banks $7E/$7F are WRAM, their ROM is reached through $FE/$FF
+        assert_eq!(mode.bus_addr(0x3f0000), 0xfe8000);
+        assert_eq!(mode.rom_offset(0x808000, 0x400000), Some(0));
+        assert_eq!(mode.rom_offset(0x007fff, 0x400000), None);
+        assert_eq!(mode.rom_offset(0x7e8000, 0x400000), None);
+        // Small images are mirrored
+        assert_eq!(mode.rom_offset(0x048000, 0x10000), Some(0));
+        assert_round_trip(mode, 0x400000, 0x1fff);
+    }
+
+    #[test]
+    fn hirom() {
+        let mode = MapMode::HiRom;
+        assert_eq!(mode.bus_addr(0), 0xc00000);
+        assert_eq!(mode.bus_addr(0x12345), 0xc12345);
+        assert_eq!(mode.rom_offset(0x018000, 0x400000), Some(0x18000));
+        assert_eq!(mode.rom_offset(0x410000, 0x400000), Some(0x10000));
+        assert_eq!(mode.rom_offset(0x017fff, 0x400000), None);
+        assert_round_trip(mode, 0x400000, 0x1fff);
+    }
+
+    #[test]
+    fn exhirom() {
+        let mode = MapMode::ExHiRom;
+        assert_eq!(mode.bus_addr(0), 0xc00000);
+        assert_eq!(mode.bus_addr(0x400000), 0x400000);
+        assert_eq!(mode.bus_addr(0x7e8000), 0x3e8000);
+        // Banks $80-$FF map the first 4 MiB, $00-$7D the second
+        assert_eq!(mode.rom_offset(0x808000, 0x800000), Some(0x8000));
+        assert_eq!(mode.rom_offset(0x008000, 0x800000), Some(0x408000));
+        assert_eq!(mode.rom_offset(0x3e8000, 0x800000), Some(0x7e8000));
+        assert_eq!(mode.rom_offset(0x007fff, 0x800000), None);
+        assert_eq!(mode.rom_offset(0x7e0000, 0x800000), None);
+        assert_eq!(mode.header_offset(), 0x40ffc0);
+        assert_round_trip(mode, 0x600000, 0x1fff);
+    }
+
+    #[test]
+    fn sram_offset() {
+        let lorom = MapMode::LoRom;
+        assert_eq!(lorom.sram_offset(0x700000, 0x8000), Some(0));
+        assert_eq!(lorom.sram_offset(0xf07fff, 0x8000), Some(0x7fff));
+        assert_eq!(lorom.sram_offset(0x710000, 0x10000), Some(0x8000));
+        // Small RAM is mirrored
+        assert_eq!(lorom.sram_offset(0x702001, 0x2000), Some(1));
+        assert_eq!(lorom.sram_offset(0x708000, 0x8000), None);
+        assert_eq!(lorom.sram_offset(0x306000, 0x8000), None);
+
+        for &mode in &[MapMode::HiRom, MapMode::ExHiRom] {
+            assert_eq!(mode.sram_offset(0x206000, 0x8000), Some(0));
+            assert_eq!(mode.sram_offset(0xa17fff, 0x8000), Some(0x3fff));
+            assert_eq!(mode.sram_offset(0x205fff, 0x8000), None);
+            assert_eq!(mode.sram_offset(0x006000, 0x8000), None);
+            assert_eq!(mode.sram_offset(0x700000, 0x8000), None);
+        }
+
+        assert_eq!(lorom.sram_offset(0x700000, 0), None);
+    }
+}
@@ -0,0 +1,96 @@
+//! Logging of CPU accesses to memory-mapped I/O registers
+
+use data::CpuState;
+use disasm::{Instr, MemAccess, Access};
+use memmap;
+
+/// A single byte read from or written to an I/O register by the CPU
+#[derive(Clone, Debug)]
+pub struct MmioAccess {
+    /// Scanline at the start of the instruction
+    pub line: u16,
+    /// H position at the start of the instruction
+    pub h: u16,
+    /// Address of the accessing instruction
+    pub pc: u32,
+    /// Register address in bank `$00`
+    pub reg: u16,
+    pub write: bool,
+    /// The value read or written, if it can be determined from the CPU registers
+    pub value: Option<u8>,
+}
+
+/// Returns the register address if `addr` is one of the logged I/O registers (`$2100-$21FF`,
+/// `$4016-$4017` and `$4200-$43FF`)
+pub fn mmio_reg(addr: u32) -> Option<u16> {
+    let addr = memmap::canonical(addr);
+    if addr > 0xffff {
+        return None;
+    }
+    match addr as u16 {
+        reg @ 0x2100...0x21ff | reg @ 0x4016...0x4017 | reg @ 0x4200...0x43ff => Some(reg),
+        _ => None,
+    }
+}
+
+/// Checks whether `accesses` contain a write to the register `reg`
+pub fn writes_reg(accesses: &[MemAccess], reg: u16) -> bool {
+    accesses.iter().any(|access| {
+        access.kind != Access::Read && (0..access.len as u32).any(|i| mmio_reg(access.addr + i) == Some(reg))
+    })
+}
+
+/// Appends the I/O register accesses done by `instr` to `log`
+///
+/// `before` and `after` are the CPU states before and after executing the instruction. They're
+/// used to determine the transferred values of loads and stores.
+pub fn log_accesses(log: &mut Vec<MmioAccess>,
+                    instr: &Instr,
+                    accesses: &[MemAccess],
+                    before: &CpuState,
+                    after: &CpuState,
+                    line: u16,
+                    h: u16) {
+    for access in accesses {
+        // A read-modify-write instruction shows up as a read followed by a write
+        let kinds: &[bool] = match access.kind {
+            Access::Read => &[false],
+            Access::Write => &[true],
+            Access::Modify => &[false, true],
+        };
+
+        for &write in kinds {
+            let value = if access.kind == Access::Modify {
+                None
+            } else if write {
+                match instr.mnemonic {
+                    "STA" => Some(before.a),
+                    "STX" => Some(before.x),
+                    "STY" => Some(before.y),
+                    "STZ" => Some(0),
+                    _ => None,
+                }
+            } else {
+                match instr.mnemonic {
+                    "LDA" => Some(after.a),
+                    "LDX" => Some(after.x),
+                    "LDY" => Some(after.y),
+                    _ => None,
+                }
+            };
+
+            for i in 0..access.len as u32 {
+                if let Some(reg) = mmio_reg(access.addr + i) {
+                    log.push(MmioAccess {
+                        line: line,
+                        h: h,
+                        pc: instr.addr,
+                        reg: reg,
+                        write: write,
+                        value: value.map(|v| (v >> (8 * i)) as u8),
+                    });
+                }
+            }
+        }
+    }
+}
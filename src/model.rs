@@ -1,4 +1,23 @@
-use blank_rom::load_blank_rom;
+use audio::Recorder;
+use blank_rom::blank_rom_image;
+use breakpoint::{Breakpoint, Kind};
+use callstack::{self, CallStack, StackEntry};
+use cdl::CodeDataLog;
+use cheat::{self, Cheat};
+use config;
+use disasm::{Instr, MemAccess};
+use export::{self, ExportSummary};
+use hdma::HdmaRecorder;
+use header::RomHeader;
+use memmap::{self, MapMode};
+use mmio;
+use patch;
+use profiler::{Profiler, Profile, CYCLES_PER_DOT};
+use romfile::{self, RomSource};
+use spc;
+use symbols::SymbolTable;
+use timeline::{self, TimingEvent, EventKind};
+use trace::{Tracer, TraceOptions};
 use view::View;
 use data::*;
 
@@ -7,13 +26,70 @@ use breeze_core::snes::Snes;
 use breeze_core::save::SaveStateFormat;
 use breeze_core::ppu::FrameBuf;
 
-use std::io::{self, Read};
-use std::path::PathBuf;
+use std::io::{self, Read, Write, BufWriter};
+use std::path::{Path, PathBuf};
 use std::rc::{Rc, Weak};
 use std::fs::File;
 
+/// Maximum number of frames to emulate while waiting for a stop condition, before giving up
+const RUN_LIMIT: u32 = 600;
+
+/// Number of instructions to disassemble, starting at the program counter
+const DISASM_LINES: usize = 64;
+
 pub struct Model {
     snes: Snes,
+    /// Path of the loaded ROM file, if any
+    rom_path: Option<PathBuf>,
+    /// ROM image as loaded, before cheats are applied
+    clean_rom: Vec<u8>,
+    /// CRC32 of `clean_rom`, identifying the ROM in the configuration
+    rom_crc: u32,
+    /// Raw ROM image (with cheats applied), used to read code without going through the emulated
+    /// bus
+    rom_data: Vec<u8>,
+    map_mode: MapMode,
+    /// Internal header of the clean ROM image
+    header: Option<RomHeader>,
+    /// How the ROM file was loaded
+    rom_source: RomSource,
+    breakpoints: Vec<Breakpoint>,
+    /// Describes why emulation was last halted, if it was halted by a breakpoint
+    stop_reason: Option<String>,
+    /// Address of the instruction a breakpoint halted at, so that resuming executes it instead of
+    /// halting again. Cleared once an instruction was executed.
+    resume_addr: Option<u32>,
+    /// Memory contents at the start of the last step, for highlighting changes
+    prev_memory: Option<MemoryDump>,
+    freezes: Vec<Freeze>,
+    cheats: Vec<Cheat>,
+    /// Results of the last profiling run
+    profile: Option<Profile>,
+    /// Whether subroutine calls are tracked while executing instructions
+    track_calls: bool,
+    /// Active subroutine calls, if `track_calls` is set
+    calls: CallStack,
+    /// Whether `calls` was tracked since the ROM was loaded, or seeded from a stack scan
+    calls_from_reset: bool,
+    /// Symbols used to label addresses
+    symbols: SymbolTable,
+    /// Path of the loaded symbol file, if any
+    symbols_path: Option<PathBuf>,
+    /// Whether executed instructions and their accesses are recorded in `cdl`
+    log_cdl: bool,
+    cdl: CodeDataLog,
+    /// Active audio recording, if any
+    recorder: Option<Recorder>,
+    /// Why the last recording was stopped, if it failed
+    recording_error: Option<String>,
+    /// Voices muted by the user
+    muted_voices: u8,
+    /// Voices soloed by the user. If any are, all other voices are muted.
+    soloed_voices: u8,
+    /// Data captured while emulating the last previewed frame
+    capture: FrameCapture,
+    /// Save state `capture` was started from, to only capture again when the state changed
+    capture_state: Vec<u8>,
     view: Option<Weak<View>>,
 }
 
@@ -22,8 +98,37 @@ impl Model {
     ///
     /// `Model::set_view` must be called before attempting to use it.
     pub fn new() -> Self {
+        let rom_data = blank_rom_image();
+        let cdl = CodeDataLog::new(rom_data.len(), MapMode::detect(&rom_data).bank_size());
         Model {
-            snes: Snes::new(load_blank_rom()),
+            snes: Snes::new(Rom::from_bytes(&rom_data).unwrap()),
+            map_mode: MapMode::detect(&rom_data),
+            header: RomHeader::parse(&rom_data, MapMode::detect(&rom_data).header_offset()),
+            rom_path: None,
+            rom_source: RomSource::default(),
+            clean_rom: rom_data.clone(),
+            rom_crc: patch::crc32(&rom_data),
+            rom_data: rom_data,
+            breakpoints: Vec::new(),
+            stop_reason: None,
+            resume_addr: None,
+            prev_memory: None,
+            freezes: Vec::new(),
+            cheats: Vec::new(),
+            profile: None,
+            track_calls: false,
+            calls: CallStack::new(),
+            calls_from_reset: true,
+            symbols: SymbolTable::new(),
+            symbols_path: None,
+            log_cdl: false,
+            cdl: cdl,
+            recorder: None,
+            recording_error: None,
+            muted_voices: 0,
+            soloed_voices: 0,
+            capture: FrameCapture::default(),
+            capture_state: Vec::new(),
             view: None,
         }
     }
@@ -37,11 +142,56 @@ impl Model {
     }
 
     /// Load a ROM file from the given path
-    pub fn load_rom(&mut self, path: PathBuf) -> io::Result<()> {
-        let mut file = try!(File::open(path));
-        let mut content = vec![];
-        try!(file.read_to_end(&mut content));
-        self.snes = Snes::new(try!(Rom::from_bytes(&content)));
+    ///
+    /// The file may be a `.zip` or `.gz` archive, and copier headers are removed. If `patch` is
+    /// given, the IPS, UPS or BPS patch at that path is applied.
+    ///
+    /// Cheats and the code/data log saved alongside the ROM are loaded as well, and so is the
+    /// symbol file last used with the ROM (or one next to it).
+    pub fn load_rom(&mut self, path: PathBuf, patch: Option<PathBuf>) -> io::Result<()> {
+        let (content, source) = try!(romfile::load(&path, patch.as_ref().map(|patch| &**patch)));
+        let cheats = try!(cheat::load(&cheats_path(&path)));
+        let map_mode = MapMode::detect(&content);
+        let rom_data = apply_rom_cheats(&content, &cheats, map_mode);
+        let snes = Snes::new(try!(Rom::from_bytes(&rom_data)));
+
+        // Nothing below can fail, so a ROM that fails to load leaves the old one untouched
+        self.snes = snes;
+        self.cheats = cheats;
+        self.rom_source = source;
+        self.map_mode = map_mode;
+        self.header = RomHeader::parse(&content, map_mode.header_offset());
+        self.rom_crc = patch::crc32(&content);
+        self.clean_rom = content;
+        self.rom_data = rom_data;
+        let cdl_path = cdl_path(&path);
+        self.rom_path = Some(path);
+        self.prev_memory = None;
+        self.resume_addr = None;
+        self.calls = CallStack::new();
+        self.calls_from_reset = true;
+        self.freezes.clear();
+        self.profile = None;
+
+        self.cdl = CodeDataLog::new(self.rom_data.len(), self.map_mode.bank_size());
+        if cdl_path.is_file() {
+            match CodeDataLog::load(&cdl_path, &self.clean_rom, self.map_mode.bank_size()) {
+                Ok(cdl) => self.cdl = cdl,
+                Err(e) => warn!("couldn't load code/data log from {}: {}", cdl_path.display(), e),
+            }
+        }
+
+        self.symbols = SymbolTable::new();
+        self.symbols_path = None;
+        if let Some(sym_path) = self.associated_symbols() {
+            match SymbolTable::load(&sym_path) {
+                Ok(symbols) => {
+                    self.symbols = symbols;
+                    self.symbols_path = Some(sym_path);
+                }
+                Err(e) => warn!("couldn't load symbols from {}: {}", sym_path.display(), e),
+            }
+        }
 
         self.update_frame();
         Ok(())
@@ -53,6 +203,9 @@ impl Model {
         try!(file.read_to_end(&mut content));
         let mut reader = &*content;
         try!(self.snes.restore_save_state(SaveStateFormat::Custom, &mut reader));
+        self.calls = CallStack::from_entries(self.scan_stack());
+        self.calls_from_reset = false;
+        self.resume_addr = None;
 
         self.update_frame();
         Ok(())
@@ -62,7 +215,397 @@ impl Model {
     ///
     /// More accurately, this will run emulation until the last pixel of the frame is rendered.
     pub fn step(&mut self) {
-        self.snes.render_frame(|_| None);
+        self.begin_step();
+        if self.per_instruction() {
+            while !self.exec_instruction() {}
+        } else {
+            self.snes.render_frame(|_| None);
+            self.resume_addr = None;
+        }
+        self.update_frame();
+    }
+
+    /// Runs until the end of the current frame, like `step`, but halts early when a breakpoint is
+    /// hit.
+    ///
+    /// Returns `true` if emulation was halted by a breakpoint.
+    pub fn run_frame(&mut self) -> bool {
+        if !self.breakpoints.iter().any(|bp| bp.enabled) {
+            self.step();
+            return false;
+        }
+
+        self.begin_step();
+        loop {
+            let instr = self.current_instr();
+            if self.check_breakpoints(&instr) {
+                self.update_frame();
+                return true;
+            }
+            if self.exec_instruction() {
+                break;
+            }
+        }
+
+        self.update_frame();
+        false
+    }
+
+    /// Executes a single instruction
+    pub fn step_into(&mut self) {
+        self.begin_step();
+        self.exec_instruction();
+        self.update_frame();
+    }
+
+    /// Executes a single instruction, treating subroutine calls as a single instruction
+    pub fn step_over(&mut self) -> Result<(), String> {
+        let instr = self.current_instr();
+        if instr.is_call() {
+            let ret = instr.next_addr();
+            let sp = self.snes.cpu().s;
+            self.run_until(|model, _| {
+                let cpu = model.snes.cpu();
+                (cpu.pbr as u32) << 16 | cpu.pc as u32 == ret && cpu.s == sp
+            }, "Subroutine did not return")
+        } else {
+            self.step_into();
+            Ok(())
+        }
+    }
+
+    /// Runs until the current subroutine (or interrupt handler) returns to its caller
+    pub fn step_out(&mut self) -> Result<(), String> {
+        let sp = self.snes.cpu().s;
+        self.run_until(|model, executed| {
+            executed.is_return() && model.snes.cpu().s > sp
+        }, "Subroutine did not return")
+    }
+
+    /// Runs until the instruction at the given 24-bit address is about to be executed
+    pub fn run_to(&mut self, addr: u32) -> Result<(), String> {
+        self.run_until(|model, _| {
+            let cpu = model.snes.cpu();
+            (cpu.pbr as u32) << 16 | cpu.pc as u32 == addr
+        }, &format!("${:06X} was not reached", addr))
+    }
+
+    /// Runs until the PPU starts rendering the given scanline
+    pub fn run_to_scanline(&mut self, line: u16) -> Result<(), String> {
+        let mut last_line = self.snes.peripherals().ppu.v_counter();
+        self.run_until(|model, _| {
+            let v = model.snes.peripherals().ppu.v_counter();
+            let reached = v == line && last_line != line;
+            last_line = v;
+            reached
+        }, &format!("Scanline {} was not reached", line))
+    }
+
+    /// Writes a trace of all executed instructions to a file
+    ///
+    /// Runs `opts.skip_frames` frames without logging, then logs `opts.frames` frames. Returns the
+    /// number of lines written.
+    pub fn trace(&mut self, path: PathBuf, opts: TraceOptions) -> io::Result<u64> {
+        let mut tracer = try!(Tracer::create(&path, opts));
+        self.begin_step();
+        for _ in 0..opts.skip_frames {
+            self.snes.render_frame(|_| None);
+            self.resume_addr = None;
+        }
+
+        let mut frames = 0;
+        while frames < opts.frames {
+            let instr = self.current_instr();
+            let cpu = CpuState::new(self.snes.cpu());
+            {
+                let ppu = &self.snes.peripherals().ppu;
+                try!(tracer.log(&instr, &cpu, ppu.h_counter(), ppu.v_counter()));
+            }
+            if self.exec_instruction() {
+                frames += 1;
+            }
+        }
+
+        self.update_frame();
+        tracer.finish()
+    }
+
+    /// Runs `frames` frames, measuring how many cycles are spent at each address and in each
+    /// subroutine
+    ///
+    /// Cycles are derived from the beam movement, so time spent in DMA transfers is attributed to
+    /// the instruction that started them.
+    pub fn profile(&mut self, frames: u32) {
+        let mut profiler = Profiler::new();
+        self.begin_step();
+        let mut done = 0;
+        while done < frames {
+            let instr = self.current_instr();
+            let before = CpuState::new(self.snes.cpu());
+            let pos_before = self.beam_pos();
+            if self.exec_instruction() {
+                done += 1;
+            }
+
+            let after = CpuState::new(self.snes.cpu());
+            let cycles = timeline::dots_between(pos_before, self.beam_pos()) * CYCLES_PER_DOT;
+            let interrupt = self.interrupt_taken(&instr, &after).map(|event| event.kind);
+            let snes = &self.snes;
+            let (rom_data, map_mode) = (&self.rom_data, self.map_mode);
+            profiler.record(&instr, &before, &after, cycles, interrupt,
+                |addr| peek(snes, rom_data, map_mode, addr));
+        }
+
+        self.profile = Some(profiler.finish(frames));
+        self.update_frame();
+    }
+
+    /// Enables or disables tracking of subroutine calls
+    ///
+    /// When enabled after the ROM was loaded, the calls that are already active are reconstructed
+    /// from the stack.
+    pub fn set_call_tracking(&mut self, enabled: bool) {
+        if enabled != self.track_calls {
+            self.track_calls = enabled;
+            self.calls = CallStack::from_entries(self.scan_stack());
+            self.calls_from_reset = false;
+            self.update_frame();
+        }
+    }
+
+    /// Enables or disables code/data logging
+    pub fn set_cdl_logging(&mut self, enabled: bool) {
+        self.log_cdl = enabled;
+        self.update_frame();
+    }
+
+    /// Saves the code/data log next to the ROM file, returning the path of the log
+    pub fn save_cdl(&self) -> io::Result<PathBuf> {
+        let path = match self.rom_path {
+            Some(ref rom_path) => cdl_path(rom_path),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "no ROM file loaded")),
+        };
+        try!(self.cdl.save(&path, &self.clean_rom));
+        Ok(path)
+    }
+
+    /// Replaces the code/data log with the one saved next to the ROM file, returning its path
+    pub fn load_cdl(&mut self) -> io::Result<PathBuf> {
+        let path = match self.rom_path {
+            Some(ref rom_path) => cdl_path(rom_path),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "no ROM file loaded")),
+        };
+        self.cdl = try!(CodeDataLog::load(&path, &self.clean_rom, self.map_mode.bank_size()));
+        self.update_frame();
+        Ok(path)
+    }
+
+    /// Replaces the cartridge RAM with the contents of a battery save (`.srm`) file
+    pub fn import_sram(&mut self, path: &Path) -> io::Result<()> {
+        let mut content = vec![];
+        try!(try!(File::open(path)).read_to_end(&mut content));
+        {
+            let ram = self.snes.peripherals_mut().rom.ram_mut();
+            if content.len() != ram.len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                    format!("save file has {} bytes, but the cartridge has {} bytes of RAM", content.len(), ram.len())));
+            }
+            ram.copy_from_slice(&content);
+        }
+        self.update_frame();
+        Ok(())
+    }
+
+    /// Writes the cartridge RAM to a battery save (`.srm`) file
+    pub fn export_sram(&self, path: &Path) -> io::Result<()> {
+        let ram = self.snes.peripherals().rom.ram();
+        if ram.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "the cartridge has no RAM"));
+        }
+        try!(File::create(path)).write_all(ram)
+    }
+
+    /// Clears the code/data log
+    pub fn reset_cdl(&mut self) {
+        self.cdl = CodeDataLog::new(self.rom_data.len(), self.map_mode.bank_size());
+        self.update_frame();
+    }
+
+    /// Writes a disassembly of the whole ROM (without cheats) to a file
+    ///
+    /// The code/data log and the symbols are used to separate code from data and to name labels.
+    pub fn export_disassembly(&self, path: &Path) -> io::Result<ExportSummary> {
+        let mut out = BufWriter::new(try!(File::create(path)));
+        export::write_disassembly(&mut out, &self.clean_rom, self.map_mode, &self.cdl, &self.symbols,
+            self.rom_title())
+    }
+
+    /// Reloads the current ROM with a patch applied
+    pub fn apply_patch(&mut self, patch: PathBuf) -> io::Result<()> {
+        match self.rom_path.clone() {
+            Some(path) => self.load_rom(path, Some(patch)),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "no ROM loaded")),
+        }
+    }
+
+    /// Writes the current APU state to an `.spc` file
+    pub fn export_spc(&self, path: &Path) -> io::Result<()> {
+        let apu = &self.snes.peripherals().apu;
+        let mut out = BufWriter::new(try!(File::create(path)));
+        spc::write_spc(&mut out, &ApuState::new(apu), &apu.mem[..], self.rom_title())
+    }
+
+    /// Starts recording the audio output to a WAV file at `path`
+    ///
+    /// If `per_voice` is set, the output of each voice is also written to its own file.
+    pub fn start_recording(&mut self, path: &Path, per_voice: bool) -> io::Result<()> {
+        if let Some(recorder) = self.recorder.take() {
+            try!(recorder.finish());
+        }
+        // Don't record output generated before
+        self.take_audio();
+        self.recording_error = None;
+        self.recorder = Some(try!(Recorder::create(path, per_voice)));
+        self.update_frame();
+        Ok(())
+    }
+
+    /// Stops the audio recording, returning the number of recorded sample frames
+    pub fn stop_recording(&mut self) -> io::Result<u32> {
+        let result = self.recorder.take().map_or(Ok(0), |recorder| recorder.finish());
+        self.update_frame();
+        result
+    }
+
+    /// Sets the voices muted and soloed in the audio output
+    pub fn set_voice_mute(&mut self, muted: u8, soloed: u8) {
+        self.muted_voices = muted;
+        self.soloed_voices = soloed;
+        self.snes.peripherals_mut().apu.dsp.set_voice_mask(self.voice_mask());
+        self.update_frame();
+    }
+
+    /// Loads a symbol file and remembers it for the current ROM
+    ///
+    /// Returns the number of loaded symbols.
+    pub fn load_symbols(&mut self, path: PathBuf) -> io::Result<usize> {
+        self.symbols = try!(SymbolTable::load(&path));
+        self.symbols_path = Some(path.clone());
+        self.update_frame();
+
+        let line = path.to_string_lossy().into_owned();
+        let file = try!(config::rom_crc_file("symbols", self.rom_crc));
+        try!(config::write_lines(&file, &[line]));
+        Ok(self.symbols.symbols().len())
+    }
+
+    /// Unloads the symbols and forgets the association with the current ROM
+    pub fn clear_symbols(&mut self) -> io::Result<()> {
+        self.symbols = SymbolTable::new();
+        self.symbols_path = None;
+        self.update_frame();
+        config::rom_crc_file("symbols", self.rom_crc).and_then(|file| config::write_lines(&file, &[]))
+    }
+
+    pub fn add_breakpoint(&mut self, bp: Breakpoint) {
+        self.breakpoints.push(bp);
+        self.update_frame();
+    }
+
+    pub fn remove_breakpoint(&mut self, index: usize) {
+        if index < self.breakpoints.len() {
+            self.breakpoints.remove(index);
+            self.update_frame();
+        }
+    }
+
+    /// Enables a disabled breakpoint and vice versa
+    pub fn toggle_breakpoint(&mut self, index: usize) {
+        if let Some(bp) = self.breakpoints.get_mut(index) {
+            bp.enabled = !bp.enabled;
+        }
+        self.update_frame();
+    }
+
+    /// Resets the hit counters of all breakpoints
+    pub fn reset_breakpoint_hits(&mut self) {
+        for bp in &mut self.breakpoints {
+            bp.hits = 0;
+        }
+        self.update_frame();
+    }
+
+    /// Overwrites bytes in one of the memory spaces, starting at `offset`
+    ///
+    /// The ROM can't be modified this way. Bytes past the end of the memory space are ignored.
+    pub fn poke_memory(&mut self, space: MemorySpace, offset: usize, bytes: &[u8]) {
+        for (i, &value) in bytes.iter().enumerate() {
+            let offset = offset + i;
+            let p = self.snes.peripherals_mut();
+            match space {
+                MemorySpace::Wram if offset < p.wram.len() => p.wram[offset] = value,
+                MemorySpace::Vram if offset < p.ppu.vram.len() => p.ppu.vram[offset] = value,
+                MemorySpace::Oam if offset < p.ppu.oam.len() => p.ppu.oam[offset] = value,
+                MemorySpace::Cgram if offset < 512 => {
+                    let index = (offset / 2) as u8;
+                    let raw = p.ppu.cgram.get_color_raw(index);
+                    let raw = if offset % 2 == 0 {
+                        raw & 0xff00 | value as u16
+                    } else {
+                        raw & 0x00ff | (value as u16) << 8
+                    };
+                    p.ppu.cgram.set_color_raw(index, raw);
+                }
+                MemorySpace::Sram if offset < p.rom.ram().len() => p.rom.ram_mut()[offset] = value,
+                MemorySpace::Aram if offset < p.apu.mem.len() => p.apu.mem[offset] = value,
+                _ => {}
+            }
+        }
+        self.update_frame();
+    }
+
+    /// Freezes the WRAM location at `offset` to the given value, which is written to WRAM before
+    /// each step
+    ///
+    /// Replaces an existing freeze at the same offset.
+    pub fn freeze(&mut self, offset: usize, bytes: Vec<u8>) {
+        self.freezes.retain(|f| f.offset != offset);
+        self.poke_wram(offset, &bytes);
+        self.freezes.push(Freeze {
+            offset: offset,
+            bytes: bytes,
+        });
+        self.update_frame();
+    }
+
+    /// Removes the freeze at the given WRAM offset
+    pub fn unfreeze(&mut self, offset: usize) {
+        self.freezes.retain(|f| f.offset != offset);
+        self.update_frame();
+    }
+
+    pub fn add_cheat(&mut self, cheat: Cheat) -> io::Result<()> {
+        self.cheats.push(cheat);
+        self.cheats_changed()
+    }
+
+    pub fn remove_cheat(&mut self, index: usize) -> io::Result<()> {
+        if index < self.cheats.len() {
+            self.cheats.remove(index);
+        }
+        self.cheats_changed()
+    }
+
+    /// Enables a disabled cheat and vice versa
+    pub fn toggle_cheat(&mut self, index: usize) -> io::Result<()> {
+        if let Some(cheat) = self.cheats.get_mut(index) {
+            cheat.enabled = !cheat.enabled;
+        }
+        self.cheats_changed()
+    }
+
+    /// Updates the view without changing the emulator state
+    pub fn refresh(&mut self) {
         self.update_frame();
     }
 
@@ -83,12 +626,346 @@ impl Model {
         save
     }
 
+    /// Applies the ROM patches of the cheat list, saves it next to the ROM and updates the view
+    fn cheats_changed(&mut self) -> io::Result<()> {
+        let patched = self.patched_rom();
+        if patched != self.rom_data {
+            // Swap in the patched ROM without resetting the console
+            let save = self.create_save_state();
+            self.snes = Snes::new(Rom::from_bytes(&patched).expect("patched ROM failed to load"));
+            self.snes.restore_save_state(SaveStateFormat::Custom, &mut &*save).unwrap();
+            self.rom_data = patched;
+        }
+        self.apply_ram_cheats();
+        self.update_frame();
+
+        match self.rom_path {
+            Some(ref path) => cheat::save(&cheats_path(path), &self.cheats),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the loaded ROM image with all enabled ROM cheats applied
+    fn patched_rom(&self) -> Vec<u8> {
+        apply_rom_cheats(&self.clean_rom, &self.cheats, self.map_mode)
+    }
+
+    /// Writes the values of all enabled WRAM cheats
+    fn apply_ram_cheats(&mut self) {
+        let wram = &mut self.snes.peripherals_mut().wram[..];
+        for cheat in self.cheats.iter().filter(|c| c.enabled) {
+            if let Some(offset) = memmap::wram_offset(cheat.addr) {
+                wram[offset] = cheat.value;
+            }
+        }
+    }
+
+    /// Prepares for running emulation after a stop
+    fn begin_step(&mut self) {
+        self.stop_reason = None;
+        {
+            let wram = &mut self.snes.peripherals_mut().wram[..];
+            for freeze in &self.freezes {
+                write_wrapping(wram, freeze.offset, &freeze.bytes);
+            }
+        }
+        self.apply_ram_cheats();
+        // The mask isn't part of the emulator state, so it's lost when the ROM is swapped
+        let mask = self.voice_mask();
+        self.snes.peripherals_mut().apu.dsp.set_voice_mask(mask);
+        self.prev_memory = Some(MemoryDump::new(self.snes.peripherals()));
+    }
+
+    /// Voices audible in the audio output
+    fn voice_mask(&self) -> u8 {
+        if self.soloed_voices != 0 { self.soloed_voices } else { !self.muted_voices }
+    }
+
+    /// Takes the audio generated by the DSP since the last call: the mixed output and the output of
+    /// each voice
+    fn take_audio(&mut self) -> (Vec<(i16, i16)>, Vec<[(i16, i16); 8]>) {
+        let dsp = &mut self.snes.peripherals_mut().apu.dsp;
+        (dsp.take_samples(), dsp.take_voice_samples())
+    }
+
+    /// Writes the audio generated since the last call to the recording, if any
+    ///
+    /// Write errors stop the recording. They're shown by the view with the next model data, since
+    /// the model is borrowed here.
+    fn record_audio(&mut self) {
+        let (mix, voices) = self.take_audio();
+        let failed = match self.recorder {
+            Some(ref mut recorder) => recorder.write(&mix, &voices).err(),
+            None => None,
+        };
+        if let Some(e) = failed {
+            if let Some(recorder) = self.recorder.take() {
+                let _ = recorder.finish();
+            }
+            self.recording_error = Some(e.to_string());
+        }
+    }
+
+    /// Writes bytes to WRAM, wrapping around at the end
+    fn poke_wram(&mut self, offset: usize, bytes: &[u8]) {
+        write_wrapping(&mut self.snes.peripherals_mut().wram[..], offset, bytes);
+    }
+
+    /// Executes a single CPU instruction (along with everything else happening in the meantime)
+    ///
+    /// Returns `true` if the last visible line of a frame was completed, which is where
+    /// `Snes::render_frame` stops. WRAM cheats are reapplied after each frame.
+    fn exec_instruction(&mut self) -> bool {
+        let v_before = self.snes.peripherals().ppu.v_counter();
+        if self.per_instruction() {
+            let instr = self.current_instr();
+            let before = CpuState::new(self.snes.cpu());
+            let accesses = instr.accesses(&before, |addr| self.peek(addr));
+            // The DMA registers are modified by the transfer, so save them before it starts
+            let dma = if self.log_cdl && mmio::writes_reg(&accesses, 0x420b) {
+                Some(DmaState::new(self.snes.peripherals()))
+            } else {
+                None
+            };
+
+            self.snes.step_instruction();
+
+            let after = CpuState::new(self.snes.cpu());
+            if self.track_calls {
+                let interrupt = self.interrupt_taken(&instr, &after).map(|event| event.kind);
+                let snes = &self.snes;
+                let (rom_data, map_mode) = (&self.rom_data, self.map_mode);
+                self.calls.update(&instr, &before, &after, interrupt,
+                    |addr| peek(snes, rom_data, map_mode, addr));
+            }
+            if self.log_cdl {
+                self.log_code_data(&instr, &before, &after, &accesses, dma.as_ref());
+            }
+        } else {
+            self.snes.step_instruction();
+        }
+        self.resume_addr = None;
+        let v_after = self.snes.peripherals().ppu.v_counter();
+        let frame_done = v_before < VISIBLE_LINES && (v_after >= VISIBLE_LINES || v_after < v_before);
+        if frame_done {
+            self.apply_ram_cheats();
+        }
+        frame_done
+    }
+
+    /// Whether instructions have to be executed one at a time, to track calls or log code and data
+    fn per_instruction(&self) -> bool {
+        self.track_calls || self.log_cdl
+    }
+
+    /// Records the execution of `instr` in the code/data log
+    ///
+    /// `dma` is the DMA configuration before the instruction, if it started a DMA transfer.
+    fn log_code_data(&mut self,
+                     instr: &Instr,
+                     before: &CpuState,
+                     after: &CpuState,
+                     accesses: &[MemAccess],
+                     dma: Option<&DmaState>) {
+        let (map_mode, len) = (self.map_mode, self.rom_data.len());
+        let rom_offset = |addr: u32| map_mode.rom_offset(addr, len);
+        self.cdl.log_instr(instr, before, after.pc24(), &rom_offset);
+        self.cdl.log_accesses(accesses, &rom_offset);
+        if let Some(dma) = dma {
+            let mut log = Vec::new();
+            mmio::log_accesses(&mut log, instr, accesses, before, after, 0, 0);
+            let mdmaen = log.iter().filter(|access| access.write && access.reg == 0x420b)
+                                   .filter_map(|access| access.value)
+                                   .next();
+            if let Some(mdmaen) = mdmaen {
+                self.cdl.log_dma(dma, mdmaen, &rom_offset);
+            }
+        }
+    }
+
+    /// Executes instructions until `stop` returns `true` or a breakpoint is hit
+    ///
+    /// `stop` is called after each instruction, and is passed the instruction that was just
+    /// executed. Gives up after `RUN_LIMIT` frames and returns `fail_msg` as the error. The view is
+    /// updated in all cases.
+    fn run_until<F>(&mut self, mut stop: F, fail_msg: &str) -> Result<(), String>
+        where F: FnMut(&Model, &Instr) -> bool {
+        self.begin_step();
+        let mut frames = 0;
+        while frames < RUN_LIMIT {
+            let instr = self.current_instr();
+            if self.check_breakpoints(&instr) {
+                self.update_frame();
+                return Ok(());
+            }
+
+            if self.exec_instruction() {
+                frames += 1;
+            }
+            if stop(self, &instr) {
+                self.update_frame();
+                return Ok(());
+            }
+        }
+
+        self.update_frame();
+        Err(fail_msg.to_string())
+    }
+
+    /// Checks whether executing `instr` (at the program counter) would hit a breakpoint
+    ///
+    /// Updates the hit counters of all matching breakpoints. If one of them halts emulation, the
+    /// stop reason is recorded and the view is told to bring up the relevant tool.
+    ///
+    /// Breakpoints are not checked for the instruction emulation was last halted at, so resuming
+    /// doesn't immediately halt again.
+    fn check_breakpoints(&mut self, instr: &Instr) -> bool {
+        if self.resume_addr == Some(instr.addr) {
+            return false;
+        }
+
+        let cpu = CpuState::new(self.snes.cpu());
+        let accesses = instr.accesses(&cpu, |addr| self.peek(addr));
+
+        let mut halt = None;
+        for (i, bp) in self.breakpoints.iter_mut().enumerate() {
+            if !bp.matches(instr.addr, &accesses) {
+                continue;
+            }
+            if let Some(ref cond) = bp.condition {
+                // `self` is already borrowed, so we can't use `peek` here
+                let snes = &self.snes;
+                let rom_data = &self.rom_data;
+                let map_mode = self.map_mode;
+                if !cond.eval(&cpu, |addr| peek(snes, rom_data, map_mode, addr)) {
+                    continue;
+                }
+            }
+
+            bp.hits += 1;
+            if bp.hits >= bp.hit_count && halt.is_none() {
+                halt = Some((i, bp.kind));
+            }
+        }
+
+        match halt {
+            Some((i, kind)) => {
+                self.stop_reason = Some(format!("Breakpoint #{} ({} {})", i,
+                    kind.name(), self.breakpoints[i].range_string()));
+                self.resume_addr = Some(instr.addr);
+                self.view().focus_tool(if kind == Kind::Exec { "Disassembly" } else { "Breakpoints" });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reads a byte from the 24-bit address space, without side effects
+    ///
+    /// Only ROM, WRAM and cartridge RAM are accessible, everything else reads as 0.
+    fn peek(&self, addr: u32) -> u8 {
+        peek(&self.snes, &self.rom_data, self.map_mode, addr)
+    }
+
+    fn rom_title(&self) -> &str {
+        self.snes.peripherals().rom.get_title().unwrap_or("<none>")
+    }
+
+    /// Path of the symbol file to load with the current ROM
+    ///
+    /// This is the file last loaded for the ROM, or a symbol file with the same name as the ROM.
+    fn associated_symbols(&self) -> Option<PathBuf> {
+        let remembered = config::rom_crc_file("symbols", self.rom_crc)
+            .and_then(|file| config::read_lines(&file))
+            .ok()
+            .and_then(|lines| lines.into_iter().next());
+        if let Some(path) = remembered {
+            return Some(PathBuf::from(path));
+        }
+
+        let rom_path = match self.rom_path {
+            Some(ref path) => path,
+            None => return None,
+        };
+        ["sym", "dbg", "lbl"].iter()
+            .map(|ext| rom_path.with_extension(ext))
+            .find(|path| path.is_file())
+    }
+
+    /// Heuristically reconstructs the active subroutine calls from the stack
+    fn scan_stack(&self) -> Vec<StackEntry> {
+        let cpu = CpuState::new(self.snes.cpu());
+        callstack::scan_stack(&cpu, |addr| self.peek(addr))
+    }
+
+    /// Current beam position as `(h, line)`
+    fn beam_pos(&self) -> (u16, u16) {
+        let ppu = &self.snes.peripherals().ppu;
+        (ppu.h_counter(), ppu.v_counter())
+    }
+
+    /// Checks whether an interrupt was taken right after executing `instr`, which left the CPU in
+    /// state `after`
+    fn interrupt_taken(&self, instr: &Instr, after: &CpuState) -> Option<TimingEvent> {
+        let timing = TimingRegs::new(self.snes.peripherals());
+        let (h, line) = self.beam_pos();
+        timeline::interrupt_entry(instr, after, &timing, line, h, |addr| self.peek(addr))
+    }
+
+    /// Decodes the instruction at the program counter
+    fn current_instr(&self) -> Instr {
+        let cpu = CpuState::new(self.snes.cpu());
+        Instr::decode(cpu.pc24(), cpu.m8(), cpu.x8(), |addr| self.peek(addr))
+    }
+
+    /// Disassembles `DISASM_LINES` instructions starting at the program counter
+    fn disassemble(&self, cpu: &CpuState) -> Vec<DisasmLine> {
+        let (mut m8, mut x8) = (cpu.m8(), cpu.x8());
+        let mut addr = cpu.pc24();
+        let mut lines = Vec::with_capacity(DISASM_LINES);
+        for _ in 0..DISASM_LINES {
+            let instr = Instr::decode(addr, m8, x8, |addr| self.peek(addr));
+            if !cpu.emulation {
+                let (m, x) = instr.update_widths(m8, x8);
+                m8 = m;
+                x8 = x;
+            }
+            lines.push(DisasmLine {
+                addr: addr,
+                bytes: instr.bytes_string(),
+                text: instr.to_string(),
+                target: instr.target(),
+            });
+            addr = instr.next_addr();
+        }
+        lines
+    }
+
     /// Emulates one frame and renders the result on the view
     ///
     /// Does nothing if ROM is unset
     fn update_frame(&mut self) {
+        self.record_audio();
+
+        // The CPU state is reported as it is at the stop, not after the rendered frame
+        let cpu = CpuState::new(self.snes.cpu());
+        let dma = DmaState::new(self.snes.peripherals());
+        let apu = ApuState::new(&self.snes.peripherals().apu);
+        let disasm = self.disassemble(&cpu);
+        let memory = MemoryDump::new(self.snes.peripherals());
+        let (calls, call_source) = match (self.track_calls, self.calls_from_reset) {
+            (true, true) => (self.calls.entries().to_vec(), CallSource::TrackedSinceReset),
+            (true, false) => (self.calls.entries().to_vec(), CallSource::TrackedSinceEnable),
+            (false, _) => (self.scan_stack(), CallSource::Scanned),
+        };
+
         // Create a save state, render frame, restore save state
         let save = self.create_save_state();
+        if self.view().wants_capture() && save != self.capture_state {
+            self.capture = self.capture_frame();
+            self.snes.restore_save_state(SaveStateFormat::Custom, &mut &*save).unwrap();
+            self.capture_state = save.clone();
+        }
 
         let mut framebuf = FrameBuf::default();
         self.snes.render_frame(|fb| {
@@ -106,21 +983,250 @@ impl Model {
         self.view().update_model_data(&ModelData {
             sprites: &sprites,
             ppu: &self.snes.peripherals().ppu,
+            cpu: &cpu,
+            dma: &dma,
+            apu: &apu,
+            capture: &self.capture,
+            disasm: &disasm,
+            breakpoints: &self.breakpoints,
+            memory: &memory,
+            prev_memory: self.prev_memory.as_ref(),
+            rom: &self.rom_data,
+            map_mode: self.map_mode,
+            freezes: &self.freezes,
+            cheats: &self.cheats,
+            profile: self.profile.as_ref(),
+            calls: &calls,
+            call_source: call_source,
+            symbols: &self.symbols,
+            cdl: &self.cdl,
+            cdl_logging: self.log_cdl,
+            symbols_path: self.symbols_path.as_ref().map(|path| &**path),
+            recording: self.recorder.as_ref().map(|recorder| RecordingState {
+                path: recorder.path(),
+                per_voice: recorder.per_voice(),
+                frames: recorder.frames(),
+            }),
+            recording_error: self.recording_error.as_ref().map(|e| &**e),
+            muted_voices: self.muted_voices,
+            soloed_voices: self.soloed_voices,
+            rom_title: self.rom_title(),
+            header: self.header.as_ref(),
+            rom_source: &self.rom_source,
         });
         self.view().update_frame(&*framebuf);
-        self.update_info();
 
         self.snes.restore_save_state(SaveStateFormat::Custom, &mut reader).unwrap();
+        // Discard the audio of the preview frame
+        self.take_audio();
+        self.update_info(&cpu);
     }
 
-    fn update_info(&self) {
-        let rom_name = self.snes.peripherals().rom.get_title().unwrap_or("<none>");
-        let info = format!("\
+    /// Emulates a whole frame instruction by instruction, starting at the stop, recording
+    /// per-scanline state and timing events along the way
+    ///
+    /// Per-scanline state is only recorded up to the end of the visible part of the frame, which
+    /// covers the same scanlines as the frame rendered by `update_frame`. The emulator state is not
+    /// restored.
+    fn capture_frame(&mut self) -> FrameCapture {
+        let mut hdma = HdmaRecorder::new();
+        let mut dma_before = DmaState::new(self.snes.peripherals());
+        let mut ppu = vec![None; VISIBLE_LINES as usize];
+        let mut mmio_log = Vec::new();
+        let mut events = Vec::new();
+        let start_line = self.snes.peripherals().ppu.v_counter();
+        let start_h = self.snes.peripherals().ppu.h_counter();
+        let mut last_line = start_line;
+        let mut wrapped = false;
+        let mut visible_done = false;
+        loop {
+            let instr = self.current_instr();
+            let before = CpuState::new(self.snes.cpu());
+            let accesses = instr.accesses(&before, |addr| self.peek(addr));
+            let h = self.snes.peripherals().ppu.h_counter();
+
+            self.snes.step_instruction();
+
+            let after = CpuState::new(self.snes.cpu());
+            let logged = mmio_log.len();
+            mmio::log_accesses(&mut mmio_log, &instr, &accesses, &before, &after, last_line, h);
+            for access in &mmio_log[logged..] {
+                if access.write && access.reg == 0x420b {
+                    events.push(TimingEvent {
+                        kind: EventKind::Dma,
+                        line: access.line,
+                        h: access.h,
+                        detail: access.value.map_or(String::new(), |v| format!("MDMAEN = ${:02X}", v)),
+                    });
+                }
+            }
+
+            let line = self.snes.peripherals().ppu.v_counter();
+            let h_after = self.snes.peripherals().ppu.h_counter();
+            let timing = TimingRegs::new(self.snes.peripherals());
+            if let Some(event) = self.interrupt_taken(&instr, &after) {
+                events.push(event);
+            }
+
+            let dma = DmaState::new(self.snes.peripherals());
+            if line < last_line {
+                // HDMA tables are (re)started at the beginning of each frame
+                hdma.init();
+                if dma.hdmaen != 0 {
+                    events.push(TimingEvent {
+                        kind: EventKind::HdmaInit,
+                        line: 0,
+                        h: 0,
+                        detail: format!("HDMAEN = ${:02X}", dma.hdmaen),
+                    });
+                }
+            } else if !visible_done {
+                let cpu_writes = mmio_log[logged..].iter()
+                    .filter(|access| access.write && access.reg >= 0x4300 && access.reg < 0x4380)
+                    .fold(0u8, |mask, access| mask | 1 << ((access.reg >> 4) & 7));
+                // The lines whose HDMA dot was passed during the instruction
+                let first = if h < timeline::HDMA_DOT { last_line } else { last_line + 1 };
+                let last = if h_after >= timeline::HDMA_DOT { line } else { line.saturating_sub(1) };
+                let snes = &self.snes;
+                let (rom_data, map_mode) = (&self.rom_data, self.map_mode);
+                hdma.record(first, last.max(first), &dma_before, &dma, cpu_writes,
+                    |addr| peek(snes, rom_data, map_mode, addr));
+            }
+            dma_before = dma;
+
+            if line == last_line {
+                continue;
+            }
+
+            if line < last_line {
+                wrapped = true;
+                events.push(TimingEvent {
+                    kind: EventKind::VblankEnd,
+                    line: line,
+                    h: 0,
+                    detail: String::new(),
+                });
+            }
+            if last_line < VISIBLE_LINES && line >= VISIBLE_LINES {
+                events.push(TimingEvent {
+                    kind: EventKind::VblankStart,
+                    line: VISIBLE_LINES,
+                    h: 0,
+                    detail: String::new(),
+                });
+                if timing.nmitimen & 0x01 != 0 {
+                    events.push(TimingEvent {
+                        kind: EventKind::AutoJoypad,
+                        line: VISIBLE_LINES,
+                        h: 32,
+                        detail: String::new(),
+                    });
+                }
+            }
+
+            if !visible_done {
+                if line < VISIBLE_LINES {
+                    ppu[line as usize] = Some(PpuRegValues::new(&self.snes.peripherals().ppu));
+                }
+                if last_line < VISIBLE_LINES && line >= VISIBLE_LINES {
+                    visible_done = true;
+                }
+            }
+
+            if wrapped && line >= start_line {
+                break;
+            }
+            last_line = line;
+        }
+
+        let hdma = hdma.finish();
+        for (line, writes) in hdma.lines.iter().enumerate() {
+            let count = writes.iter().filter(|w| w.is_some()).count();
+            let unknown = hdma.unknown[line];
+            if count > 0 || unknown != 0 {
+                let detail = match unknown {
+                    0 => format!("{} registers written", count),
+                    _ => format!("{} registers written, channels {} unknown", count,
+                        (0..8).filter(|ch| unknown & (1 << ch) != 0).map(|ch| ch.to_string())
+                              .collect::<Vec<_>>().join(", ")),
+                };
+                events.push(TimingEvent {
+                    kind: EventKind::Hdma,
+                    line: line as u16,
+                    h: timeline::HDMA_DOT,
+                    detail: detail,
+                });
+            }
+        }
+
+        FrameCapture {
+            hdma: hdma,
+            ppu: ppu,
+            mmio: mmio_log,
+            events: events,
+            stop_pos: (start_h, start_line),
+        }
+    }
+
+    fn update_info(&self, cpu: &CpuState) {
+        let rom_name = self.rom_title();
+        let (h, v) = (self.snes.peripherals().ppu.h_counter(), self.snes.peripherals().ppu.v_counter());
+        let mut info = format!("\
             ROM name: {}\n\
             H position: {}\n\
-            V position: {}",
-            rom_name, self.snes.peripherals().ppu.h_counter(), self.snes.peripherals().ppu.v_counter());
+            V position: {}\n\
+            Beam: {}\n\
+            PC: ${:06X}",
+            rom_name, h, v, timeline::beam_region(h, v, VISIBLE_LINES), cpu.pc24());
+        if let Some(ref reason) = self.stop_reason {
+            info.push_str(&format!("\nStopped at {}", reason));
+        }
 
         self.view().update_info(&info);
     }
 }
+
+/// Reads a byte from the 24-bit address space, without side effects
+///
+/// Only ROM, WRAM and cartridge RAM are accessible, everything else reads as 0.
+fn peek(snes: &Snes, rom_data: &[u8], map_mode: MapMode, addr: u32) -> u8 {
+    let sram = snes.peripherals().rom.ram();
+    if let Some(offset) = memmap::wram_offset(addr) {
+        snes.peripherals().wram[offset]
+    } else if let Some(offset) = map_mode.sram_offset(addr, sram.len()) {
+        sram[offset]
+    } else if let Some(offset) = map_mode.rom_offset(addr, rom_data.len()) {
+        rom_data[offset]
+    } else {
+        0
+    }
+}
+
+/// Returns a copy of `rom` with all enabled ROM cheats applied
+fn apply_rom_cheats(rom: &[u8], cheats: &[Cheat], map_mode: MapMode) -> Vec<u8> {
+    let mut rom = rom.to_vec();
+    for cheat in cheats.iter().filter(|c| c.enabled) {
+        if let Some(offset) = map_mode.rom_offset(cheat.addr, rom.len()) {
+            rom[offset] = cheat.value;
+        }
+    }
+    rom
+}
+
+/// Path of the cheat list belonging to the ROM at `rom_path`
+fn cheats_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("cht")
+}
+
+/// Path of the code/data log belonging to the ROM at `rom_path`
+fn cdl_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("cdl")
+}
+
+/// Writes `bytes` to `mem` at `offset`, wrapping around at the end
+fn write_wrapping(mem: &mut [u8], offset: usize, bytes: &[u8]) {
+    let len = mem.len();
+    for (i, &b) in bytes.iter().enumerate() {
+        mem[(offset + i) % len] = b;
+    }
+}
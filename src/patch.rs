@@ -0,0 +1,443 @@
+//! Application of IPS, UPS and BPS patches
+//!
+//! UPS and BPS patches contain CRC32 checksums of the source, the result and the patch itself,
+//! which are all verified.
+
+use std::fmt;
+use std::io;
+
+/// Supported patch formats
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PatchFormat {
+    Ips,
+    Ups,
+    Bps,
+}
+
+impl fmt::Display for PatchFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            PatchFormat::Ips => "IPS",
+            PatchFormat::Ups => "UPS",
+            PatchFormat::Bps => "BPS",
+        })
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Calculates the CRC32 (as used by zip) of `data`
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Detects the format of a patch by its signature
+pub fn detect(patch: &[u8]) -> Option<PatchFormat> {
+    if patch.starts_with(b"PATCH") {
+        Some(PatchFormat::Ips)
+    } else if patch.starts_with(b"UPS1") {
+        Some(PatchFormat::Ups)
+    } else if patch.starts_with(b"BPS1") {
+        Some(PatchFormat::Bps)
+    } else {
+        None
+    }
+}
+
+/// Applies `patch` to `rom`, returning the patched image and the patch format
+pub fn apply(rom: &[u8], patch: &[u8]) -> io::Result<(Vec<u8>, PatchFormat)> {
+    let format = match detect(patch) {
+        Some(format) => format,
+        None => return Err(invalid("unknown patch format (expected IPS, UPS or BPS)")),
+    };
+    let patched = match format {
+        PatchFormat::Ips => apply_ips(rom, patch),
+        PatchFormat::Ups => apply_ups(rom, patch),
+        PatchFormat::Bps => apply_bps(rom, patch),
+    };
+    patched.map(|patched| (patched, format))
+}
+
+/// Reads patch data, failing with an error if the patch is truncated
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> io::Result<u8> {
+        match self.data.get(self.pos) {
+            Some(&b) => {
+                self.pos += 1;
+                Ok(b)
+            }
+            None => Err(invalid("patch is truncated")),
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.pos + len > self.data.len() {
+            return Err(invalid("patch is truncated"));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Reads a big-endian number of `len` bytes
+    fn be(&mut self, len: usize) -> io::Result<usize> {
+        let bytes = try!(self.bytes(len));
+        Ok(bytes.iter().fold(0, |value, &b| value << 8 | b as usize))
+    }
+
+    /// Reads a variable-length number as used by UPS and BPS
+    fn varint(&mut self) -> io::Result<usize> {
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let b = try!(self.byte());
+            value = value.wrapping_add((b & 0x7f) as usize * shift);
+            if b & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift <<= 7;
+            value = value.wrapping_add(shift);
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = rom.to_vec();
+    let mut reader = Reader { data: patch, pos: 5 };
+    loop {
+        if reader.data[reader.pos..].starts_with(b"EOF") {
+            reader.pos += 3;
+            break;
+        }
+        let offset = try!(reader.be(3));
+        let len = try!(reader.be(2));
+        let (len, rle) = if len == 0 {
+            (try!(reader.be(2)), Some(try!(reader.byte())))
+        } else {
+            (len, None)
+        };
+
+        if out.len() < offset + len {
+            out.resize(offset + len, 0);
+        }
+        match rle {
+            Some(value) => for b in &mut out[offset..offset + len] { *b = value; },
+            None => out[offset..offset + len].copy_from_slice(try!(reader.bytes(len))),
+        }
+    }
+
+    // Optional truncation extension
+    if patch.len() - reader.pos == 3 {
+        let len = try!(reader.be(3));
+        out.truncate(len);
+    }
+    Ok(out)
+}
+
+/// Checks the CRC32 footer of a UPS or BPS patch
+fn check_footer(source: &[u8], target: &[u8], patch: &[u8]) -> io::Result<()> {
+    let crc = |offset: usize| {
+        let b = &patch[patch.len() - 12 + offset..];
+        b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24
+    };
+    if crc32(&patch[..patch.len() - 4]) != crc(8) {
+        return Err(invalid("patch file is corrupted (CRC mismatch)"));
+    }
+    if crc32(source) != crc(0) {
+        return Err(invalid("patch was made for a different ROM (CRC mismatch)"));
+    }
+    if crc32(target) != crc(4) {
+        return Err(invalid("patched ROM is corrupted (CRC mismatch)"));
+    }
+    Ok(())
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> io::Result<Vec<u8>> {
+    if patch.len() < 16 {
+        return Err(invalid("patch is truncated"));
+    }
+    let end = patch.len() - 12;
+    let mut reader = Reader { data: &patch[..end], pos: 4 };
+    let source_size = try!(reader.varint());
+    let target_size = try!(reader.varint());
+    if source_size != rom.len() {
+        return Err(invalid(&format!("patch was made for a ROM of {} bytes, but the ROM has {} bytes",
+            source_size, rom.len())));
+    }
+
+    let mut out = rom.to_vec();
+    out.resize(target_size, 0);
+    let mut pos = 0;
+    while reader.pos < end {
+        pos += try!(reader.varint());
+        loop {
+            let x = try!(reader.byte());
+            if pos < out.len() {
+                out[pos] ^= x;
+            }
+            pos += 1;
+            if x == 0 {
+                break;
+            }
+        }
+    }
+
+    try!(check_footer(rom, &out, patch));
+    Ok(out)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> io::Result<Vec<u8>> {
+    if patch.len() < 16 {
+        return Err(invalid("patch is truncated"));
+    }
+    let end = patch.len() - 12;
+    let mut reader = Reader { data: &patch[..end], pos: 4 };
+    let source_size = try!(reader.varint());
+    let target_size = try!(reader.varint());
+    let metadata_size = try!(reader.varint());
+    try!(reader.bytes(metadata_size));
+    if source_size != rom.len() {
+        return Err(invalid(&format!("patch was made for a ROM of {} bytes, but the ROM has {} bytes",
+            source_size, rom.len())));
+    }
+
+    let mut out = Vec::with_capacity(target_size);
+    let mut source_offset = 0isize;
+    let mut target_offset = 0isize;
+    let out_of_range = || invalid("patch reads outside of the ROM");
+    while reader.pos < end {
+        let data = try!(reader.varint());
+        let len = (data >> 2) + 1;
+        match data & 3 {
+            // Source read
+            0 => {
+                let start = out.len();
+                if start + len > rom.len() {
+                    return Err(out_of_range());
+                }
+                out.extend_from_slice(&rom[start..start + len]);
+            }
+            // Target read
+            1 => out.extend_from_slice(try!(reader.bytes(len))),
+            // Source copy
+            2 => {
+                let d = try!(reader.varint());
+                source_offset += if d & 1 != 0 { -((d >> 1) as isize) } else { (d >> 1) as isize };
+                if source_offset < 0 || source_offset as usize + len > rom.len() {
+                    return Err(out_of_range());
+                }
+                out.extend_from_slice(&rom[source_offset as usize..source_offset as usize + len]);
+                source_offset += len as isize;
+            }
+            // Target copy, byte by byte since source and destination can overlap
+            _ => {
+                let d = try!(reader.varint());
+                target_offset += if d & 1 != 0 { -((d >> 1) as isize) } else { (d >> 1) as isize };
+                if target_offset < 0 || target_offset as usize >= out.len() {
+                    return Err(out_of_range());
+                }
+                for _ in 0..len {
+                    let b = out[target_offset as usize];
+                    out.push(b);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if out.len() != target_size {
+        return Err(invalid("patch produced a ROM of the wrong size"));
+    }
+    try!(check_footer(rom, &out, patch));
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a number the way `Reader::varint` decodes it
+    fn varint(out: &mut Vec<u8>, mut value: usize) {
+        loop {
+            let x = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(0x80 | x);
+                return;
+            }
+            out.push(x);
+            value -= 1;
+        }
+    }
+
+    /// Appends the UPS/BPS footer with the CRCs of source, target and patch
+    fn footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        for &crc in &[crc32(source), crc32(target)] {
+            patch.extend_from_slice(&[crc as u8, (crc >> 8) as u8, (crc >> 16) as u8, (crc >> 24) as u8]);
+        }
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&[crc as u8, (crc >> 8) as u8, (crc >> 16) as u8, (crc >> 24) as u8]);
+        patch
+    }
+
+    fn rom() -> Vec<u8> {
+        (0..8).collect()
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn varint_roundtrip() {
+        for &value in &[0, 1, 0x7f, 0x80, 0x407f, 0x4080, 0x123456] {
+            let mut data = Vec::new();
+            varint(&mut data, value);
+            let mut reader = Reader { data: &data, pos: 0 };
+            assert_eq!(reader.varint().unwrap(), value);
+            assert_eq!(reader.pos, data.len());
+        }
+    }
+
+    #[test]
+    fn ips() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x02, 0xaa, 0xbb]);
+        // Records may write past the end of the ROM
+        patch.extend_from_slice(&[0x00, 0x00, 0x09, 0x00, 0x01, 0xcc]);
+        patch.extend_from_slice(b"EOF");
+
+        let (patched, format) = apply(&rom(), &patch).unwrap();
+        assert_eq!(format, PatchFormat::Ips);
+        assert_eq!(patched, [0, 1, 0xaa, 0xbb, 4, 5, 6, 7, 0, 0xcc]);
+    }
+
+    #[test]
+    fn ips_rle() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04, 0xee]);
+        patch.extend_from_slice(b"EOF");
+
+        let (patched, _) = apply(&rom(), &patch).unwrap();
+        assert_eq!(patched, [0, 1, 2, 0xee, 0xee, 0xee, 0xee, 7]);
+    }
+
+    #[test]
+    fn ips_truncation() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x01, 0xff]);
+        patch.extend_from_slice(b"EOF");
+        patch.extend_from_slice(&[0x00, 0x00, 0x04]);
+
+        let (patched, _) = apply(&rom(), &patch).unwrap();
+        assert_eq!(patched, [0xff, 1, 2, 3]);
+    }
+
+    #[test]
+    fn ips_truncated_patch() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x04, 0xaa]);
+        assert!(apply(&rom(), &patch).is_err());
+    }
+
+    #[test]
+    fn unknown_format() {
+        assert!(apply(&rom(), b"NOT A PATCH").is_err());
+    }
+
+    fn ups_patch() -> (Vec<u8>, Vec<u8>) {
+        let target = vec![0, 1, 0x20, 0x30, 4, 5, 6, 7, 0x08];
+        let mut patch = b"UPS1".to_vec();
+        varint(&mut patch, 8);
+        varint(&mut patch, 9);
+        // Skip 2 bytes, then XOR until the terminating zero
+        varint(&mut patch, 2);
+        patch.extend_from_slice(&[0x22, 0x33, 0x00]);
+        // The appended byte is XORed with zero
+        varint(&mut patch, 3);
+        patch.extend_from_slice(&[0x08, 0x00]);
+        (footer(patch, &rom(), &target), target)
+    }
+
+    #[test]
+    fn ups() {
+        let (patch, target) = ups_patch();
+        let (patched, format) = apply(&rom(), &patch).unwrap();
+        assert_eq!(format, PatchFormat::Ups);
+        assert_eq!(patched, target);
+    }
+
+    #[test]
+    fn ups_wrong_source() {
+        let (patch, _) = ups_patch();
+        let other = vec![0xff; 8];
+        let err = apply(&other, &patch).unwrap_err();
+        assert!(err.to_string().contains("different ROM"));
+    }
+
+    #[test]
+    fn ups_corrupted_patch() {
+        let (mut patch, _) = ups_patch();
+        patch[8] ^= 0x01;
+        let err = apply(&rom(), &patch).unwrap_err();
+        assert!(err.to_string().contains("patch file is corrupted"));
+    }
+
+    fn bps_patch() -> (Vec<u8>, Vec<u8>) {
+        let target = vec![0, 1, 0xaa, 0xbb, 6, 7, 0, 1];
+        let mut patch = b"BPS1".to_vec();
+        varint(&mut patch, 8);
+        varint(&mut patch, 8);
+        varint(&mut patch, 0);
+        // Source read of 2 bytes
+        varint(&mut patch, 1 << 2);
+        // Target read of 2 bytes
+        varint(&mut patch, (1 << 2) | 1);
+        patch.extend_from_slice(&[0xaa, 0xbb]);
+        // Source copy of 2 bytes from offset 6
+        varint(&mut patch, (1 << 2) | 2);
+        varint(&mut patch, 6 << 1);
+        // Target copy of 2 bytes from offset 0
+        varint(&mut patch, (1 << 2) | 3);
+        varint(&mut patch, 0);
+        (footer(patch, &rom(), &target), target)
+    }
+
+    #[test]
+    fn bps() {
+        let (patch, target) = bps_patch();
+        let (patched, format) = apply(&rom(), &patch).unwrap();
+        assert_eq!(format, PatchFormat::Bps);
+        assert_eq!(patched, target);
+    }
+
+    #[test]
+    fn bps_wrong_source() {
+        let (patch, _) = bps_patch();
+        let mut other = rom();
+        other[7] = 0xff;
+        let err = apply(&other, &patch).unwrap_err();
+        assert!(err.to_string().contains("different ROM"));
+    }
+
+    #[test]
+    fn bps_wrong_source_size() {
+        let (patch, _) = bps_patch();
+        assert!(apply(&[0; 4], &patch).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! CPU profiling: where the executed cycles go
+
+use callstack::CallStack;
+use data::CpuState;
+use disasm::Instr;
+use timeline::EventKind;
+
+use std::collections::HashMap;
+
+/// Master clock cycles per dot of the beam
+pub const CYCLES_PER_DOT: u32 = 4;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct AddrStats {
+    /// Master cycles spent executing the instruction at the address
+    pub cycles: u64,
+    /// Number of times the instruction was executed
+    pub count: u64,
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SubStats {
+    /// Master cycles spent in the subroutine, including everything it calls
+    pub cycles: u64,
+    /// Number of calls (or interrupts, for interrupt handlers)
+    pub calls: u64,
+}
+
+/// Time spent in a single frame, measured from NMI to NMI
+#[derive(Clone, Copy, Debug)]
+pub struct FrameTime {
+    /// Master cycles until the wait loop was reached, or `None` if it wasn't reached (the game
+    /// lagged)
+    pub busy: Option<u64>,
+    /// Master cycles of the whole frame
+    pub total: u64,
+}
+
+/// Results of a profiling run
+#[derive(Clone, Default, Debug)]
+pub struct Profile {
+    /// Number of profiled frames
+    pub frames: u32,
+    /// Total number of master cycles
+    pub cycles: u64,
+    /// Statistics per instruction address, most expensive first
+    pub addrs: Vec<(u32, AddrStats)>,
+    /// Statistics per subroutine entry point, most expensive first
+    pub subs: Vec<(u32, SubStats)>,
+    /// Time spent in each frame that was completely profiled
+    pub frame_times: Vec<FrameTime>,
+    /// Address most often interrupted by the NMI, which should be the game's wait loop
+    pub wait_loop: Option<u32>,
+}
+
+/// Collects profiling data one instruction at a time
+pub struct Profiler {
+    addrs: HashMap<u32, AddrStats>,
+    subs: HashMap<u32, SubStats>,
+    frame_times: Vec<FrameTime>,
+    calls: CallStack,
+    /// Master cycles since the start of profiling
+    now: u64,
+    /// Start of the current frame (the last NMI), if an NMI was seen yet
+    frame_start: Option<u64>,
+    /// Time of the first execution and number of executions of each address in the current frame
+    visits: HashMap<u32, (u64, u32)>,
+    /// How often the NMI interrupted each address
+    nmi_addrs: HashMap<u32, u32>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            addrs: HashMap::new(),
+            subs: HashMap::new(),
+            frame_times: Vec::new(),
+            calls: CallStack::new(),
+            now: 0,
+            frame_start: None,
+            visits: HashMap::new(),
+            nmi_addrs: HashMap::new(),
+        }
+    }
+
+    /// Records the execution of `instr`, which took `cycles` master cycles
+    ///
+    /// `before`, `after` and `interrupt` are passed on to `CallStack::update`.
+    pub fn record<F>(&mut self,
+                     instr: &Instr,
+                     before: &CpuState,
+                     after: &CpuState,
+                     cycles: u32,
+                     interrupt: Option<EventKind>,
+                     peek: F) where F: FnMut(u32) -> u8 {
+        let cycles = cycles as u64;
+        {
+            let stats = self.addrs.entry(instr.addr).or_insert_with(AddrStats::default);
+            stats.cycles += cycles;
+            stats.count += 1;
+        }
+
+        // Every subroutine on the call stack is running. Recursive calls are counted once.
+        let mut active = self.calls.entries().iter().map(|entry| entry.target).collect::<Vec<_>>();
+        active.sort();
+        active.dedup();
+        for target in active {
+            self.subs.entry(target).or_insert_with(SubStats::default).cycles += cycles;
+        }
+
+        {
+            let visit = self.visits.entry(instr.addr).or_insert((self.now, 0));
+            visit.1 += 1;
+        }
+        self.now += cycles;
+
+        let depth = self.calls.entries().len();
+        self.calls.update(instr, before, after, interrupt, peek);
+        if self.calls.entries().len() > depth {
+            for entry in &self.calls.entries()[depth..] {
+                self.subs.entry(entry.target).or_insert_with(SubStats::default).calls += 1;
+            }
+        }
+
+        if interrupt == Some(EventKind::Nmi) {
+            let interrupted = self.calls.entries().last().map_or(0, |entry| entry.return_addr);
+            *self.nmi_addrs.entry(interrupted).or_insert(0) += 1;
+            if let Some(start) = self.frame_start {
+                // The main loop is done once it spins in the code the NMI interrupts. If that code
+                // didn't run repeatedly, the NMI hit in the middle of the game logic.
+                let busy = match self.visits.get(&interrupted) {
+                    Some(&(first, count)) if count >= 2 => Some(first - start),
+                    _ => None,
+                };
+                self.frame_times.push(FrameTime {
+                    busy: busy,
+                    total: self.now - start,
+                });
+            }
+            self.frame_start = Some(self.now);
+            self.visits.clear();
+        }
+    }
+
+    /// Finishes profiling after `frames` frames
+    pub fn finish(self, frames: u32) -> Profile {
+        let mut addrs = self.addrs.into_iter().collect::<Vec<_>>();
+        addrs.sort_by(|a, b| (b.1.cycles, a.0).cmp(&(a.1.cycles, b.0)));
+        let mut subs = self.subs.into_iter().collect::<Vec<_>>();
+        subs.sort_by(|a, b| (b.1.cycles, a.0).cmp(&(a.1.cycles, b.0)));
+        let wait_loop = self.nmi_addrs.iter()
+            .max_by_key(|&(&addr, &count)| (count, !addr))
+            .map(|(&addr, _)| addr);
+
+        Profile {
+            frames: frames,
+            cycles: self.now,
+            addrs: addrs,
+            subs: subs,
+            frame_times: self.frame_times,
+            wait_loop: wait_loop,
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Classic RAM search: narrow down the WRAM locations holding a game variable by comparing
+//! successive snapshots
+
+/// Size of the searched values
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Width {
+    Byte,
+    Word,
+    Long,
+}
+
+impl Width {
+    pub fn bytes(&self) -> usize {
+        match *self {
+            Width::Byte => 1,
+            Width::Word => 2,
+            Width::Long => 3,
+        }
+    }
+}
+
+/// Reads a little-endian value of the given width from `mem` at `offset`
+pub fn read_value(mem: &[u8], offset: usize, width: Width, signed: bool) -> i64 {
+    let bytes = width.bytes();
+    let mut value = 0u32;
+    for i in 0..bytes {
+        value |= (mem[offset + i] as u32) << (8 * i);
+    }
+
+    if signed {
+        // Sign-extend from the highest bit of the value
+        let shift = 32 - 8 * bytes as u32;
+        ((value << shift) as i32 >> shift) as i64
+    } else {
+        value as i64
+    }
+}
+
+/// Encodes `value` as `width.bytes()` little-endian bytes
+pub fn value_bytes(value: i64, width: Width) -> Vec<u8> {
+    (0..width.bytes()).map(|i| (value >> (8 * i)) as u8).collect()
+}
+
+/// How the current value of a candidate must relate to the previous one to keep it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compare {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+    /// The current value must equal the given value
+    Value(i64),
+}
+
+pub struct RamSearch {
+    width: Width,
+    signed: bool,
+    /// WRAM contents at the last filter step
+    snapshot: Vec<u8>,
+    /// WRAM offsets that are still candidates
+    candidates: Vec<usize>,
+}
+
+impl RamSearch {
+    /// Starts a new search. Every offset is a candidate.
+    pub fn new(wram: &[u8], width: Width, signed: bool) -> Self {
+        let last = wram.len().saturating_sub(width.bytes() - 1);
+        RamSearch {
+            width: width,
+            signed: signed,
+            snapshot: wram.to_vec(),
+            candidates: (0..last).collect(),
+        }
+    }
+
+    /// Removes all candidates whose value in `wram` doesn't compare to the last snapshot as
+    /// requested, then takes a new snapshot.
+    pub fn filter(&mut self, wram: &[u8], cmp: Compare) {
+        let (width, signed) = (self.width, self.signed);
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&offset| {
+            let old = read_value(snapshot, offset, width, signed);
+            let new = read_value(wram, offset, width, signed);
+            match cmp {
+                Compare::Equal => new == old,
+                Compare::Changed => new != old,
+                Compare::Increased => new > old,
+                Compare::Decreased => new < old,
+                Compare::Value(value) => new == value,
+            }
+        });
+        self.snapshot = wram.to_vec();
+    }
+
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+
+    pub fn width(&self) -> Width {
+        self.width
+    }
+
+    pub fn signed(&self) -> bool {
+        self.signed
+    }
+
+    /// Value of the candidate at `offset` at the last snapshot
+    pub fn previous(&self, offset: usize) -> i64 {
+        read_value(&self.snapshot, offset, self.width, self.signed)
+    }
+}
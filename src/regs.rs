@@ -0,0 +1,141 @@
+//! Names of the memory-mapped I/O registers
+
+/// B-bus (`$21xx`) and CPU (`$40xx`/`$42xx`) registers, sorted by address
+static REG_NAMES: &'static [(u16, &'static str)] = &[
+    (0x2100, "INIDISP"),
+    (0x2101, "OBSEL"),
+    (0x2102, "OAMADDL"),
+    (0x2103, "OAMADDH"),
+    (0x2104, "OAMDATA"),
+    (0x2105, "BGMODE"),
+    (0x2106, "MOSAIC"),
+    (0x2107, "BG1SC"),
+    (0x2108, "BG2SC"),
+    (0x2109, "BG3SC"),
+    (0x210a, "BG4SC"),
+    (0x210b, "BG12NBA"),
+    (0x210c, "BG34NBA"),
+    (0x210d, "BG1HOFS"),
+    (0x210e, "BG1VOFS"),
+    (0x210f, "BG2HOFS"),
+    (0x2110, "BG2VOFS"),
+    (0x2111, "BG3HOFS"),
+    (0x2112, "BG3VOFS"),
+    (0x2113, "BG4HOFS"),
+    (0x2114, "BG4VOFS"),
+    (0x2115, "VMAIN"),
+    (0x2116, "VMADDL"),
+    (0x2117, "VMADDH"),
+    (0x2118, "VMDATAL"),
+    (0x2119, "VMDATAH"),
+    (0x211a, "M7SEL"),
+    (0x211b, "M7A"),
+    (0x211c, "M7B"),
+    (0x211d, "M7C"),
+    (0x211e, "M7D"),
+    (0x211f, "M7X"),
+    (0x2120, "M7Y"),
+    (0x2121, "CGADD"),
+    (0x2122, "CGDATA"),
+    (0x2123, "W12SEL"),
+    (0x2124, "W34SEL"),
+    (0x2125, "WOBJSEL"),
+    (0x2126, "WH0"),
+    (0x2127, "WH1"),
+    (0x2128, "WH2"),
+    (0x2129, "WH3"),
+    (0x212a, "WBGLOG"),
+    (0x212b, "WOBJLOG"),
+    (0x212c, "TM"),
+    (0x212d, "TS"),
+    (0x212e, "TMW"),
+    (0x212f, "TSW"),
+    (0x2130, "CGWSEL"),
+    (0x2131, "CGADSUB"),
+    (0x2132, "COLDATA"),
+    (0x2133, "SETINI"),
+    (0x2134, "MPYL"),
+    (0x2135, "MPYM"),
+    (0x2136, "MPYH"),
+    (0x2137, "SLHV"),
+    (0x2138, "OAMDATAREAD"),
+    (0x2139, "VMDATALREAD"),
+    (0x213a, "VMDATAHREAD"),
+    (0x213b, "CGDATAREAD"),
+    (0x213c, "OPHCT"),
+    (0x213d, "OPVCT"),
+    (0x213e, "STAT77"),
+    (0x213f, "STAT78"),
+    (0x2140, "APUIO0"),
+    (0x2141, "APUIO1"),
+    (0x2142, "APUIO2"),
+    (0x2143, "APUIO3"),
+    (0x2180, "WMDATA"),
+    (0x2181, "WMADDL"),
+    (0x2182, "WMADDM"),
+    (0x2183, "WMADDH"),
+    (0x4016, "JOYSER0"),
+    (0x4017, "JOYSER1"),
+    (0x4200, "NMITIMEN"),
+    (0x4201, "WRIO"),
+    (0x4202, "WRMPYA"),
+    (0x4203, "WRMPYB"),
+    (0x4204, "WRDIVL"),
+    (0x4205, "WRDIVH"),
+    (0x4206, "WRDIVB"),
+    (0x4207, "HTIMEL"),
+    (0x4208, "HTIMEH"),
+    (0x4209, "VTIMEL"),
+    (0x420a, "VTIMEH"),
+    (0x420b, "MDMAEN"),
+    (0x420c, "HDMAEN"),
+    (0x420d, "MEMSEL"),
+    (0x4210, "RDNMI"),
+    (0x4211, "TIMEUP"),
+    (0x4212, "HVBJOY"),
+    (0x4213, "RDIO"),
+    (0x4214, "RDDIVL"),
+    (0x4215, "RDDIVH"),
+    (0x4216, "RDMPYL"),
+    (0x4217, "RDMPYH"),
+    (0x4218, "JOY1L"),
+    (0x4219, "JOY1H"),
+    (0x421a, "JOY2L"),
+    (0x421b, "JOY2H"),
+    (0x421c, "JOY3L"),
+    (0x421d, "JOY3H"),
+    (0x421e, "JOY4L"),
+    (0x421f, "JOY4H"),
+];
+
+/// Names of the per-channel DMA registers (`$43x0`-`$43xA`)
+pub static DMA_REG_NAMES: [&'static str; 11] = [
+    "DMAP", "BBAD", "A1TL", "A1TH", "A1B", "DASL", "DASH", "DASB", "A2AL", "A2AH", "NLTR",
+];
+
+/// Returns the name of the I/O register at the given address in bank `$00`, like `INIDISP` or
+/// `A1T3L`
+pub fn name(addr: u16) -> Option<String> {
+    if let Ok(i) = REG_NAMES.binary_search_by(|&(a, _)| a.cmp(&addr)) {
+        return Some(REG_NAMES[i].1.to_string());
+    }
+
+    // The APU ports are mirrored up to $217F
+    if addr >= 0x2144 && addr < 0x2180 {
+        return Some(format!("APUIO{}", addr & 3));
+    }
+
+    // DMA channel registers insert the channel number before the low/high byte suffix
+    if addr & 0xff80 == 0x4300 && addr & 0x0f <= 0x0a {
+        let channel = (addr >> 4) & 7;
+        let reg = DMA_REG_NAMES[(addr & 0x0f) as usize];
+        let (base, suffix) = if reg.ends_with('L') || reg.ends_with('H') {
+            reg.split_at(reg.len() - 1)
+        } else {
+            (reg, "")
+        };
+        return Some(format!("{}{}{}", base, channel, suffix));
+    }
+
+    None
+}
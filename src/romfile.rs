@@ -0,0 +1,113 @@
+//! Loading of ROM images from files: archives, copier headers and patches
+
+use patch::{self, PatchFormat};
+
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use std::ascii::AsciiExt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Size of the header added by SMC/SWC/FIG copiers
+pub const COPIER_HEADER_LEN: usize = 512;
+
+/// Extensions of ROM files inside of archives, preferred over other files
+static ROM_EXTENSIONS: [&'static str; 5] = ["sfc", "smc", "swc", "fig", "bin"];
+
+/// Describes how a ROM image was loaded
+#[derive(Clone, Default, Debug)]
+pub struct RomSource {
+    /// Name of the file inside an archive, if the ROM was loaded from one
+    pub archive_entry: Option<String>,
+    /// Whether a copier header was removed
+    pub copier_header: bool,
+    /// Path and format of the applied patch, if any
+    pub patch: Option<(PathBuf, PatchFormat)>,
+}
+
+fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension().map_or(false, |e| e.to_string_lossy().eq_ignore_ascii_case(ext))
+}
+
+/// Reads a whole file
+fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    let mut content = vec![];
+    try!(try!(File::open(path)).read_to_end(&mut content));
+    Ok(content)
+}
+
+/// Reads the ROM from a zip archive. A file with a ROM extension is preferred, otherwise the
+/// largest file is used.
+fn read_zip(path: &Path) -> io::Result<(Vec<u8>, String)> {
+    let zip_error = |e: ::zip::result::ZipError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+    let mut archive = try!(ZipArchive::new(try!(File::open(path))).map_err(&zip_error));
+
+    let mut best = None;
+    for i in 0..archive.len() {
+        let file = try!(archive.by_index(i).map_err(&zip_error));
+        let is_rom = ROM_EXTENSIONS.iter().any(|ext| has_extension(Path::new(file.name()), ext));
+        let rank = (is_rom, file.size());
+        if best.map_or(true, |(_, best_rank)| rank > best_rank) {
+            best = Some((i, rank));
+        }
+    }
+
+    let index = match best {
+        Some((index, _)) => index,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "archive is empty")),
+    };
+    let mut file = try!(archive.by_index(index).map_err(&zip_error));
+    let mut content = vec![];
+    try!(file.read_to_end(&mut content));
+    Ok((content, file.name().to_string()))
+}
+
+/// Reads a ROM image, decompressing `.zip` and `.gz` files
+///
+/// Returns the image and the name of the file in the archive, if it was an archive.
+fn read_image(path: &Path) -> io::Result<(Vec<u8>, Option<String>)> {
+    if has_extension(path, "zip") {
+        let (content, name) = try!(read_zip(path));
+        Ok((content, Some(name)))
+    } else if has_extension(path, "gz") {
+        let mut content = vec![];
+        try!(try!(GzDecoder::new(try!(File::open(path)))).read_to_end(&mut content));
+        let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned());
+        Ok((content, name))
+    } else {
+        Ok((try!(read_file(path)), None))
+    }
+}
+
+/// Whether `rom` starts with a copier header
+///
+/// ROM sizes are multiples of 1 KiB, so 512 extra bytes are a copier header.
+pub fn has_copier_header(rom: &[u8]) -> bool {
+    rom.len() % 1024 == COPIER_HEADER_LEN
+}
+
+/// Loads the ROM at `path`, removing a copier header and applying `patch` to the result
+pub fn load(path: &Path, patch: Option<&Path>) -> io::Result<(Vec<u8>, RomSource)> {
+    let (mut rom, archive_entry) = try!(read_image(path));
+    let copier_header = has_copier_header(&rom);
+    if copier_header {
+        rom.drain(..COPIER_HEADER_LEN);
+    }
+
+    let patch = match patch {
+        Some(patch_path) => {
+            let (patched, format) = try!(patch::apply(&rom, &try!(read_file(patch_path))));
+            rom = patched;
+            Some((patch_path.to_owned(), format))
+        }
+        None => None,
+    };
+
+    Ok((rom, RomSource {
+        archive_entry: archive_entry,
+        copier_header: copier_header,
+        patch: patch,
+    }))
+}
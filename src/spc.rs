@@ -0,0 +1,65 @@
+//! Export of the APU state as an `.spc` file (version 0.30, with a text ID666 tag)
+
+use data::ApuState;
+
+use std::io::{self, Write};
+
+const SIGNATURE: &'static [u8] = b"SNES-SPC700 Sound File Data v0.30";
+
+/// Start of the hidden RAM below the IPL ROM
+const IPL_START: usize = 0xffc0;
+
+/// Default length in seconds before fading out, written to the ID666 tag
+const PLAY_SECONDS: u32 = 180;
+/// Default fade length in milliseconds
+const FADE_MILLIS: u32 = 10000;
+
+/// Writes `text` as a fixed-size, zero padded ID666 field
+fn write_field<W: Write>(out: &mut W, text: &str, len: usize) -> io::Result<()> {
+    let mut field = vec![0; len];
+    for (dest, b) in field.iter_mut().zip(text.bytes()) {
+        *dest = b;
+    }
+    out.write_all(&field)
+}
+
+/// Writes an `.spc` file of the APU state `apu` and its RAM `aram`
+///
+/// The I/O registers at `$F0`-`$FF` are filled in from `apu`, since players restore them from
+/// there. `game_title` is written to the ID666 tag.
+pub fn write_spc<W: Write>(out: &mut W, apu: &ApuState, aram: &[u8], game_title: &str) -> io::Result<()> {
+    let mut ram = aram.to_vec();
+    ram[0xf1] = apu.control;
+    for i in 0..4 {
+        ram[0xf4 + i] = apu.cpu_to_apu[i];
+    }
+    for (i, timer) in apu.timers.iter().enumerate() {
+        ram[0xfa + i] = timer.target;
+        ram[0xfd + i] = timer.counter;
+    }
+
+    // Header and SPC700 registers
+    try!(out.write_all(SIGNATURE));
+    try!(out.write_all(&[26, 26]));
+    try!(out.write_all(&[26]));     // Has an ID666 tag
+    try!(out.write_all(&[30]));     // Minor version
+    try!(out.write_all(&[apu.pc as u8, (apu.pc >> 8) as u8, apu.a, apu.x, apu.y, apu.psw, apu.sp, 0, 0]));
+
+    // ID666 tag in text format
+    try!(write_field(out, "", 32));             // Song title
+    try!(write_field(out, game_title.trim(), 32));
+    try!(write_field(out, "breeze-dbg", 16));   // Dumper
+    try!(write_field(out, "", 32));             // Comments
+    try!(write_field(out, "", 11));             // Date dumped
+    try!(write_field(out, &PLAY_SECONDS.to_string(), 3));
+    try!(write_field(out, &FADE_MILLIS.to_string(), 5));
+    try!(write_field(out, "", 32));             // Artist
+    try!(out.write_all(&[0]));                  // Muted voices
+    try!(out.write_all(&[0]));                  // Emulator used (unknown)
+    try!(write_field(out, "", 45));
+
+    try!(out.write_all(&ram));
+    try!(out.write_all(&apu.dsp));
+    try!(out.write_all(&[0; 64]));
+    out.write_all(&ram[IPL_START..])
+}
@@ -0,0 +1,267 @@
+//! Symbol files produced by assemblers
+//!
+//! Supported formats:
+//!
+//! * WLA-DX `.sym` files (`00:8000 label`, in the `[labels]` section)
+//! * ca65/ld65 debug info (`sym` lines of `--dbgfile` output)
+//! * VICE label files (`al 008000 .label`, ld65 `-Ln` output)
+//! * bass symbol files and other lists of `address label` pairs
+
+use memmap;
+use util::parse_hex;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Maximum distance from a symbol at which addresses are still described relative to it
+const MAX_OFFSET: u32 = 0x100;
+
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    /// 24-bit address, as given in the symbol file
+    pub addr: u32,
+    pub name: String,
+}
+
+/// A set of symbols, which can be used to resolve addresses to labels
+#[derive(Clone, Default)]
+pub struct SymbolTable {
+    /// All symbols, sorted by `key(addr)`
+    symbols: Vec<Symbol>,
+}
+
+/// Maps mirrored addresses to the same key
+///
+/// In addition to `memmap::canonical`, banks `$80-$FD` are folded onto `$00-$7D`, since symbol
+/// files may use either half for ROM addresses.
+fn key(addr: u32) -> u32 {
+    let addr = memmap::canonical(addr);
+    match (addr >> 16) as u8 {
+        0x80...0xfd => addr & 0x7fffff,
+        _ => addr,
+    }
+}
+
+/// Parses a hexadecimal 24-bit address
+fn parse_addr(s: &str) -> Option<u32> {
+    match parse_hex(s) {
+        Some(addr) if addr <= 0xffffff => Some(addr),
+        _ => None,
+    }
+}
+
+/// Parses a `sym` line of ca65 debug info, eg. `sym id=3,name="main",...,val=0x8000,type=lab`
+fn parse_ca65(fields: &str) -> Option<Symbol> {
+    let mut name = None;
+    let mut val = None;
+    let mut label = false;
+    for field in fields.split(',') {
+        let mut parts = field.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some("name"), Some(value)) => name = Some(value.trim_matches('"').to_string()),
+            (Some("val"), Some(value)) => val = parse_addr(value),
+            (Some("type"), Some(value)) => label = value == "lab",
+            _ => {}
+        }
+    }
+
+    match (name, val) {
+        (Some(name), Some(val)) if label => Some(Symbol { addr: val, name: name }),
+        _ => None,
+    }
+}
+
+/// Parses an `address name` or `bank:address name` line
+fn parse_pair(addr: &str, name: &str) -> Option<Symbol> {
+    let addr = match addr.find(':') {
+        Some(colon) => match (parse_hex(&addr[..colon]), parse_hex(&addr[colon + 1..])) {
+            (Some(bank), Some(offset)) if bank <= 0xff && offset <= 0xffff => Some(bank << 16 | offset),
+            _ => None,
+        },
+        None => parse_addr(addr),
+    };
+    addr.map(|addr| Symbol {
+        addr: addr,
+        name: name.to_string(),
+    })
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    /// Loads a symbol file, detecting its format
+    pub fn load(path: &Path) -> io::Result<SymbolTable> {
+        let mut content = vec![];
+        try!(try!(File::open(path)).read_to_end(&mut content));
+        let table = SymbolTable::parse(&String::from_utf8_lossy(&content));
+        if table.symbols.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("no symbols found in {}", path.display())));
+        }
+        Ok(table)
+    }
+
+    /// Parses the contents of a symbol file. Lines that can't be parsed are skipped.
+    pub fn parse(text: &str) -> SymbolTable {
+        let mut symbols = Vec::new();
+        // WLA-DX files are split into sections, only `[labels]` contains addresses
+        let mut in_labels = true;
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap().trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                in_labels = line == "[labels]";
+                continue;
+            }
+            if !in_labels {
+                continue;
+            }
+
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let symbol = match words.len() {
+                2 if words[0] == "sym" => parse_ca65(words[1]),
+                3 if words[0] == "al" => {
+                    // VICE itself prefixes addresses with the memory space (`C:8000`)
+                    parse_pair(words[1].trim_left_matches("C:"), words[2].trim_left_matches('.'))
+                }
+                2 => parse_pair(words[0], words[1]),
+                _ => None,
+            };
+            if let Some(symbol) = symbol {
+                symbols.push(symbol);
+            }
+        }
+
+        symbols.sort_by_key(|symbol| key(symbol.addr));
+        SymbolTable { symbols: symbols }
+    }
+
+    /// All symbols, sorted by address
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Returns the name of a symbol at exactly `addr`
+    pub fn lookup(&self, addr: u32) -> Option<&str> {
+        let wanted = key(addr);
+        match self.symbols.binary_search_by_key(&wanted, |symbol| key(symbol.addr)) {
+            Ok(i) => {
+                // There may be several symbols at the address, use the first one
+                let first = self.symbols[..i].iter()
+                    .rposition(|symbol| key(symbol.addr) != wanted)
+                    .map_or(0, |j| j + 1);
+                Some(&self.symbols[first].name)
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Describes `addr` using the closest symbol at or before it, eg. `main` or `main+$12`
+    ///
+    /// Only symbols in the same bank and at most `MAX_OFFSET` bytes before `addr` are used.
+    pub fn label(&self, addr: u32) -> Option<String> {
+        if let Some(name) = self.lookup(addr) {
+            return Some(name.to_string());
+        }
+
+        let wanted = key(addr);
+        let index = match self.symbols.binary_search_by_key(&wanted, |symbol| key(symbol.addr)) {
+            Ok(i) | Err(i) => i,
+        };
+        if index == 0 {
+            return None;
+        }
+        // There may be several symbols at the address, use the first one like `lookup`
+        let base = key(self.symbols[index - 1].addr);
+        let symbol = self.symbols[..index].iter().find(|symbol| key(symbol.addr) == base).unwrap();
+        if base >> 16 == wanted >> 16 && wanted - base <= MAX_OFFSET {
+            Some(format!("{}+${:X}", symbol.name, wanted - base))
+        } else {
+            None
+        }
+    }
+
+    /// Formats a 24-bit address, followed by its label if there is one
+    pub fn format_addr(&self, addr: u32) -> String {
+        match self.label(addr) {
+            Some(label) => format!("${:02X}:{:04X} {}", addr >> 16, addr & 0xffff, label),
+            None => format!("${:02X}:{:04X}", addr >> 16, addr & 0xffff),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs(table: &SymbolTable) -> Vec<(u32, &str)> {
+        table.symbols().iter().map(|symbol| (symbol.addr, &*symbol.name)).collect()
+    }
+
+    #[test]
+    fn wla_dx() {
+        let table = SymbolTable::parse("\
+            ; wla symbolic information file\n\
+            [labels]\n\
+            00:8000 reset\n\
+            7e:0010 counter\n\
+            \n\
+            [definitions]\n\
+            00000010 _sizeof_counter\n");
+        assert_eq!(addrs(&table), [(0x008000, "reset"), (0x7e0010, "counter")]);
+    }
+
+    #[test]
+    fn ca65() {
+        let table = SymbolTable::parse("\
+            version major=2,minor=0\n\
+            sym id=0,name=\"main\",addrsize=absolute,scope=0,def=1,val=0x8123,seg=0,type=lab\n\
+            sym id=1,name=\"SIZE\",addrsize=zeropage,scope=0,def=2,val=0x10,type=equ\n");
+        assert_eq!(addrs(&table), [(0x008123, "main")]);
+    }
+
+    #[test]
+    fn vice() {
+        let table = SymbolTable::parse("al C:8123 .handler\nal 01A000 .data_table\n");
+        assert_eq!(addrs(&table), [(0x008123, "handler"), (0x01a000, "data_table")]);
+    }
+
+    #[test]
+    fn pairs() {
+        let table = SymbolTable::parse("# bass\n$C08000 start\n7E:0100 buffer\n1000000 too_large\nfoo bar\n");
+        // Sorted by address, with banks $80-$FD folded onto $00-$7D
+        assert_eq!(addrs(&table), [(0xc08000, "start"), (0x7e0100, "buffer")]);
+    }
+
+    #[test]
+    fn mirrors() {
+        let table = SymbolTable::parse("80:8000 reset\n7E:0010 counter\n00:2100 INIDISP\n");
+        // FastROM mirror
+        assert_eq!(table.lookup(0x008000), Some("reset"));
+        // WRAM mirror in the system banks
+        assert_eq!(table.lookup(0x800010), Some("counter"));
+        // I/O registers are mapped into every system bank
+        assert_eq!(table.lookup(0xbf2100), Some("INIDISP"));
+        assert_eq!(table.lookup(0x7f0010), None);
+    }
+
+    #[test]
+    fn labels() {
+        let table = SymbolTable::parse("00:8000 first\n00:8000 second\n00:9000 other\n");
+        assert_eq!(table.lookup(0x008000), Some("first"));
+        assert_eq!(table.label(0x008012), Some("first+$12".to_string()));
+        assert_eq!(table.label(0x008000 + MAX_OFFSET + 1), None);
+        assert_eq!(table.label(0x018000), None);
+        assert_eq!(table.format_addr(0x009000), "$00:9000 other");
+        assert_eq!(table.format_addr(0x7e0000), "$7E:0000");
+    }
+}
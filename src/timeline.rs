@@ -0,0 +1,139 @@
+//! Interrupt and timing events over a frame
+
+use data::{CpuState, TimingRegs};
+use disasm::{Instr, Mode};
+
+/// Number of dots per scanline (the range of the H counter)
+pub const DOTS_PER_LINE: u16 = 340;
+
+/// Number of scanlines per frame (the range of the V counter, NTSC)
+pub const LINES_PER_FRAME: u16 = 262;
+
+/// H position of the HDMA transfers on each line
+pub const HDMA_DOT: u16 = 278;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EventKind {
+    Nmi,
+    Irq,
+    VblankStart,
+    VblankEnd,
+    AutoJoypad,
+    Dma,
+    HdmaInit,
+    Hdma,
+}
+
+/// All event kinds, in the order they're listed in the legend
+pub static EVENT_KINDS: [EventKind; 8] = [
+    EventKind::Nmi,
+    EventKind::Irq,
+    EventKind::VblankStart,
+    EventKind::VblankEnd,
+    EventKind::AutoJoypad,
+    EventKind::Dma,
+    EventKind::HdmaInit,
+    EventKind::Hdma,
+];
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            EventKind::Nmi => "NMI",
+            EventKind::Irq => "IRQ",
+            EventKind::VblankStart => "V-Blank start",
+            EventKind::VblankEnd => "V-Blank end",
+            EventKind::AutoJoypad => "Auto-joypad read",
+            EventKind::Dma => "DMA",
+            EventKind::HdmaInit => "HDMA init",
+            EventKind::Hdma => "HDMA",
+        }
+    }
+
+    /// Color used to mark the event in the beam diagram
+    pub fn color(&self) -> [u8; 3] {
+        match *self {
+            EventKind::Nmi => [0xe0, 0x20, 0x20],
+            EventKind::Irq => [0xe0, 0x80, 0x00],
+            EventKind::VblankStart => [0x20, 0x20, 0xe0],
+            EventKind::VblankEnd => [0x20, 0xa0, 0xe0],
+            EventKind::AutoJoypad => [0xa0, 0x20, 0xc0],
+            EventKind::Dma => [0x20, 0xa0, 0x20],
+            EventKind::HdmaInit => [0x60, 0x60, 0x60],
+            EventKind::Hdma => [0x80, 0xc0, 0x80],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TimingEvent {
+    pub kind: EventKind,
+    pub line: u16,
+    pub h: u16,
+    pub detail: String,
+}
+
+/// Checks whether executing `instr` ended in an interrupt handler, which means that an NMI or IRQ
+/// was taken right after it
+///
+/// `after` is the CPU state after the instruction, and `line` and `h` the beam position at that
+/// point. `peek` is used to read the interrupt vectors.
+pub fn interrupt_entry<F>(instr: &Instr,
+                          after: &CpuState,
+                          timing: &TimingRegs,
+                          line: u16,
+                          h: u16,
+                          mut peek: F)
+                          -> Option<TimingEvent> where F: FnMut(u32) -> u8 {
+    let pc = after.pc24();
+    // Control flow instructions may legitimately jump to the handler address
+    let jump = pc == instr.next_addr() || Some(pc) == instr.target() || instr.is_return() ||
+        instr.opcode == 0x00 || instr.opcode == 0x02 ||
+        instr.mode == Mode::AbsInd || instr.mode == Mode::AbsIndX || instr.mode == Mode::AbsIndLong;
+    if jump {
+        return None;
+    }
+
+    let (nmi, irq) = if after.emulation { (0xfffa, 0xfffe) } else { (0xffea, 0xffee) };
+    let mut vector = |addr: u32| peek(addr) as u32 | (peek(addr + 1) as u32) << 8;
+
+    let (kind, detail) = if pc == vector(nmi) {
+        (EventKind::Nmi, String::new())
+    } else if pc == vector(irq) {
+        let detail = match (timing.nmitimen >> 4) & 0b11 {
+            1 => format!("H timer (HTIME = {})", timing.htime),
+            2 => format!("V timer (VTIME = {})", timing.vtime),
+            3 => format!("H+V timer (HTIME = {}, VTIME = {})", timing.htime, timing.vtime),
+            _ => "External".to_string(),
+        };
+        (EventKind::Irq, detail)
+    } else {
+        return None;
+    };
+
+    Some(TimingEvent {
+        kind: kind,
+        line: line,
+        h: h,
+        detail: detail,
+    })
+}
+
+/// Describes the part of the frame the beam is in
+pub fn beam_region(h: u16, line: u16, visible_lines: u16) -> &'static str {
+    if line >= visible_lines {
+        "V-Blank"
+    } else if h < 22 || h >= 22 + 256 {
+        "H-Blank"
+    } else {
+        "Visible"
+    }
+}
+
+/// Number of dots the beam moves from `from` to `to` (both `(h, line)`), wrapping around at the end
+/// of the frame
+pub fn dots_between(from: (u16, u16), to: (u16, u16)) -> u32 {
+    let frame = DOTS_PER_LINE as u32 * LINES_PER_FRAME as u32;
+    let pos = |(h, line): (u16, u16)| line as u32 * DOTS_PER_LINE as u32 + h as u32;
+    (pos(to) + frame - pos(from)) % frame
+}
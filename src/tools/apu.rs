@@ -0,0 +1,168 @@
+//! View the SPC700 registers, the CPU-APU ports and the S-DSP registers
+
+use super::Tool;
+use view::RealMainView;
+use util::*;
+use data::ModelData;
+use apu::{self, VOICES, KON, KOFF, FLG, ENDX, PMON, NON, EON, EDL};
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+
+#[derive(Clone)]    //:
+pub struct Apu {
+    cpu: Label,
+    ports: Label,
+    timers: Label,
+    globals: Label,
+    voices: ListStore,
+    regs: ListStore,
+}
+
+/// Wraps `widget` in a frame with the given title
+fn framed<W: IsA<gtk::Widget>>(title: &str, widget: &W) -> gtk::Frame {
+    let frame = gtk::Frame::new(Some(title));
+    let hbox = gtk::Box::new(Orientation::Horizontal, 5);
+    hbox.set_border_width(5);
+    hbox.pack_start(widget, false, true, 0);
+    frame.add(&hbox);
+    frame
+}
+
+impl Tool for Apu {
+    fn new() -> Self {
+        Apu {
+            cpu: Label::new(None),
+            ports: Label::new(None),
+            timers: Label::new(None),
+            globals: Label::new(None),
+            voices: ListStore::new(&[
+                gtk::Type::U32,     // Voice
+                gtk::Type::I32,     // Left volume
+                gtk::Type::I32,     // Right volume
+                gtk::Type::String,  // Pitch (Hex) and frequency
+                gtk::Type::String,  // Source number (Hex)
+                gtk::Type::String,  // ADSR / GAIN
+                gtk::Type::U32,     // Envelope level
+                gtk::Type::I32,     // Output sample
+                gtk::Type::Bool,    // KON bit
+                gtk::Type::Bool,    // KOFF bit
+                gtk::Type::Bool,    // ENDX bit
+                gtk::Type::Bool,    // EON bit
+                gtk::Type::Bool,    // NON bit
+                gtk::Type::Bool,    // PMON bit
+            ]),
+            regs: ListStore::new(&[
+                gtk::Type::String,  // Address (Hex `u8`)
+                gtk::Type::String,  // Name
+                gtk::Type::String,  // Raw value (Hex `u8`)
+            ]),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "APU" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        let voices = TreeView::new_with_model(&self.voices);
+        add_text_column(&voices, "Voice");
+        add_text_column(&voices, "Vol L");
+        add_text_column(&voices, "Vol R");
+        add_text_column(&voices, "Pitch");
+        add_text_column(&voices, "Source");
+        add_text_column(&voices, "Envelope");
+        add_text_column(&voices, "ENVX");
+        add_text_column(&voices, "OUTX");
+        add_text_column(&voices, "KON");
+        add_text_column(&voices, "KOFF");
+        add_text_column(&voices, "End");
+        add_text_column(&voices, "Echo");
+        add_text_column(&voices, "Noise");
+        add_text_column(&voices, "Pitch Mod");
+
+        let left_column = gtk::Box::new(Orientation::Vertical, 5);
+        left_column.set_border_width(5);
+        left_column.pack_start(&framed("SPC700", &self.cpu), false, true, 0);
+        left_column.pack_start(&framed("Ports ($2140-$2143 / $F4-$F7)", &self.ports), false, true, 0);
+        left_column.pack_start(&framed("Timers", &self.timers), false, true, 0);
+        left_column.pack_start(&framed("S-DSP", &self.globals), false, true, 0);
+        left_column.pack_start(&voices, true, true, 0);
+
+        let raw = TreeView::new_with_model(&self.regs);
+        add_text_column(&raw, "Addr");
+        add_text_column(&raw, "Name");
+        add_text_column(&raw, "Raw");
+
+        let hbox = gtk::Paned::new(Orientation::Horizontal);
+        hbox.pack1(&left_column, true, true);
+        hbox.pack2(&raw, false, true);
+        win.add(&hbox);
+    }
+
+    fn connect_events(&mut self, _view: Rc<RealMainView>) {}
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let state = data.apu;
+        let dsp = &state.dsp;
+
+        self.cpu.set_text(&format!("A: ${:02X}  X: ${:02X}  Y: ${:02X}  SP: $01{:02X}  PC: ${:04X}  PSW: {} (${:02X})",
+            state.a, state.x, state.y, state.sp, state.pc, state.flags_string(), state.psw));
+        self.ports.set_text(&format!("CPU -> APU: {}\nAPU -> CPU: {}",
+            state.cpu_to_apu.iter().map(|b| format!("${:02X}", b)).collect::<Vec<_>>().join(" "),
+            state.apu_to_cpu.iter().map(|b| format!("${:02X}", b)).collect::<Vec<_>>().join(" ")));
+        self.timers.set_text(&state.timers.iter().enumerate().map(|(i, timer)| {
+            format!("T{}: {}, divider {}, counter {}", i,
+                if timer.enabled { "enabled" } else { "disabled" },
+                if timer.target == 0 { 256 } else { timer.target as u32 },
+                timer.counter)
+        }).collect::<Vec<_>>().join("\n") + &format!("\nCONTROL ($F1): ${:02X}", state.control));
+
+        let (esa, echo_size) = apu::echo_buffer(dsp);
+        let fir = (0..8).map(|i| format!("{}", dsp[i << 4 | 0xf] as i8)).collect::<Vec<_>>().join(" ");
+        self.globals.set_text(&format!(
+            "Main volume: {} / {}    Echo volume: {} / {}\n\
+             FLG: {}\n\
+             DIR: ${:04X}    Echo buffer: ${:04X} ({} bytes, delay {} ms)    Echo feedback: {}\n\
+             FIR: {}",
+            dsp[0x0c] as i8, dsp[0x1c] as i8, dsp[0x2c] as i8, dsp[0x3c] as i8,
+            apu::flg_string(dsp[FLG as usize]),
+            apu::dir_addr(dsp), esa, echo_size, (dsp[EDL as usize] & 0x0f) as u32 * 16,
+            dsp[0x0d] as i8, fir));
+
+        set_row_count(&self.voices, VOICES);
+        for voice in 0..VOICES {
+            let bit = |reg: u8| dsp[reg as usize] & (1 << voice) != 0;
+            let pitch = apu::pitch(dsp, voice);
+            let entry = self.voices.iter_nth_child(None, voice as i32).expect(&format!("child #{} not found", voice));
+            self.voices.set(&entry, &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13], &[
+                &(voice as u32),
+                &(apu::voice_reg(dsp, voice, 0) as i8 as i32),
+                &(apu::voice_reg(dsp, voice, 1) as i8 as i32),
+                &format!("${:04X} ({:.0} Hz)", pitch, apu::pitch_hz(pitch)),
+                &format!("${:02X}", apu::voice_reg(dsp, voice, 4)),
+                &apu::envelope_string(dsp, voice),
+                &(apu::voice_reg(dsp, voice, 8) as u32 & 0x7f),
+                &(apu::voice_reg(dsp, voice, 9) as i8 as i32),
+                &bit(KON),
+                &bit(KOFF),
+                &bit(ENDX),
+                &bit(EON),
+                &bit(NON),
+                // Voice 0 can't be pitch modulated
+                &(voice != 0 && bit(PMON)),
+            ]);
+        }
+
+        // Raw register values on the right
+        set_row_count(&self.regs, dsp.len());
+        for (reg, &value) in dsp.iter().enumerate() {
+            let entry = self.regs.iter_nth_child(None, reg as i32).expect(&format!("child #{} not found", reg));
+            self.regs.set(&entry, &[0, 1, 2], &[
+                &format!("${:02X}", reg),
+                &apu::reg_name(reg as u8).unwrap_or(String::new()),
+                &format!("${:02X}", value),
+            ]);
+        }
+    }
+}
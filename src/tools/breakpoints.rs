@@ -0,0 +1,197 @@
+//! Manage execution breakpoints and memory watchpoints
+
+use super::Tool;
+use view::{View, RealMainView};
+use util::*;
+use data::ModelData;
+use breakpoint::{Breakpoint, Kind, Condition};
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, Button, Entry, ComboBoxText};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+
+/// Breakpoint kinds, in the order they appear in the dropdown box
+const KINDS: [Kind; 4] = [Kind::Exec, Kind::Read, Kind::Write, Kind::ReadWrite];
+
+#[derive(Clone)]    //:
+pub struct Breakpoints {
+    treeview: TreeView,
+    breakpoints: ListStore,
+    kind: ComboBoxText,
+    start: Entry,
+    end: Entry,
+    hit_count: Entry,
+    condition: Entry,
+    add: Button,
+    remove: Button,
+    toggle: Button,
+    reset_hits: Button,
+}
+
+impl Breakpoints {
+    /// Builds a breakpoint from the contents of the input widgets
+    fn read_input(&self) -> Result<Breakpoint, String> {
+        let start_text = self.start.get_text().unwrap_or(String::new());
+        let start = match parse_hex(&start_text) {
+            Some(addr) => addr,
+            None => return Err(format!("Invalid start address '{}'", start_text)),
+        };
+        let end_text = self.end.get_text().unwrap_or(String::new());
+        let end = if end_text.trim().is_empty() {
+            start
+        } else {
+            match parse_hex(&end_text) {
+                Some(addr) => addr,
+                None => return Err(format!("Invalid end address '{}'", end_text)),
+            }
+        };
+        let kind = KINDS[self.kind.get_active().max(0) as usize];
+
+        let mut bp = Breakpoint::new(kind, start, end);
+
+        let hits_text = self.hit_count.get_text().unwrap_or(String::new());
+        if !hits_text.trim().is_empty() {
+            bp.hit_count = match parse_number(&hits_text) {
+                Some(count) => count,
+                None => return Err(format!("Invalid hit count '{}'", hits_text)),
+            };
+        }
+
+        let cond_text = self.condition.get_text().unwrap_or(String::new());
+        if !cond_text.trim().is_empty() {
+            bp.condition = Some(try!(Condition::parse(&cond_text)));
+        }
+
+        Ok(bp)
+    }
+
+    /// Returns the index of the selected breakpoint
+    fn selected(&self) -> Option<usize> {
+        self.treeview.get_selection().get_selected_rows().0.first()
+            .map(|row| row.get_indices()[0] as usize)
+    }
+}
+
+impl Tool for Breakpoints {
+    fn new() -> Self {
+        let model = ListStore::new(&[
+            gtk::Type::U32,     // #
+            gtk::Type::Bool,    // Enabled
+            gtk::Type::String,  // Kind
+            gtk::Type::String,  // Address range (Hex)
+            gtk::Type::U32,     // Hit count
+            gtk::Type::U32,     // Hits
+            gtk::Type::String,  // Condition
+        ]);
+        Breakpoints {
+            treeview: TreeView::new_with_model(&model),
+            breakpoints: model,
+            kind: combo_box_text(&[
+                "Execute",
+                "Read",
+                "Write",
+                "Read/Write",
+            ]),
+            start: Entry::new(),
+            end: Entry::new(),
+            hit_count: Entry::new(),
+            condition: Entry::new(),
+            add: Button::new_with_label("Add"),
+            remove: Button::new_with_label("Remove"),
+            toggle: Button::new_with_label("Enable/Disable"),
+            reset_hits: Button::new_with_label("Reset Hits"),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Breakpoints" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        add_text_column(&self.treeview, "#");
+        add_text_column(&self.treeview, "Enabled");
+        add_text_column(&self.treeview, "Type");
+        add_text_column(&self.treeview, "Address");
+        add_text_column(&self.treeview, "Hit Count");
+        add_text_column(&self.treeview, "Hits");
+        add_text_column(&self.treeview, "Condition");
+
+        self.kind.set_active(0);
+        self.start.set_placeholder_text(Some("$00:8000"));
+        self.end.set_placeholder_text(Some("(single address)"));
+        self.hit_count.set_placeholder_text(Some("1"));
+        self.condition.set_placeholder_text(Some("A == $10"));
+
+        let input = gtk::Box::new(Orientation::Horizontal, 5);
+        input.set_border_width(5);
+        input.pack_start(&self.kind, false, true, 0);
+        input.pack_start(&Label::new(Some("From:")), false, true, 0);
+        input.pack_start(&self.start, false, true, 0);
+        input.pack_start(&Label::new(Some("To:")), false, true, 0);
+        input.pack_start(&self.end, false, true, 0);
+        input.pack_start(&Label::new(Some("Hit Count:")), false, true, 0);
+        input.pack_start(&self.hit_count, false, true, 0);
+        input.pack_start(&Label::new(Some("Condition:")), false, true, 0);
+        input.pack_start(&self.condition, true, true, 0);
+        input.pack_end(&self.add, false, true, 0);
+
+        let buttons = gtk::Box::new(Orientation::Horizontal, 5);
+        buttons.set_border_width(5);
+        buttons.pack_start(&self.toggle, false, true, 0);
+        buttons.pack_start(&self.remove, false, true, 0);
+        buttons.pack_start(&self.reset_hits, false, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&input, false, true, 0);
+        vbox.pack_start(&buttons, false, true, 0);
+        vbox.pack_start(&self.treeview, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let this = self.clone();
+        let v = view.clone();
+        self.add.connect_clicked(move |_| {
+            match this.read_input() {
+                Ok(bp) => v.model.borrow_mut().add_breakpoint(bp),
+                Err(e) => v.error(&e),
+            }
+        });
+
+        let this = self.clone();
+        let v = view.clone();
+        self.remove.connect_clicked(move |_| {
+            if let Some(index) = this.selected() {
+                v.model.borrow_mut().remove_breakpoint(index);
+            }
+        });
+
+        let this = self.clone();
+        let v = view.clone();
+        self.toggle.connect_clicked(move |_| {
+            if let Some(index) = this.selected() {
+                v.model.borrow_mut().toggle_breakpoint(index);
+            }
+        });
+
+        self.reset_hits.connect_clicked(move |_| {
+            view.model.borrow_mut().reset_breakpoint_hits();
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        set_row_count(&self.breakpoints, data.breakpoints.len());
+
+        for (id, bp) in data.breakpoints.iter().enumerate() {
+            let entry = self.breakpoints.iter_nth_child(None, id as i32).expect(&format!("child #{} not found", id));
+            self.breakpoints.set(&entry, &[0, 1, 2, 3, 4, 5, 6], &[
+                &(id as u32),
+                &bp.enabled,
+                &bp.kind.name(),
+                &bp.range_string(),
+                &bp.hit_count.max(1),
+                &bp.hits,
+                &bp.condition.as_ref().map(|c| c.text()).unwrap_or(""),
+            ]);
+        }
+    }
+}
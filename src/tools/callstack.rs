@@ -0,0 +1,97 @@
+//! Show the chain of active subroutine calls and interrupts
+
+use super::Tool;
+use view::RealMainView;
+use util::*;
+use data::{ModelData, CallSource};
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, CheckButton};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+use std::cell::Cell;
+
+#[derive(Clone)]    //:
+pub struct CallStack {
+    track: CheckButton,
+    source: Label,
+    calls: ListStore,
+    /// Tracking state last reported by the model, to tell user changes of the check button apart
+    /// from updates
+    tracking: Rc<Cell<bool>>,
+}
+
+impl Tool for CallStack {
+    fn new() -> Self {
+        CallStack {
+            track: CheckButton::new_with_label("Track calls"),
+            source: Label::new(None),
+            calls: ListStore::new(&[
+                gtk::Type::U32,     // Depth
+                gtk::Type::String,  // Kind
+                gtk::Type::String,  // Subroutine
+                gtk::Type::String,  // Call site
+                gtk::Type::String,  // Return address
+                gtk::Type::String,  // Stack pointer
+            ]),
+            tracking: Rc::new(Cell::new(false)),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Call Stack" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        let treeview = TreeView::new_with_model(&self.calls);
+        add_text_column(&treeview, "#");
+        add_text_column(&treeview, "Kind");
+        add_text_column(&treeview, "Subroutine");
+        add_text_column(&treeview, "Called From");
+        add_text_column(&treeview, "Returns To");
+        add_text_column(&treeview, "SP");
+
+        let control_box = gtk::Box::new(Orientation::Horizontal, 5);
+        control_box.set_border_width(5);
+        control_box.pack_start(&self.track, false, true, 0);
+        control_box.pack_start(&self.source, false, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&control_box, false, true, 0);
+        vbox.pack_start(&treeview, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let this = self.clone();
+        self.track.connect_toggled(move |button| {
+            let active = button.get_active();
+            if active != this.tracking.get() {
+                view.model.borrow_mut().set_call_tracking(active);
+            }
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let tracking = data.call_source != CallSource::Scanned;
+        self.tracking.set(tracking);
+        self.track.set_active(tracking);
+        self.source.set_text(match data.call_source {
+            CallSource::Scanned => "Calls reconstructed from the stack contents (may be inaccurate)",
+            CallSource::TrackedSinceEnable => "Calls tracked since tracking was enabled, older calls reconstructed from the stack contents",
+            CallSource::TrackedSinceReset => "Calls tracked since reset",
+        });
+
+        // Innermost call first
+        set_row_count(&self.calls, data.calls.len());
+        for (row, call) in data.calls.iter().rev().enumerate() {
+            let entry = self.calls.iter_nth_child(None, row as i32).expect(&format!("child #{} not found", row));
+            self.calls.set(&entry, &[0, 1, 2, 3, 4, 5], &[
+                &(row as u32),
+                &call.kind,
+                &data.symbols.format_addr(call.target),
+                &data.symbols.format_addr(call.call_site),
+                &data.symbols.format_addr(call.return_addr),
+                &format!("${:04X}", call.sp),
+            ]);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Show the internal cartridge header and the interrupt vectors
+
+use super::Tool;
+use view::RealMainView;
+use util::*;
+use data::ModelData;
+use header::{NATIVE_VECTORS, EMULATION_VECTORS};
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+
+#[derive(Clone)]    //:
+pub struct Cartridge {
+    info: ListStore,
+    vectors: ListStore,
+}
+
+/// Formats a size in bytes
+fn size_string(bytes: usize) -> String {
+    if bytes >= 0x100000 && bytes % 0x100000 == 0 {
+        format!("{} MiB", bytes / 0x100000)
+    } else {
+        format!("{} KiB", bytes / 0x400)
+    }
+}
+
+impl Tool for Cartridge {
+    fn new() -> Self {
+        Cartridge {
+            info: ListStore::new(&[
+                gtk::Type::String,  // Field
+                gtk::Type::String,  // Value
+            ]),
+            vectors: ListStore::new(&[
+                gtk::Type::String,  // Mode
+                gtk::Type::String,  // Vector
+                gtk::Type::String,  // Vector address (Hex)
+                gtk::Type::String,  // Handler
+            ]),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Cartridge" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        let info = TreeView::new_with_model(&self.info);
+        add_text_column(&info, "Field");
+        add_text_column(&info, "Value");
+
+        let vectors = TreeView::new_with_model(&self.vectors);
+        add_text_column(&vectors, "Mode");
+        add_text_column(&vectors, "Vector");
+        add_text_column(&vectors, "Address");
+        add_text_column(&vectors, "Handler");
+
+        let hbox = gtk::Paned::new(Orientation::Horizontal);
+        hbox.pack1(&info, true, true);
+        hbox.pack2(&vectors, true, true);
+        win.add(&hbox);
+    }
+
+    fn connect_events(&mut self, _view: Rc<RealMainView>) {}
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let header = match data.header {
+            Some(header) => header,
+            None => {
+                set_row_count(&self.info, 1);
+                let entry = self.info.iter_nth_child(None, 0).unwrap();
+                self.info.set(&entry, &[0, 1], &[&"Header", &"ROM too small"]);
+                set_row_count(&self.vectors, 0);
+                return;
+            }
+        };
+
+        let checksum = if header.checksum_valid() {
+            format!("${:04X} (valid)", header.checksum)
+        } else {
+            format!("${:04X} (invalid, calculated ${:04X})", header.checksum, header.actual_checksum)
+        };
+        let complement = format!("${:04X} ({})", header.complement,
+            if header.complement_valid() { "matches checksum" } else { "doesn't match checksum" });
+        let rom_size = if header.rom_bytes() == data.rom.len() {
+            size_string(header.rom_bytes())
+        } else {
+            format!("{} (image has {} bytes)", size_string(header.rom_bytes()), data.rom.len())
+        };
+        let ram_size = match header.ram_bytes() {
+            0 => "None".to_string(),
+            bytes => size_string(bytes),
+        };
+
+        let mut rows = vec![
+            ("Header offset", format!("${:06X}", header.offset)),
+            ("Title", header.title.clone()),
+            ("Map mode", header.map_mode_string()),
+            ("Chipset", header.chipset_string()),
+            ("ROM size", rom_size),
+            ("RAM size", ram_size),
+            ("Region", format!("{} (${:02X})", header.region_string(), header.region)),
+            ("Developer ID", format!("${:02X}", header.developer)),
+            ("Version", format!("1.{}", header.version)),
+            ("Checksum", checksum),
+            ("Complement", complement),
+        ];
+        if let Some(ref maker) = header.maker_code {
+            rows.push(("Maker code", maker.clone()));
+        }
+        if let Some(ref game) = header.game_code {
+            rows.push(("Game code", game.clone()));
+        }
+
+        let source = data.rom_source;
+        if let Some(ref entry) = source.archive_entry {
+            rows.push(("Archive entry", entry.clone()));
+        }
+        rows.push(("Copier header", if source.copier_header { "Removed" } else { "None" }.to_string()));
+        if let Some((ref path, format)) = source.patch {
+            rows.push(("Patch", format!("{} ({})", path.display(), format)));
+        }
+
+        set_row_count(&self.info, rows.len());
+        for (i, &(field, ref value)) in rows.iter().enumerate() {
+            let entry = self.info.iter_nth_child(None, i as i32).expect(&format!("child #{} not found", i));
+            self.info.set(&entry, &[0, 1], &[&field, value]);
+        }
+
+        set_row_count(&self.vectors, NATIVE_VECTORS.len() + EMULATION_VECTORS.len());
+        let native = NATIVE_VECTORS.iter().zip(header.native_vectors.iter()).enumerate()
+            .map(|(i, (name, &handler))| ("Native", name, 0xffe4 + i as u16 * 2, handler));
+        let emulation = EMULATION_VECTORS.iter().zip(header.emulation_vectors.iter()).enumerate()
+            .map(|(i, (name, &handler))| ("Emulation", name, 0xfff4 + i as u16 * 2, handler));
+        for (row, (mode, name, addr, handler)) in native.chain(emulation).enumerate() {
+            let entry = self.vectors.iter_nth_child(None, row as i32).expect(&format!("child #{} not found", row));
+            self.vectors.set(&entry, &[0, 1, 2, 3], &[
+                &mode,
+                name,
+                &format!("${:04X}", addr),
+                &data.symbols.format_addr(handler as u32),
+            ]);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Record which parts of the ROM are code and which are data
+
+use super::Tool;
+use view::RealMainView;
+use util::*;
+use data::ModelData;
+use cdl::{CODE, DATA, DMA};
+
+use gdk_pixbuf::{Pixbuf, InterpType};
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, Button, CheckButton, Image};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+use std::cell::Cell;
+
+/// Width of the coverage map. Each row shows a bank.
+const MAP_WIDTH: usize = 256;
+
+/// Scale factor of the coverage map
+const SCALE: i32 = 2;
+
+#[derive(Clone)]    //:
+pub struct CodeDataLog {
+    log: CheckButton,
+    save: Button,
+    load: Button,
+    reset: Button,
+    total: Label,
+    map: Image,
+    banks: ListStore,
+    /// Logging state last reported by the model, to tell user changes of the check button apart
+    /// from updates
+    logging: Rc<Cell<bool>>,
+}
+
+/// Color of a part of the coverage map containing bytes with the given flags
+fn map_color(flags: u8) -> [u8; 3] {
+    let code = flags & CODE != 0;
+    // DMA sources are also marked as data
+    let data = flags & DATA != 0 && flags & DMA == 0;
+    match (code, data, flags & DMA != 0) {
+        (true, true, _) => [0xa0, 0x40, 0xc0],
+        (true, false, _) => [0xd0, 0x30, 0x30],
+        (false, true, _) => [0x30, 0x60, 0xd0],
+        (false, false, true) => [0x30, 0xb0, 0x30],
+        (false, false, false) => [0xe0, 0xe0, 0xe0],
+    }
+}
+
+impl Tool for CodeDataLog {
+    fn new() -> Self {
+        CodeDataLog {
+            log: CheckButton::new_with_label("Log code and data"),
+            save: Button::new_with_label("Save"),
+            load: Button::new_with_label("Load"),
+            reset: Button::new_with_label("Reset"),
+            total: Label::new(None),
+            map: Image::new(),
+            banks: ListStore::new(&[
+                gtk::Type::String,  // Bank (Hex)
+                gtk::Type::U32,     // Code bytes
+                gtk::Type::U32,     // Data bytes
+                gtk::Type::String,  // Coverage
+            ]),
+            logging: Rc::new(Cell::new(false)),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Code/Data Log" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        let treeview = TreeView::new_with_model(&self.banks);
+        add_text_column(&treeview, "Bank");
+        add_text_column(&treeview, "Code");
+        add_text_column(&treeview, "Data");
+        add_text_column(&treeview, "Coverage");
+
+        let control_box = gtk::Box::new(Orientation::Horizontal, 5);
+        control_box.set_border_width(5);
+        control_box.pack_start(&self.log, false, true, 0);
+        control_box.pack_start(&self.save, false, true, 0);
+        control_box.pack_start(&self.load, false, true, 0);
+        control_box.pack_start(&self.reset, false, true, 0);
+        control_box.pack_start(&self.total, false, true, 0);
+
+        let legend = Label::new(None);
+        legend.set_markup("<span foreground=\"#d03030\">\u{25a0}</span> Code   \
+                           <span foreground=\"#3060d0\">\u{25a0}</span> Data   \
+                           <span foreground=\"#a040c0\">\u{25a0}</span> Code and data   \
+                           <span foreground=\"#30b030\">\u{25a0}</span> DMA source");
+
+        let map_box = gtk::Box::new(Orientation::Vertical, 5);
+        map_box.set_border_width(5);
+        map_box.pack_start(&self.map, false, true, 0);
+        map_box.pack_start(&legend, false, true, 0);
+
+        let hbox = gtk::Paned::new(Orientation::Horizontal);
+        hbox.pack1(&map_box, false, true);
+        hbox.pack2(&treeview, true, true);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&control_box, false, true, 0);
+        vbox.pack_start(&hbox, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let this = self.clone();
+        let v = view.clone();
+        self.log.connect_toggled(move |button| {
+            let active = button.get_active();
+            if active != this.logging.get() {
+                v.model.borrow_mut().set_cdl_logging(active);
+            }
+        });
+
+        let v = view.clone();
+        self.save.connect_clicked(move |_| {
+            let result = v.model.borrow().save_cdl();
+            if let Err(e) = result {
+                v.error(&format!("Couldn't save code/data log: {}", e));
+            }
+        });
+
+        let v = view.clone();
+        self.load.connect_clicked(move |_| {
+            let result = v.model.borrow_mut().load_cdl();
+            if let Err(e) = result {
+                v.error(&format!("Couldn't load code/data log: {}", e));
+            }
+        });
+
+        self.reset.connect_clicked(move |_| {
+            view.model.borrow_mut().reset_cdl();
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        self.logging.set(data.cdl_logging);
+        self.log.set_active(data.cdl_logging);
+
+        let flags = data.cdl.flags();
+        let bank_size = data.cdl.bank_size().max(MAP_WIDTH);
+        let coverage = data.cdl.coverage();
+
+        // One row per bank, each pixel combining the flags of `bank_size / MAP_WIDTH` bytes
+        let rows = coverage.len().max(1);
+        let mut pixels = vec![0; MAP_WIDTH * rows * 3];
+        for (bank, bank_flags) in flags.chunks(bank_size).enumerate() {
+            for (x, part) in bank_flags.chunks(bank_size / MAP_WIDTH).enumerate() {
+                let color = map_color(part.iter().fold(0, |acc, &f| acc | f));
+                let i = (bank * MAP_WIDTH + x) * 3;
+                pixels[i..i + 3].copy_from_slice(&color);
+            }
+        }
+        let (width, height) = (MAP_WIDTH as i32, rows as i32);
+        let pixbuf = Pixbuf::new_from_vec(pixels, 0, false, 8, width, height, width * 3);
+        let pixbuf = pixbuf.scale_simple(width * SCALE, height * SCALE, InterpType::Nearest).unwrap();
+        self.map.set_from_pixbuf(Some(&pixbuf));
+
+        set_row_count(&self.banks, coverage.len());
+        for (bank, cov) in coverage.iter().enumerate() {
+            let entry = self.banks.iter_nth_child(None, bank as i32).expect(&format!("child #{} not found", bank));
+            self.banks.set(&entry, &[0, 1, 2, 3], &[
+                &format!("${:02X}", bank),
+                &(cov.code as u32),
+                &(cov.data as u32),
+                &format!("{:.1}%", cov.percent()),
+            ]);
+        }
+
+        let logged = coverage.iter().map(|cov| cov.code + cov.data).sum::<usize>();
+        let code = coverage.iter().map(|cov| cov.code).sum::<usize>();
+        self.total.set_text(&format!("{:.1}% of the ROM logged ({} bytes code, {} bytes data)",
+            logged as f64 * 100.0 / flags.len().max(1) as f64, code, logged - code));
+    }
+}
@@ -0,0 +1,146 @@
+//! Manage Game Genie and Pro Action Replay cheats
+
+use super::Tool;
+use view::{View, RealMainView};
+use util::*;
+use data::ModelData;
+use cheat::Cheat;
+use memmap;
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, Button, Entry};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+
+#[derive(Clone)]    //:
+pub struct Cheats {
+    treeview: TreeView,
+    cheats: ListStore,
+    code: Entry,
+    description: Entry,
+    add: Button,
+    remove: Button,
+    toggle: Button,
+}
+
+impl Cheats {
+    /// Returns the index of the selected cheat
+    fn selected(&self) -> Option<usize> {
+        self.treeview.get_selection().get_selected_rows().0.first()
+            .map(|row| row.get_indices()[0] as usize)
+    }
+}
+
+impl Tool for Cheats {
+    fn new() -> Self {
+        let model = ListStore::new(&[
+            gtk::Type::Bool,    // Enabled
+            gtk::Type::String,  // Code
+            gtk::Type::String,  // Format
+            gtk::Type::String,  // Address (Hex)
+            gtk::Type::String,  // Value (Hex)
+            gtk::Type::String,  // Target
+            gtk::Type::String,  // Description
+        ]);
+        Cheats {
+            treeview: TreeView::new_with_model(&model),
+            cheats: model,
+            code: Entry::new(),
+            description: Entry::new(),
+            add: Button::new_with_label("Add"),
+            remove: Button::new_with_label("Remove"),
+            toggle: Button::new_with_label("Enable/Disable"),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Cheats" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        add_text_column(&self.treeview, "Enabled");
+        add_text_column(&self.treeview, "Code");
+        add_text_column(&self.treeview, "Format");
+        add_text_column(&self.treeview, "Address");
+        add_text_column(&self.treeview, "Value");
+        add_text_column(&self.treeview, "Target");
+        add_text_column(&self.treeview, "Description");
+
+        self.code.set_placeholder_text(Some("DD62-3B1D or 7E0DBE09"));
+        self.description.set_placeholder_text(Some("Description"));
+
+        let input = gtk::Box::new(Orientation::Horizontal, 5);
+        input.set_border_width(5);
+        input.pack_start(&Label::new(Some("Code:")), false, true, 0);
+        input.pack_start(&self.code, false, true, 0);
+        input.pack_start(&self.description, true, true, 0);
+        input.pack_end(&self.add, false, true, 0);
+
+        let buttons = gtk::Box::new(Orientation::Horizontal, 5);
+        buttons.set_border_width(5);
+        buttons.pack_start(&self.toggle, false, true, 0);
+        buttons.pack_start(&self.remove, false, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&input, false, true, 0);
+        vbox.pack_start(&buttons, false, true, 0);
+        vbox.pack_start(&self.treeview, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let this = self.clone();
+        let v = view.clone();
+        self.add.connect_clicked(move |_| {
+            let code = this.code.get_text().unwrap_or(String::new());
+            let description = this.description.get_text().unwrap_or(String::new());
+            let cheat = match Cheat::decode(&code, &description) {
+                Ok(cheat) => cheat,
+                Err(e) => return v.error(&e),
+            };
+            let result = v.model.borrow_mut().add_cheat(cheat);
+            if let Err(e) = result {
+                return v.error(&format!("Couldn't save cheats: {}", e));
+            }
+            this.code.set_text("");
+            this.description.set_text("");
+        });
+
+        let this = self.clone();
+        let v = view.clone();
+        self.remove.connect_clicked(move |_| {
+            if let Some(index) = this.selected() {
+                let result = v.model.borrow_mut().remove_cheat(index);
+                if let Err(e) = result {
+                    v.error(&format!("Couldn't save cheats: {}", e));
+                }
+            }
+        });
+
+        let this = self.clone();
+        self.toggle.connect_clicked(move |_| {
+            if let Some(index) = this.selected() {
+                let result = view.model.borrow_mut().toggle_cheat(index);
+                if let Err(e) = result {
+                    view.error(&format!("Couldn't save cheats: {}", e));
+                }
+            }
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        set_row_count(&self.cheats, data.cheats.len());
+
+        for (id, cheat) in data.cheats.iter().enumerate() {
+            let target = if memmap::wram_offset(cheat.addr).is_some() { "WRAM" } else { "ROM" };
+            let entry = self.cheats.iter_nth_child(None, id as i32).expect(&format!("child #{} not found", id));
+            self.cheats.set(&entry, &[0, 1, 2, 3, 4, 5, 6], &[
+                &cheat.enabled,
+                &cheat.code,
+                &cheat.format.name(),
+                &format!("${:02X}:{:04X}", cheat.addr >> 16, cheat.addr & 0xffff),
+                &format!("${:02X}", cheat.value),
+                &target,
+                &cheat.description,
+            ]);
+        }
+    }
+}
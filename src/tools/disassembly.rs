@@ -0,0 +1,112 @@
+//! Disassembly of the code at the program counter, and CPU registers
+
+use super::Tool;
+use view::{View, RealMainView};
+use util::*;
+use data::ModelData;
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, Button};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+#[derive(Clone)]    //:
+pub struct Disassembly {
+    treeview: TreeView,
+    lines: ListStore,
+    regs: Label,
+    run_to_cursor: Button,
+    /// Addresses of the displayed instructions, by row
+    addrs: Rc<RefCell<Vec<u32>>>,
+}
+
+impl Tool for Disassembly {
+    fn new() -> Self {
+        let model = ListStore::new(&[
+            gtk::Type::String,  // PC marker
+            gtk::Type::String,  // Address (Hex)
+            gtk::Type::String,  // Label
+            gtk::Type::String,  // Raw bytes (Hex)
+            gtk::Type::String,  // Instruction
+        ]);
+        Disassembly {
+            treeview: TreeView::new_with_model(&model),
+            lines: model,
+            regs: Label::new(None),
+            run_to_cursor: Button::new_with_label("Run to Cursor"),
+            addrs: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Disassembly" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        add_text_column(&self.treeview, "");
+        add_text_column(&self.treeview, "Addr");
+        add_text_column(&self.treeview, "Label");
+        add_text_column(&self.treeview, "Bytes");
+        add_text_column(&self.treeview, "Instruction");
+
+        self.regs.set_halign(gtk::Align::Start);
+
+        let hbox = gtk::Box::new(Orientation::Horizontal, 5);
+        hbox.set_border_width(5);
+        hbox.pack_start(&self.regs, true, true, 0);
+        hbox.pack_end(&self.run_to_cursor, false, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&hbox, false, true, 0);
+        vbox.pack_start(&self.treeview, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let this = self.clone();
+        self.run_to_cursor.connect_clicked(move |_| {
+            if view.is_running() {
+                return;
+            }
+            let rows = this.treeview.get_selection().get_selected_rows().0;
+            let addr = match rows.first() {
+                Some(row) => this.addrs.borrow()[row.get_indices()[0] as usize],
+                None => return,
+            };
+            let result = view.model.borrow_mut().run_to(addr);
+            if let Err(e) = result {
+                view.error(&e);
+            }
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let cpu = data.cpu;
+        self.regs.set_text(&format!(
+            "A: ${:04X}  X: ${:04X}  Y: ${:04X}  S: ${:04X}  D: ${:04X}  DB: ${:02X}  P: {} ({})",
+            cpu.a, cpu.x, cpu.y, cpu.s, cpu.d, cpu.dbr, cpu.flags_string(),
+            if cpu.emulation { "emulation" } else { "native" }));
+
+        let entry_count = self.lines.iter_n_children(None) as usize;
+        for _ in entry_count..data.disasm.len() {
+            self.lines.append();
+        }
+
+        let mut addrs = self.addrs.borrow_mut();
+        addrs.clear();
+        for (i, line) in data.disasm.iter().enumerate() {
+            let entry = self.lines.iter_nth_child(None, i as i32).expect(&format!("child #{} not found", i));
+            let text = match line.target.and_then(|target| data.symbols.label(target)) {
+                Some(label) => format!("{}  ; {}", line.text, label),
+                None => line.text.clone(),
+            };
+            self.lines.set(&entry, &[0, 1, 2, 3, 4], &[
+                &if line.addr == cpu.pc24() { "▶" } else { "" },
+                &format!("${:02X}:{:04X}", line.addr >> 16, line.addr & 0xffff),
+                &data.symbols.lookup(line.addr).unwrap_or(""),
+                &line.bytes,
+                &text,
+            ]);
+            addrs.push(line.addr);
+        }
+    }
+}
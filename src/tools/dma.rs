@@ -0,0 +1,161 @@
+//! View DMA and HDMA channel registers
+
+use super::Tool;
+use view::RealMainView;
+use util::*;
+use data::ModelData;
+use regs::{self, DMA_REG_NAMES};
+use hdma::MODE_PATTERNS;
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+
+#[derive(Clone)]    //:
+pub struct Dma {
+    channels: ListStore,
+    regs: ListStore,
+    enables: Label,
+}
+
+/// Formats a B-bus address with its register name
+fn b_bus_string(addr: u16) -> String {
+    match regs::name(addr) {
+        Some(name) => format!("${:04X} {}", addr, name),
+        None => format!("${:04X}", addr),
+    }
+}
+
+impl Tool for Dma {
+    fn new() -> Self {
+        Dma {
+            channels: ListStore::new(&[
+                gtk::Type::U32,     // Channel
+                gtk::Type::Bool,    // MDMAEN bit
+                gtk::Type::Bool,    // HDMAEN bit
+                gtk::Type::String,  // Direction
+                gtk::Type::String,  // Transfer mode
+                gtk::Type::String,  // A-bus step
+                gtk::Type::String,  // A-bus address (Hex)
+                gtk::Type::String,  // B-bus register
+                gtk::Type::String,  // Byte count / HDMA indirect address
+                gtk::Type::String,  // HDMA table address (Hex)
+                gtk::Type::String,  // HDMA line counter
+            ]),
+            regs: ListStore::new(&[
+                gtk::Type::String,  // Address (Hex `u16`)
+                gtk::Type::String,  // Name
+                gtk::Type::String,  // Raw value (Hex `u8`)
+            ]),
+            enables: Label::new(None),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "DMA" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        let channels = TreeView::new_with_model(&self.channels);
+        add_text_column(&channels, "Ch");
+        add_text_column(&channels, "DMA");
+        add_text_column(&channels, "HDMA");
+        add_text_column(&channels, "Direction");
+        add_text_column(&channels, "Mode");
+        add_text_column(&channels, "A-Bus Step");
+        add_text_column(&channels, "A-Bus Address");
+        add_text_column(&channels, "B-Bus Register");
+        add_text_column(&channels, "Count / Indirect");
+        add_text_column(&channels, "HDMA Table");
+        add_text_column(&channels, "Line Counter");
+
+        let enables_frame = gtk::Frame::new(Some("$420B - MDMAEN / $420C - HDMAEN"));
+        let enables_box = gtk::Box::new(Orientation::Horizontal, 5);
+        enables_box.set_border_width(5);
+        enables_box.pack_start(&self.enables, false, true, 0);
+        enables_frame.add(&enables_box);
+
+        let left_column = gtk::Box::new(Orientation::Vertical, 5);
+        left_column.set_border_width(5);
+        left_column.pack_start(&enables_frame, false, true, 0);
+        left_column.pack_start(&channels, true, true, 0);
+
+        let raw = TreeView::new_with_model(&self.regs);
+        add_text_column(&raw, "Addr");
+        add_text_column(&raw, "Name");
+        add_text_column(&raw, "Raw");
+
+        let hbox = gtk::Paned::new(Orientation::Horizontal);
+        hbox.pack1(&left_column, true, true);
+        hbox.pack2(&raw, false, true);
+        win.add(&hbox);
+    }
+
+    fn connect_events(&mut self, _view: Rc<RealMainView>) {}
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let dma = data.dma;
+        self.enables.set_text(&format!("MDMAEN: ${:02X}    HDMAEN: ${:02X}", dma.mdmaen, dma.hdmaen));
+
+        set_row_count(&self.channels, 8);
+        for (ch, r) in dma.channels.iter().enumerate() {
+            let dmap = r[0];
+            let b_addr = 0x2100 | r[1] as u16;
+            let a_addr = (r[4] as u32) << 16 | (r[3] as u32) << 8 | r[2] as u32;
+            let das = (r[6] as u16) << 8 | r[5] as u16;
+            let indirect = dmap & 0x40 != 0;
+            let hdma = dma.hdmaen & (1 << ch) != 0;
+
+            let mode = dmap & 0x07;
+            let targets = MODE_PATTERNS[mode as usize].iter()
+                .map(|&offset| format!("{:02X}", (b_addr + offset) & 0xff))
+                .collect::<Vec<_>>()
+                .join(",");
+
+            let count = if hdma && indirect {
+                format!("${:02X}:{:04X} (indirect)", r[7], das)
+            } else if hdma {
+                format!("${:04X}", das)
+            } else {
+                format!("{} bytes", if das == 0 { 0x10000 } else { das as u32 })
+            };
+
+            let entry = self.channels.iter_nth_child(None, ch as i32).expect(&format!("child #{} not found", ch));
+            self.channels.set(&entry, &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10], &[
+                &(ch as u32),
+                &(dma.mdmaen & (1 << ch) != 0),
+                &hdma,
+                &if dmap & 0x80 == 0 { "A -> B" } else { "B -> A" },
+                &format!("{} ({}){}", mode, targets, if indirect { ", indirect" } else { "" }),
+                &match (dmap >> 3) & 0b11 {
+                    0 => "Increment",
+                    2 => "Decrement",
+                    _ => "Fixed",
+                },
+                &format!("${:02X}:{:04X}", a_addr >> 16, a_addr & 0xffff),
+                &b_bus_string(b_addr),
+                &count,
+                &format!("${:02X}:{:02X}{:02X}", r[4], r[9], r[8]),
+                &format!("{}{}", r[10] & 0x7f, if r[10] & 0x80 != 0 { " (repeat)" } else { "" }),
+            ]);
+        }
+
+        // Raw register values on the right
+        set_row_count(&self.regs, 8 * DMA_REG_NAMES.len() + 2);
+        let mut child = self.regs.iter_children(None).unwrap();
+        let mut set_row = |addr: u16, value: u8| {
+            self.regs.set(&child, &[0, 1, 2], &[
+                &format!("${:04X}", addr),
+                &regs::name(addr).unwrap_or(String::new()),
+                &format!("${:02X}", value),
+            ]);
+            self.regs.iter_next(&mut child);
+        };
+        set_row(0x420b, dma.mdmaen);
+        set_row(0x420c, dma.hdmaen);
+        for (ch, r) in dma.channels.iter().enumerate() {
+            for (reg, &value) in r.iter().enumerate() {
+                set_row(0x4300 | (ch as u16) << 4 | reg as u16, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Visualize the register writes HDMA performs on each scanline
+
+use super::Tool;
+use view::RealMainView;
+use util::*;
+use data::{ModelData, VISIBLE_LINES};
+use hdma::HdmaCapture;
+use regs;
+
+use gdk_pixbuf::{Pixbuf, InterpType};
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, ComboBoxText, Image};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+/// Maximum number of registers that can be shown in the table (8 channels with 4 registers each)
+const MAX_TARGETS: usize = 32;
+
+/// Height of the graph in pixels, before scaling
+const GRAPH_HEIGHT: i32 = 128;
+
+struct State {
+    capture: HdmaCapture,
+    /// Column titles of the targets in `capture`
+    names: Vec<String>,
+}
+
+#[derive(Clone)]    //:
+pub struct Hdma {
+    treeview: TreeView,
+    lines: ListStore,
+    summary: Label,
+    graph_reg: ComboBoxText,
+    graph: Image,
+    state: Rc<RefCell<State>>,
+}
+
+impl Hdma {
+    /// Redraws the graph of the register selected in the dropdown box
+    fn update_graph(&self) {
+        let state = self.state.borrow();
+        let target = self.graph_reg.get_active();
+        if target < 0 || target as usize >= state.capture.targets.len() {
+            self.graph.clear();
+            return;
+        }
+        let target = target as usize;
+
+        let held = state.capture.held_values();
+        let max = held.iter().filter_map(|line| line[target]).max().unwrap_or(0).max(1) as i32;
+
+        let width = VISIBLE_LINES as i32;
+        let mut data = vec![0xff; (width * GRAPH_HEIGHT * 3) as usize];
+        for (x, line) in held.iter().enumerate() {
+            let value = match line[target] {
+                Some(value) => value as i32,
+                None => continue,
+            };
+            // Lines with a write are drawn darker than lines that just keep the old value
+            let color = if state.capture.lines[x][target].is_some() {
+                [0x30, 0x60, 0xc0]
+            } else {
+                [0x98, 0xb0, 0xe0]
+            };
+            let height = 1 + value * (GRAPH_HEIGHT - 1) / max;
+            for y in GRAPH_HEIGHT - height..GRAPH_HEIGHT {
+                let i = ((y * width + x as i32) * 3) as usize;
+                data[i..i + 3].copy_from_slice(&color);
+            }
+        }
+
+        let pixbuf = Pixbuf::new_from_vec(data, 0, false, 8, width, GRAPH_HEIGHT, width * 3);
+        let pixbuf = pixbuf.scale_simple(width * 2, GRAPH_HEIGHT * 2, InterpType::Nearest).unwrap();
+        self.graph.set_from_pixbuf(Some(&pixbuf));
+    }
+}
+
+impl Tool for Hdma {
+    fn new() -> Self {
+        let mut types = vec![gtk::Type::U32];   // Scanline
+        types.extend((0..MAX_TARGETS).map(|_| gtk::Type::String));    // Written values (Hex)
+        let model = ListStore::new(&types);
+        Hdma {
+            treeview: TreeView::new_with_model(&model),
+            lines: model,
+            summary: Label::new(None),
+            graph_reg: ComboBoxText::new(),
+            graph: Image::new(),
+            state: Rc::new(RefCell::new(State {
+                capture: HdmaCapture::default(),
+                names: Vec::new(),
+            })),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "HDMA" }
+
+    fn uses_capture(&self) -> bool { true }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        add_text_column(&self.treeview, "Line");
+        for _ in 0..MAX_TARGETS {
+            add_text_column(&self.treeview, "");
+        }
+
+        let graph_box = gtk::Box::new(Orientation::Horizontal, 5);
+        graph_box.set_border_width(5);
+        graph_box.pack_start(&Label::new(Some("Graph:")), false, true, 0);
+        graph_box.pack_start(&self.graph_reg, false, true, 0);
+        graph_box.pack_end(&self.summary, false, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&graph_box, false, true, 0);
+        vbox.pack_start(&self.graph, false, true, 0);
+        vbox.pack_start(&self.treeview, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, _view: Rc<RealMainView>) {
+        let this = self.clone();
+        self.graph_reg.connect_changed(move |_| {
+            this.update_graph();
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let capture = &data.capture.hdma;
+        let names = capture.targets.iter().map(|t| {
+            format!("{}: {}", t.channel, regs::name(t.reg).unwrap_or(format!("${:04X}", t.reg)))
+        }).collect::<Vec<_>>();
+
+        self.summary.set_text(&match capture.targets.len() {
+            0 => "No HDMA channels enabled".to_string(),
+            n => format!("{} registers written by HDMA", n),
+        });
+
+        let columns = self.treeview.get_columns();
+        for (i, column) in columns.iter().skip(1).enumerate() {
+            column.set_visible(i < names.len());
+            if let Some(name) = names.get(i) {
+                column.set_title(name);
+            }
+        }
+
+        set_row_count(&self.lines, capture.lines.len());
+        let cols = (0..names.len().min(MAX_TARGETS) as u32 + 1).collect::<Vec<_>>();
+        for (line, written) in capture.lines.iter().enumerate() {
+            let texts = written.iter().zip(&capture.targets).enumerate().take(MAX_TARGETS).map(|(t, (value, target))| {
+                match *value {
+                    Some(value) if target.bytes > 1 => format!("${:04X}", value),
+                    Some(value) => format!("${:02X}", value),
+                    None if capture.is_unknown(line, t) => "?".to_string(),
+                    None => String::new(),
+                }
+            }).collect::<Vec<_>>();
+            let line = line as u32;
+            let mut values: Vec<&ToValue> = vec![&line];
+            values.extend(texts.iter().map(|t| t as &ToValue));
+
+            let entry = self.lines.iter_nth_child(None, line as i32).expect(&format!("child #{} not found", line));
+            self.lines.set(&entry, &cols, &values);
+        }
+
+        // Swap in the new capture, then update the dropdown box (which triggers a graph redraw)
+        let names_changed = {
+            let mut state = self.state.borrow_mut();
+            state.capture = capture.clone();
+            let changed = state.names != names;
+            state.names = names.clone();
+            changed
+        };
+        if names_changed {
+            let active = self.graph_reg.get_active();
+            self.graph_reg.remove_all();
+            for name in &names {
+                self.graph_reg.append_text(name);
+            }
+            self.graph_reg.set_active(if active >= 0 && (active as usize) < names.len() { active } else { 0 });
+        } else {
+            self.update_graph();
+        }
+    }
+}
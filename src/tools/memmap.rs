@@ -0,0 +1,191 @@
+//! Show the memory map of the bus and translate between bus addresses and ROM file offsets
+
+use super::Tool;
+use view::RealMainView;
+use util::*;
+use data::{ModelData, MemorySpace};
+use memmap::{MapMode, Area};
+use romfile::COPIER_HEADER_LEN;
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, Button, Entry};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+/// Mapping information needed for translating addresses
+struct State {
+    map_mode: MapMode,
+    rom_len: usize,
+    sram_len: usize,
+    /// Size of the copier header in the ROM file
+    header_len: usize,
+}
+
+impl State {
+    /// Describes where a bus address is mapped to
+    fn bus_to_file(&self, addr: u32) -> String {
+        let addr = addr & 0xffffff;
+        let area = self.map_mode.area(addr, self.rom_len, self.sram_len);
+        let bus = format!("${:02X}:{:04X}", addr >> 16, addr & 0xffff);
+        match area {
+            Area::Rom => {
+                let offset = self.map_mode.rom_offset(addr, self.rom_len).unwrap();
+                format!("{} is ROM offset ${:06X}, file offset ${:06X}", bus, offset, offset + self.header_len)
+            }
+            Area::Sram => {
+                let offset = self.map_mode.sram_offset(addr, self.sram_len).unwrap();
+                format!("{} is SRAM offset ${:05X}", bus, offset)
+            }
+            Area::Wram | Area::WramMirror => {
+                format!("{} is WRAM offset ${:05X}", bus, addr as usize & if area == Area::Wram { 0x1ffff } else { 0x1fff })
+            }
+            area => format!("{} is not in ROM ({})", bus, area.name()),
+        }
+    }
+
+    /// Lists the bus addresses a file offset is mapped at
+    fn file_to_bus(&self, file_offset: usize) -> String {
+        if file_offset < self.header_len {
+            return format!("File offset ${:06X} is part of the copier header", file_offset);
+        }
+        let offset = file_offset - self.header_len;
+        if offset >= self.rom_len {
+            return format!("File offset ${:06X} is past the end of the ROM", file_offset);
+        }
+        let mirrors = self.map_mode.rom_mirrors(offset, self.rom_len).iter()
+            .map(|addr| format!("${:02X}:{:04X}", addr >> 16, addr & 0xffff))
+            .collect::<Vec<_>>();
+        let addr = self.map_mode.bus_addr(offset);
+        format!("File offset ${:06X} (ROM offset ${:06X}) is mapped at ${:02X}:{:04X}\nAll mirrors: {}",
+            file_offset, offset, addr >> 16, addr & 0xffff, mirrors.join(", "))
+    }
+}
+
+#[derive(Clone)]    //:
+pub struct MemoryMap {
+    ranges: ListStore,
+    info: Label,
+    input: Entry,
+    to_file: Button,
+    to_bus: Button,
+    result: Label,
+    state: Rc<RefCell<State>>,
+}
+
+impl Tool for MemoryMap {
+    fn new() -> Self {
+        MemoryMap {
+            ranges: ListStore::new(&[
+                gtk::Type::String,  // Banks (Hex)
+                gtk::Type::String,  // Addresses (Hex)
+                gtk::Type::String,  // Contents
+                gtk::Type::String,  // Offsets (Hex)
+            ]),
+            info: Label::new(None),
+            input: Entry::new(),
+            to_file: Button::new_with_label("Bus Address \u{2192} File Offset"),
+            to_bus: Button::new_with_label("File Offset \u{2192} Bus Address"),
+            result: Label::new(None),
+            state: Rc::new(RefCell::new(State {
+                map_mode: MapMode::LoRom,
+                rom_len: 0,
+                sram_len: 0,
+                header_len: 0,
+            })),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Memory Map" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        let treeview = TreeView::new_with_model(&self.ranges);
+        add_text_column(&treeview, "Banks");
+        add_text_column(&treeview, "Addresses");
+        add_text_column(&treeview, "Contents");
+        add_text_column(&treeview, "Offsets");
+
+        self.input.set_placeholder_text(Some("Address or offset"));
+
+        let control_box = gtk::Box::new(Orientation::Horizontal, 5);
+        control_box.set_border_width(5);
+        control_box.pack_start(&self.input, false, true, 0);
+        control_box.pack_start(&self.to_file, false, true, 0);
+        control_box.pack_start(&self.to_bus, false, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&self.info, false, true, 5);
+        vbox.pack_start(&control_box, false, true, 0);
+        vbox.pack_start(&self.result, false, true, 5);
+        vbox.pack_start(&treeview, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let this = self.clone();
+        let v = view.clone();
+        self.to_file.connect_clicked(move |_| {
+            let text = this.input.get_text().unwrap_or(String::new());
+            match parse_hex(&text) {
+                Some(addr) => this.result.set_text(&this.state.borrow().bus_to_file(addr)),
+                None => v.error(&format!("Invalid address '{}'", text)),
+            }
+        });
+
+        let this = self.clone();
+        self.to_bus.connect_clicked(move |_| {
+            let text = this.input.get_text().unwrap_or(String::new());
+            match parse_hex(&text) {
+                Some(offset) => this.result.set_text(&this.state.borrow().file_to_bus(offset as usize)),
+                None => view.error(&format!("Invalid offset '{}'", text)),
+            }
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let mut state = self.state.borrow_mut();
+        *state = State {
+            map_mode: data.map_mode,
+            rom_len: data.rom.len(),
+            sram_len: data.memory(MemorySpace::Sram).len(),
+            header_len: if data.rom_source.copier_header { COPIER_HEADER_LEN } else { 0 },
+        };
+
+        self.info.set_text(&format!("{:?}, {} bytes ROM, {} bytes SRAM, {}",
+            state.map_mode, state.rom_len, state.sram_len,
+            if state.header_len == 0 {
+                "no copier header".to_string()
+            } else {
+                format!("file offsets include a {} byte copier header", state.header_len)
+            }));
+
+        let map = state.map_mode.bus_map(state.rom_len, state.sram_len);
+        set_row_count(&self.ranges, map.len());
+        for (row, range) in map.iter().enumerate() {
+            let first = (range.first_bank as u32) << 16 | range.start as u32;
+            let last = (range.last_bank as u32) << 16 | range.end as u32;
+            let offsets = match range.area {
+                Area::Rom => format!("File ${:06X}-${:06X}",
+                    state.map_mode.rom_offset(first, state.rom_len).unwrap() + state.header_len,
+                    state.map_mode.rom_offset(last, state.rom_len).unwrap() + state.header_len),
+                Area::Sram => format!("SRAM ${:05X}-${:05X}",
+                    state.map_mode.sram_offset(first, state.sram_len).unwrap(),
+                    state.map_mode.sram_offset(last, state.sram_len).unwrap()),
+                _ => String::new(),
+            };
+            let banks = if range.first_bank == range.last_bank {
+                format!("${:02X}", range.first_bank)
+            } else {
+                format!("${:02X}-${:02X}", range.first_bank, range.last_bank)
+            };
+
+            let entry = self.ranges.iter_nth_child(None, row as i32).expect(&format!("child #{} not found", row));
+            self.ranges.set(&entry, &[0, 1, 2, 3], &[
+                &banks,
+                &format!("${:04X}-${:04X}", range.start, range.end),
+                &range.area.name(),
+                &offsets,
+            ]);
+        }
+    }
+}
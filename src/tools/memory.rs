@@ -0,0 +1,223 @@
+//! Hex view and editor for WRAM, VRAM, OAM, CGRAM, SRAM, ARAM and ROM
+
+use super::Tool;
+use view::{View, RealMainView};
+use util::*;
+use data::{ModelData, MemorySpace, MEMORY_SPACES};
+
+use gtk::{self, ScrolledWindow, Orientation, Label, Button, Entry, ComboBoxText};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+use std::cell::Cell;
+
+/// Number of bytes displayed per row
+const ROW_LEN: usize = 16;
+/// Number of rows displayed per page
+const PAGE_ROWS: usize = 32;
+const PAGE_LEN: usize = ROW_LEN * PAGE_ROWS;
+
+#[derive(Clone)]    //:
+pub struct Memory {
+    space: ComboBoxText,
+    goto: Entry,
+    goto_btn: Button,
+    prev_page: Button,
+    next_page: Button,
+    edit_addr: Entry,
+    edit_data: Entry,
+    write: Button,
+    hex: Label,
+    /// Offset of the first byte on the displayed page
+    page: Rc<Cell<usize>>,
+}
+
+impl Memory {
+    fn selected_space(&self) -> MemorySpace {
+        MEMORY_SPACES[self.space.get_active().max(0) as usize]
+    }
+
+    /// Translates an address typed by the user into an offset into the selected memory space.
+    ///
+    /// WRAM addresses may be given as bus addresses (`$7E:1234`), all other spaces take plain
+    /// offsets.
+    fn parse_offset(&self, text: &str) -> Option<usize> {
+        let addr = match parse_hex(text) {
+            Some(addr) => addr,
+            None => return None,
+        };
+        match self.selected_space() {
+            MemorySpace::Wram => Some(addr as usize & 0x1ffff),
+            _ => Some(addr as usize),
+        }
+    }
+
+    /// Formats an offset into the selected memory space for display
+    fn format_offset(&self, offset: usize) -> String {
+        match self.selected_space() {
+            MemorySpace::Wram => format!("$7{:X}:{:04X}", 0xe + (offset >> 16), offset & 0xffff),
+            MemorySpace::Aram => format!("${:04X}", offset),
+            _ => format!("${:06X}", offset),
+        }
+    }
+}
+
+impl Tool for Memory {
+    fn new() -> Self {
+        let space = ComboBoxText::new();
+        for space_ in MEMORY_SPACES.iter() {
+            space.append_text(space_.name());
+        }
+
+        Memory {
+            space: space,
+            goto: Entry::new(),
+            goto_btn: Button::new_with_label("Go to"),
+            prev_page: Button::new_with_label("<"),
+            next_page: Button::new_with_label(">"),
+            edit_addr: Entry::new(),
+            edit_data: Entry::new(),
+            write: Button::new_with_label("Write"),
+            hex: Label::new(None),
+            page: Rc::new(Cell::new(0)),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Memory" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        self.space.set_active(0);
+        self.goto.set_placeholder_text(Some("Address"));
+        self.edit_addr.set_placeholder_text(Some("Address"));
+        self.edit_data.set_placeholder_text(Some("Bytes (12 34 ...)"));
+        self.hex.set_halign(gtk::Align::Start);
+        self.hex.set_valign(gtk::Align::Start);
+
+        let nav = gtk::Box::new(Orientation::Horizontal, 5);
+        nav.set_border_width(5);
+        nav.pack_start(&self.space, false, true, 0);
+        nav.pack_start(&self.prev_page, false, true, 0);
+        nav.pack_start(&self.next_page, false, true, 0);
+        nav.pack_start(&self.goto, false, true, 0);
+        nav.pack_start(&self.goto_btn, false, true, 0);
+        nav.pack_end(&self.write, false, true, 0);
+        nav.pack_end(&self.edit_data, false, true, 0);
+        nav.pack_end(&self.edit_addr, false, true, 0);
+        nav.pack_end(&Label::new(Some("Edit:")), false, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&nav, false, true, 0);
+        vbox.pack_start(&self.hex, true, true, 5);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let this = self.clone();
+        let v = view.clone();
+        self.space.connect_changed(move |_| {
+            this.page.set(0);
+            // The model might be borrowed if the view is currently being updated
+            if let Ok(mut model) = v.model.try_borrow_mut() {
+                model.refresh();
+            }
+        });
+
+        let this = self.clone();
+        let v = view.clone();
+        self.goto_btn.connect_clicked(move |_| {
+            let text = this.goto.get_text().unwrap_or(String::new());
+            match this.parse_offset(&text) {
+                Some(offset) => {
+                    this.page.set(offset / ROW_LEN * ROW_LEN);
+                    v.model.borrow_mut().refresh();
+                }
+                None => v.error(&format!("Invalid address '{}'", text)),
+            }
+        });
+
+        let this = self.clone();
+        let v = view.clone();
+        self.prev_page.connect_clicked(move |_| {
+            let page = this.page.get();
+            this.page.set(if page >= PAGE_LEN { page - PAGE_LEN } else { 0 });
+            v.model.borrow_mut().refresh();
+        });
+
+        let this = self.clone();
+        let v = view.clone();
+        self.next_page.connect_clicked(move |_| {
+            // The upper bound is enforced when the page is rendered
+            this.page.set(this.page.get() + PAGE_LEN);
+            v.model.borrow_mut().refresh();
+        });
+
+        let this = self.clone();
+        self.write.connect_clicked(move |_| {
+            let space = this.selected_space();
+            if space == MemorySpace::Rom {
+                return view.error("The ROM can't be modified");
+            }
+
+            let addr_text = this.edit_addr.get_text().unwrap_or(String::new());
+            let offset = match this.parse_offset(&addr_text) {
+                Some(offset) => offset,
+                None => return view.error(&format!("Invalid address '{}'", addr_text)),
+            };
+            let data_text = this.edit_data.get_text().unwrap_or(String::new());
+            let bytes = data_text.split_whitespace().map(|b| parse_hex(b)).collect::<Vec<_>>();
+            if bytes.is_empty() || bytes.iter().any(|b| b.map_or(true, |b| b > 0xff)) {
+                return view.error(&format!("Invalid data '{}'", data_text));
+            }
+
+            let bytes = bytes.into_iter().map(|b| b.unwrap() as u8).collect::<Vec<_>>();
+            view.model.borrow_mut().poke_memory(space, offset, &bytes);
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let space = self.selected_space();
+        let mem = data.memory(space);
+        let prev = data.prev_memory.map(|prev| prev.get(space))
+                                   .and_then(|prev| if prev.len() == mem.len() { Some(prev) } else { None });
+
+        if mem.is_empty() {
+            self.hex.set_text(&format!("{} is empty", space.name()));
+            return;
+        }
+
+        let last_page = (mem.len() - 1) / PAGE_LEN * PAGE_LEN;
+        if self.page.get() > last_page {
+            self.page.set(last_page);
+        }
+        let start = self.page.get();
+        let end = (start + PAGE_LEN).min(mem.len());
+
+        let mut markup = String::from("<tt>");
+        for row in (0..(end - start + ROW_LEN - 1) / ROW_LEN).map(|r| start + r * ROW_LEN) {
+            markup.push_str(&self.format_offset(row));
+            markup.push_str(" ");
+            let row_end = (row + ROW_LEN).min(end);
+            for i in row..row_end {
+                // Highlight bytes that changed during the last step
+                if prev.map_or(false, |prev| prev[i] != mem[i]) {
+                    markup.push_str(&format!(" <span background=\"{}\">{:02X}</span>", HIGHLIGHT_COLOR, mem[i]));
+                } else {
+                    markup.push_str(&format!(" {:02X}", mem[i]));
+                }
+            }
+            for _ in row_end..row + ROW_LEN {
+                markup.push_str("   ");
+            }
+
+            let ascii = mem[row..row_end].iter().map(|&b| {
+                if b >= 0x20 && b < 0x7f { b as char } else { '.' }
+            }).collect::<String>();
+            markup.push_str("  ");
+            markup.push_str(&escape_markup(&ascii));
+            markup.push('\n');
+        }
+        markup.push_str("</tt>");
+
+        self.hex.set_markup(&markup);
+    }
+}
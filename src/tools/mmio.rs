@@ -0,0 +1,163 @@
+//! Log of CPU accesses to I/O registers during the rendered frame
+
+use super::Tool;
+use view::RealMainView;
+use util::*;
+use data::ModelData;
+use mmio::MmioAccess;
+use regs;
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, ComboBoxText};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+/// Maximum number of accesses to list
+const MAX_ROWS: usize = 5000;
+
+struct State {
+    log: Vec<MmioAccess>,
+    /// Registers accessed during the frame, sorted by address. Index `i` in the filter dropdown box
+    /// selects register `i - 1` (index 0 shows all registers).
+    regs: Vec<u16>,
+    /// Address and label of the accessing instruction of each access in `log`
+    pcs: Vec<String>,
+}
+
+#[derive(Clone)]    //:
+pub struct MmioLog {
+    accesses: ListStore,
+    filter: ComboBoxText,
+    count: Label,
+    state: Rc<RefCell<State>>,
+}
+
+impl MmioLog {
+    /// Fills the list with the accesses matching the filter
+    fn update_list(&self) {
+        let state = self.state.borrow();
+        let filter = match self.filter.get_active() {
+            i if i > 0 => state.regs.get(i as usize - 1).cloned(),
+            _ => None,
+        };
+
+        let matching = state.log.iter().zip(&state.pcs)
+            .filter(|&(access, _)| filter.map_or(true, |reg| access.reg == reg))
+            .collect::<Vec<_>>();
+        let shown = &matching[..matching.len().min(MAX_ROWS)];
+        self.count.set_text(&if matching.len() > shown.len() {
+            format!("{} accesses ({} shown)", matching.len(), shown.len())
+        } else {
+            format!("{} accesses", matching.len())
+        });
+
+        set_row_count(&self.accesses, shown.len());
+        for (row, &(access, pc)) in shown.iter().enumerate() {
+            let entry = self.accesses.iter_nth_child(None, row as i32).expect(&format!("child #{} not found", row));
+            self.accesses.set(&entry, &[0, 1, 2, 3, 4, 5, 6], &[
+                &(access.line as u32),
+                &(access.h as u32),
+                pc,
+                &if access.write { "W" } else { "R" },
+                &format!("${:04X}", access.reg),
+                &regs::name(access.reg).unwrap_or(String::new()),
+                &access.value.map(|v| format!("${:02X}", v)).unwrap_or("?".to_string()),
+            ]);
+        }
+    }
+}
+
+impl Tool for MmioLog {
+    fn new() -> Self {
+        MmioLog {
+            accesses: ListStore::new(&[
+                gtk::Type::U32,     // Scanline
+                gtk::Type::U32,     // H position
+                gtk::Type::String,  // PC (Hex)
+                gtk::Type::String,  // Read/Write
+                gtk::Type::String,  // Register address (Hex)
+                gtk::Type::String,  // Register name
+                gtk::Type::String,  // Value (Hex)
+            ]),
+            filter: combo_box_text(&["All registers"]),
+            count: Label::new(None),
+            state: Rc::new(RefCell::new(State {
+                log: Vec::new(),
+                regs: Vec::new(),
+                pcs: Vec::new(),
+            })),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "MMIO Log" }
+
+    fn uses_capture(&self) -> bool { true }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        let treeview = TreeView::new_with_model(&self.accesses);
+        add_text_column(&treeview, "Line");
+        add_text_column(&treeview, "H");
+        add_text_column(&treeview, "PC");
+        add_text_column(&treeview, "R/W");
+        add_text_column(&treeview, "Address");
+        add_text_column(&treeview, "Register");
+        add_text_column(&treeview, "Value");
+
+        self.filter.set_active(0);
+
+        let filter_box = gtk::Box::new(Orientation::Horizontal, 5);
+        filter_box.set_border_width(5);
+        filter_box.pack_start(&Label::new(Some("Register:")), false, true, 0);
+        filter_box.pack_start(&self.filter, false, true, 0);
+        filter_box.pack_end(&self.count, false, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&filter_box, false, true, 0);
+        vbox.pack_start(&treeview, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, _view: Rc<RealMainView>) {
+        let this = self.clone();
+        self.filter.connect_changed(move |_| {
+            this.update_list();
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let mut regs = data.capture.mmio.iter().map(|access| access.reg).collect::<Vec<_>>();
+        regs.sort();
+        regs.dedup();
+
+        // Keep filtering by the same register, if it was accessed again
+        let (regs_changed, selected) = {
+            let mut state = self.state.borrow_mut();
+            state.log = data.capture.mmio.clone();
+            state.pcs = data.capture.mmio.iter().map(|access| data.symbols.format_addr(access.pc)).collect();
+            let selected = match self.filter.get_active() {
+                i if i > 0 => state.regs.get(i as usize - 1).cloned(),
+                _ => None,
+            };
+            let changed = state.regs != regs;
+            state.regs = regs.clone();
+            (changed, selected)
+        };
+
+        if regs_changed {
+            // Rebuilding the dropdown box triggers `update_list`
+            self.filter.remove_all();
+            self.filter.append_text("All registers");
+            for &reg in &regs {
+                self.filter.append_text(&match regs::name(reg) {
+                    Some(name) => format!("${:04X} {}", reg, name),
+                    None => format!("${:04X}", reg),
+                });
+            }
+            let index = selected.and_then(|reg| regs.iter().position(|&r| r == reg)).map_or(0, |i| i + 1);
+            self.filter.set_active(index as i32);
+        } else {
+            self.update_list();
+        }
+    }
+}
@@ -1,8 +1,29 @@
 //! Tools manage tabs. They'll usually display some information about the emulator state.
 
+mod apu;
+mod breakpoints;
+mod callstack;
+mod cartridge;
+mod cdl;
 mod cgram;
+mod cheats;
+mod disassembly;
+mod dma;
+mod hdma;
+mod memmap;
+mod memory;
+mod mmio;
 mod oam;
 mod ppuregs;
+mod profiler;
+mod ramsearch;
+mod recording;
+mod samples;
+mod sram;
+mod symbols;
+mod timeline;
+mod trace;
+mod watches;
 
 use view::RealMainView;
 use data::ModelData;
@@ -18,6 +39,27 @@ thread_local! {
         cons_tool::<ppuregs::PpuRegs>,
         cons_tool::<oam::Oam>,
         cons_tool::<cgram::Cgram>,
+        cons_tool::<dma::Dma>,
+        cons_tool::<hdma::Hdma>,
+        cons_tool::<mmio::MmioLog>,
+        cons_tool::<timeline::Timeline>,
+        cons_tool::<apu::Apu>,
+        cons_tool::<samples::Samples>,
+        cons_tool::<recording::Recording>,
+        cons_tool::<disassembly::Disassembly>,
+        cons_tool::<symbols::Symbols>,
+        cons_tool::<breakpoints::Breakpoints>,
+        cons_tool::<callstack::CallStack>,
+        cons_tool::<trace::Trace>,
+        cons_tool::<profiler::Profiler>,
+        cons_tool::<cdl::CodeDataLog>,
+        cons_tool::<memory::Memory>,
+        cons_tool::<sram::Sram>,
+        cons_tool::<memmap::MemoryMap>,
+        cons_tool::<ramsearch::RamSearch>,
+        cons_tool::<watches::Watches>,
+        cons_tool::<cheats::Cheats>,
+        cons_tool::<cartridge::Cartridge>,
     ]
 }
 
@@ -27,4 +69,8 @@ pub trait Tool {
     fn init_tab(&mut self, win: &ScrolledWindow);
     fn connect_events(&mut self, view: Rc<RealMainView>);
     fn update_model_data(&mut self, data: &ModelData);
+
+    /// Whether the tool shows `ModelData::capture`. Capturing a frame is slow, so it's only done
+    /// while such a tool is visible.
+    fn uses_capture(&self) -> bool { false }
 }
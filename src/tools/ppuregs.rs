@@ -1,21 +1,34 @@
 //! View PPU register values (broken down to the individual bit)
+//!
+//! Values can be shown as they were at the start of any visible scanline of the rendered frame, or
+//! at the end of the frame.
 
 use super::Tool;
 use view::RealMainView;
 use util::*;
-use data::ModelData;
-
-use breeze_core::ppu::Ppu;
+use data::{ModelData, PpuRegValues, PPU_REGS, VISIBLE_LINES};
+use regs;
 
 use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Frame, CheckButton, ComboBoxText,
     Label};
 use gtk::prelude::*;
 
 use std::rc::Rc;
+use std::cell::RefCell;
+
+struct State {
+    /// Register values after the rendered frame
+    end_of_frame: PpuRegValues,
+    /// Register values at the start of each visible scanline
+    lines: Vec<Option<PpuRegValues>>,
+}
 
 #[derive(Clone)]    //:
 pub struct PpuRegs {
     regs: ListStore,
+    lines: ListStore,
+    line_view: TreeView,
+    state: Rc<RefCell<State>>,
     fblank: CheckButton,
     brightness: gtk::Scale,
     objsize: ComboBoxText,
@@ -164,6 +177,161 @@ impl PpuRegs {
 
         frame
     }
+
+    /// Shows the register values of the selected scanline
+    fn show_selected(&self) {
+        let row = self.line_view.get_selection().get_selected_rows().0.first()
+            .map(|row| row.get_indices()[0] as usize)
+            .unwrap_or(0);
+        let state = self.state.borrow();
+
+        // Row 0 is the end of the frame, row `n` is scanline `n - 1`
+        let line = row.checked_sub(1);
+        match line.and_then(|line| state.lines.get(line)).and_then(|values| values.as_ref()) {
+            Some(values) => {
+                let prev = line.unwrap().checked_sub(1).and_then(|prev| state.lines[prev].as_ref());
+                self.show_values(values, prev);
+            }
+            None => self.show_values(&state.end_of_frame, None),
+        }
+    }
+
+    /// Updates all widgets with the given register values. Registers whose value differs from
+    /// `prev` are highlighted.
+    fn show_values(&self, values: &PpuRegValues, prev: Option<&PpuRegValues>) {
+        let inidisp = values.get(0x2100);
+        let fblank = inidisp & 0x80 != 0;
+        let brightness = inidisp & 0x0f;
+        self.fblank.set_active(fblank);
+        self.brightness.set_value(brightness as f64);
+
+        let obsel = values.get(0x2101);
+        self.objsize.set_active(((obsel & 0b11100000) >> 5) as i32);
+
+        let bgmode = values.get(0x2105);
+        self.bgmode.set_active((bgmode & 0b111) as i32);
+        for bg in 1..5 {
+            self.bg_tilesizes[bg - 1].set_active(if bgmode & 0x80 << bg == 0 { 0 } else { 1 });
+        }
+
+        let mosaic = values.get(0x2106);
+        self.mosaicsize.set_active(((mosaic & 0xf0) >> 4) as i32);
+        for i in 0..4 {
+            self.mosaicbgs[i].set_active(mosaic & (1 << i) != 0);
+        }
+
+        let m7sel = values.get(0x211a);
+        self.m7_large.set_active(m7sel & 0x80 != 0);
+        self.m7_fill_chr_0.set_active(m7sel & 0x40 != 0);
+        self.m7_mirror_x.set_active(m7sel & 0x01 != 0);
+        self.m7_mirror_y.set_active(m7sel & 0x02 != 0);
+
+        let w12sel = values.get(0x2123);
+        for i in 0..8 {
+            self.w12sel[i].set_active(w12sel & (1 << i) != 0);
+        }
+
+        let w34sel = values.get(0x2124);
+        for i in 0..8 {
+            self.w34sel[i].set_active(w34sel & (1 << i) != 0);
+        }
+
+        let wobjsel = values.get(0x2125);
+        for i in 0..8 {
+            self.wobjsel[i].set_active(wobjsel & (1 << i) != 0);
+        }
+
+        self.wh0.set_value(values.get(0x2126) as f64);
+        self.wh1.set_value(values.get(0x2127) as f64);
+        self.wh2.set_value(values.get(0x2128) as f64);
+        self.wh3.set_value(values.get(0x2129) as f64);
+
+        let tm = values.get(0x212c);
+        for i in 0..5 {
+            self.tm[i].set_active(tm & (1 << i) != 0);
+        }
+
+        let ts = values.get(0x212d);
+        for i in 0..5 {
+            self.ts[i].set_active(ts & (1 << i) != 0);
+        }
+
+        let tmw = values.get(0x212e);
+        for i in 0..5 {
+            self.tmw[i].set_active(tmw & (1 << i) != 0);
+        }
+
+        let tsw = values.get(0x212f);
+        for i in 0..5 {
+            self.tsw[i].set_active(tsw & (1 << i) != 0);
+        }
+
+        let cgwsel = values.get(0x2130);
+        let cgw_clip = cgwsel >> 6;
+        let cgw_prevent = (cgwsel >> 4) & 0b11;
+        let cgw_subscreen = cgwsel & 0x02 != 0;
+        let cgw_direct_color = cgwsel & 0x01 != 0;
+        self.cgw_clip.set_active(cgw_clip as i32);
+        self.cgw_prevent.set_active(cgw_prevent as i32);
+        self.cgw_subscreen.set_active(cgw_subscreen);
+        self.cgw_direct_color.set_active(cgw_direct_color);
+
+        let cgadsub = values.get(0x2131);
+        let add_subtract = cgadsub >> 7;
+        let half_math = (cgadsub >> 6) & 1 != 0;
+        self.math_add_sub.set_active(add_subtract as i32);
+        self.half_math.set_active(half_math);
+        for i in 0..6 {
+            self.color_math[i].set_active(cgadsub & (1 << i) != 0);
+        }
+
+        // Update raw register values on the right
+        set_row_count(&self.regs, PPU_REGS.len());
+        let mut child = self.regs.iter_children(None).unwrap();
+        for (i, &(addr, reg)) in PPU_REGS.iter().enumerate() {
+            let value = values.0[i];
+            let changed = prev.map_or(false, |prev| prev.0[i] != value);
+            self.regs.set(&child, &[0, 1, 2, 3], &[
+                &format!("${:04X}", addr),
+                &regs::name(addr).unwrap_or(String::new()),
+                &reg.format(value),
+                &changed,
+            ]);
+
+            self.regs.iter_next(&mut child);
+        }
+    }
+
+    /// Fills the scanline list, highlighting lines where register values changed
+    fn update_lines(&self) {
+        let state = self.state.borrow();
+        set_row_count(&self.lines, VISIBLE_LINES as usize + 1);
+
+        let mut child = self.lines.iter_children(None).unwrap();
+        self.lines.set(&child, &[0, 1, 2], &[&"End of frame", &"", &false]);
+        for (line, values) in state.lines.iter().enumerate() {
+            self.lines.iter_next(&mut child);
+
+            let prev = line.checked_sub(1).and_then(|prev| state.lines[prev].as_ref());
+            let changes = match (values.as_ref(), prev) {
+                (None, _) => "(not captured)".to_string(),
+                (Some(values), Some(prev)) => {
+                    PPU_REGS.iter().enumerate()
+                        .filter(|&(i, _)| values.0[i] != prev.0[i])
+                        .map(|(_, &(addr, _))| regs::name(addr).unwrap_or(String::new()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                }
+                (Some(_), None) => String::new(),
+            };
+            let changed = values.is_some() && prev.is_some() && !changes.is_empty();
+            self.lines.set(&child, &[0, 1, 2], &[
+                &line.to_string(),
+                &changes,
+                &changed,
+            ]);
+        }
+    }
 }
 
 impl Tool for PpuRegs {
@@ -186,12 +354,25 @@ impl Tool for PpuRegs {
             ]));
         }
 
+        let lines = ListStore::new(&[
+            gtk::Type::String,  // Scanline
+            gtk::Type::String,  // Changed registers
+            gtk::Type::Bool,    // Any register changed
+        ]);
+
         PpuRegs {
             regs: ListStore::new(&[
                 gtk::Type::String,  // Address (Hex `u16`)
                 gtk::Type::String,  // Name
                 gtk::Type::String,  // Raw value (Hex `u8`)
+                gtk::Type::Bool,    // Changed since the previous scanline
             ]),
+            lines: lines.clone(),
+            line_view: TreeView::new_with_model(&lines),
+            state: Rc::new(RefCell::new(State {
+                end_of_frame: PpuRegValues::default(),
+                lines: Vec::new(),
+            })),
             fblank: CheckButton::new_with_label("Forced Blank"),
             brightness: gtk::Scale::new_with_range(Orientation::Horizontal, 0.0, 15.0, 1.0),
             objsize: combo_box_text(&[
@@ -253,6 +434,8 @@ impl Tool for PpuRegs {
 
     fn get_name(&self) -> &'static str { "PPU Regs" }
 
+    fn uses_capture(&self) -> bool { true }
+
     fn init_tab(&mut self, win: &ScrolledWindow) {
         let left_column = gtk::Box::new(Orientation::Vertical, 5);
         left_column.set_border_width(5);
@@ -284,13 +467,20 @@ impl Tool for PpuRegs {
         left_column.pack_start(&self.cgadsub_frame(), false, true, 0);
 
         let treeview = TreeView::new_with_model(&self.regs);
-        add_text_column(&treeview, "Addr");
-        add_text_column(&treeview, "Name");
-        add_text_column(&treeview, "Raw");
+        add_highlighted_text_column(&treeview, "Addr", 3);
+        add_highlighted_text_column(&treeview, "Name", 3);
+        add_highlighted_text_column(&treeview, "Raw", 3);
+
+        add_highlighted_text_column(&self.line_view, "Line", 2);
+        add_highlighted_text_column(&self.line_view, "Changed", 2);
+
+        let right_column = gtk::Paned::new(Orientation::Vertical);
+        right_column.pack1(&self.line_view, true, true);
+        right_column.pack2(&treeview, true, true);
 
         let hbox = gtk::Paned::new(Orientation::Horizontal);
         hbox.pack1(&left_column, true, true);
-        hbox.pack2(&treeview, false, true);
+        hbox.pack2(&right_column, false, true);
         win.add(&hbox);
     }
 
@@ -313,135 +503,20 @@ impl Tool for PpuRegs {
         self.math_add_sub.set_sensitive(false);
         for x in &self.mosaicbgs { x.set_sensitive(false); }
         for x in &self.bg_tilesizes { x.set_sensitive(false); }
+
+        let this = self.clone();
+        self.line_view.get_selection().connect_changed(move |_| {
+            this.show_selected();
+        });
     }
 
     fn update_model_data(&mut self, data: &ModelData) {
-        let inidisp = data.ppu.inidisp();
-        let fblank = inidisp & 0x80 != 0;
-        let brightness = inidisp & 0x0f;
-        self.fblank.set_active(fblank);
-        self.brightness.set_value(brightness as f64);
-
-        let obsel = data.ppu.obsel();
-        self.objsize.set_active(((obsel & 0b11100000) >> 5) as i32);
-
-        let bgmode = data.ppu.bgmode();
-        self.bgmode.set_active((bgmode & 0b111) as i32);
-        for bg in 1..5 {
-            self.bg_tilesizes[bg - 1].set_active(if bgmode & 0x80 << bg == 0 { 0 } else { 1 });
-        }
-
-        let mosaic = data.ppu.mosaic();
-        self.mosaicsize.set_active(((mosaic & 0xf0) >> 4) as i32);
-        for i in 0..4 {
-            self.mosaicbgs[i].set_active(mosaic & (1 << i) != 0);
-        }
-
-        let m7sel = data.ppu.m7sel();
-        self.m7_large.set_active(m7sel & 0x80 != 0);
-        self.m7_fill_chr_0.set_active(m7sel & 0x40 != 0);
-        self.m7_mirror_x.set_active(m7sel & 0x01 != 0);
-        self.m7_mirror_y.set_active(m7sel & 0x02 != 0);
-
-        let w12sel = data.ppu.w12sel();
-        for i in 0..8 {
-            self.w12sel[i].set_active(w12sel & (1 << i) != 0);
-        }
-
-        let w34sel = data.ppu.w34sel();
-        for i in 0..8 {
-            self.w34sel[i].set_active(w34sel & (1 << i) != 0);
-        }
-
-        let wobjsel = data.ppu.wobjsel();
-        for i in 0..8 {
-            self.wobjsel[i].set_active(wobjsel & (1 << i) != 0);
-        }
-
-        self.wh0.set_value(data.ppu.wh0() as f64);
-        self.wh1.set_value(data.ppu.wh1() as f64);
-        self.wh2.set_value(data.ppu.wh2() as f64);
-        self.wh3.set_value(data.ppu.wh3() as f64);
-
-        let tm = data.ppu.tm();
-        for i in 0..5 {
-            self.tm[i].set_active(tm & (1 << i) != 0);
-        }
-
-        let ts = data.ppu.ts();
-        for i in 0..5 {
-            self.ts[i].set_active(ts & (1 << i) != 0);
-        }
-
-        let tmw = data.ppu.tmw();
-        for i in 0..5 {
-            self.tmw[i].set_active(tmw & (1 << i) != 0);
-        }
-
-        let tsw = data.ppu.tsw();
-        for i in 0..5 {
-            self.tsw[i].set_active(tsw & (1 << i) != 0);
-        }
-
-        let cgwsel = data.ppu.cgwsel();
-        let cgw_clip = cgwsel >> 6;
-        let cgw_prevent = (cgwsel >> 4) & 0b11;
-        let cgw_subscreen = cgwsel & 0x02 != 0;
-        let cgw_direct_color = cgwsel & 0x01 != 0;
-        self.cgw_clip.set_active(cgw_clip as i32);
-        self.cgw_prevent.set_active(cgw_prevent as i32);
-        self.cgw_subscreen.set_active(cgw_subscreen);
-        self.cgw_direct_color.set_active(cgw_direct_color);
-
-        let cgadsub = data.ppu.cgadsub();
-        let add_subtract = cgadsub >> 7;
-        let half_math = (cgadsub >> 6) & 1 != 0;
-        self.math_add_sub.set_active(add_subtract as i32);
-        self.half_math.set_active(half_math);
-        for i in 0..6 {
-            self.color_math[i].set_active(cgadsub & (1 << i) != 0);
-        }
-
-        // Update raw register values on the right
-        static RAW_REGS: &'static [(u16, &'static str, fn(&Ppu) -> u8)] = &[
-            (0x2100, "INIDISP", Ppu::inidisp),
-            (0x2101, "OBSEL", Ppu::obsel),
-            (0x2105, "BGMODE", Ppu::bgmode),
-            (0x2106, "MOSAIC", Ppu::mosaic),
-            (0x211a, "M7SEL", Ppu::m7sel),
-            (0x2123, "W12SEL", Ppu::w12sel),
-            (0x2124, "W34SEL", Ppu::w34sel),
-            (0x2125, "WOBJSEL", Ppu::wobjsel),
-            (0x2126, "WH0", Ppu::wh0),
-            (0x2127, "WH1", Ppu::wh1),
-            (0x2128, "WH2", Ppu::wh2),
-            (0x2129, "WH3", Ppu::wh3),
-            (0x212a, "WBGLOG", Ppu::wbglog),
-            (0x212b, "WOBJLOG", Ppu::wobjlog),
-            (0x212c, "TM", Ppu::tm),
-            (0x212d, "TS", Ppu::ts),
-            (0x212e, "TMW", Ppu::tmw),
-            (0x212f, "TSW", Ppu::tsw),
-            (0x2130, "CGWSEL", Ppu::cgwsel),
-            (0x2131, "CGADSUB", Ppu::cgadsub),
-            (0x2133, "SETINI", Ppu::setini),
-        ];
-
-        let entry_count = self.regs.iter_n_children(None) as usize;
-        for _ in entry_count..RAW_REGS.len() {
-            self.regs.append();
-        }
-
-        let mut child = self.regs.iter_children(None).unwrap();
-        for &(addr, name, fun) in RAW_REGS {
-            let value = fun(&data.ppu);
-            self.regs.set(&child, &[0, 1, 2], &[
-                &format!("${:04X}", addr),
-                &name,
-                &format!("${:02X}", value),
-            ]);
-
-            self.regs.iter_next(&mut child);
+        {
+            let mut state = self.state.borrow_mut();
+            state.end_of_frame = PpuRegValues::new(data.ppu);
+            state.lines = data.capture.ppu.clone();
         }
+        self.update_lines();
+        self.show_selected();
     }
 }
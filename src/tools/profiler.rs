@@ -0,0 +1,183 @@
+//! Find out where the CPU spends its time
+
+use super::Tool;
+use view::RealMainView;
+use util::*;
+use data::ModelData;
+use profiler::Profile;
+use symbols::SymbolTable;
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, Button, SpinButton};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+
+/// Maximum number of addresses to list
+const MAX_ROWS: usize = 1000;
+
+#[derive(Clone)]    //:
+pub struct Profiler {
+    frames: SpinButton,
+    start: Button,
+    summary: Label,
+    addrs: ListStore,
+    subs: ListStore,
+    frame_times: ListStore,
+}
+
+/// Makes the columns of `tree_view` sortable. Column `i` is sorted by model column `sort_cols[i]`.
+fn make_sortable(tree_view: &TreeView, sort_cols: &[i32]) {
+    for (column, &sort_col) in tree_view.get_columns().iter().zip(sort_cols) {
+        column.set_sort_column_id(sort_col);
+    }
+}
+
+fn percent(part: u64, total: u64) -> String {
+    format!("{:.2}%", part as f64 * 100.0 / total.max(1) as f64)
+}
+
+impl Profiler {
+    fn show_profile(&self, profile: &Profile, symbols: &SymbolTable) {
+        let total = profile.cycles;
+
+        let addrs = &profile.addrs[..profile.addrs.len().min(MAX_ROWS)];
+        set_row_count(&self.addrs, addrs.len());
+        for (row, &(addr, stats)) in addrs.iter().enumerate() {
+            let entry = self.addrs.iter_nth_child(None, row as i32).expect(&format!("child #{} not found", row));
+            self.addrs.set(&entry, &[0, 1, 2, 3, 4], &[
+                &symbols.format_addr(addr),
+                &stats.cycles,
+                &percent(stats.cycles, total),
+                &stats.count,
+                &(stats.cycles / stats.count.max(1)),
+            ]);
+        }
+
+        set_row_count(&self.subs, profile.subs.len());
+        for (row, &(addr, stats)) in profile.subs.iter().enumerate() {
+            let entry = self.subs.iter_nth_child(None, row as i32).expect(&format!("child #{} not found", row));
+            self.subs.set(&entry, &[0, 1, 2, 3, 4], &[
+                &symbols.format_addr(addr),
+                &stats.calls,
+                &stats.cycles,
+                &percent(stats.cycles, total),
+                &(stats.cycles / stats.calls.max(1)),
+            ]);
+        }
+
+        set_row_count(&self.frame_times, profile.frame_times.len());
+        for (row, frame) in profile.frame_times.iter().enumerate() {
+            let entry = self.frame_times.iter_nth_child(None, row as i32).expect(&format!("child #{} not found", row));
+            self.frame_times.set(&entry, &[0, 1, 2, 3], &[
+                &(row as u32),
+                &frame.busy.map_or("Lag".to_string(), |busy| busy.to_string()),
+                &frame.total,
+                &frame.busy.map_or("100% (lag)".to_string(), |busy| percent(busy, frame.total)),
+            ]);
+        }
+
+        let mut summary = format!("{} frames, {} master cycles", profile.frames, total);
+        if let Some(addr) = profile.wait_loop {
+            summary.push_str(&format!(", NMI wait loop at {}", symbols.format_addr(addr)));
+        }
+        let busy = profile.frame_times.iter().filter_map(|f| f.busy.map(|busy| (busy, f.total))).collect::<Vec<_>>();
+        if !busy.is_empty() {
+            let (busy_cycles, total_cycles) = busy.iter().fold((0, 0), |(b, t), &(busy, total)| (b + busy, t + total));
+            summary.push_str(&format!(", busy {} of the frame on average", percent(busy_cycles, total_cycles)));
+        }
+        let lag = profile.frame_times.len() - busy.len();
+        if lag > 0 {
+            summary.push_str(&format!(", {} lag frames", lag));
+        }
+        self.summary.set_text(&summary);
+    }
+}
+
+impl Tool for Profiler {
+    fn new() -> Self {
+        Profiler {
+            frames: SpinButton::new_with_range(1.0, 3600.0, 1.0),
+            start: Button::new_with_label("Profile"),
+            summary: Label::new(Some("Not profiled yet")),
+            addrs: ListStore::new(&[
+                gtk::Type::String,  // Address
+                gtk::Type::U64,     // Cycles
+                gtk::Type::String,  // Percentage of all cycles
+                gtk::Type::U64,     // Executions
+                gtk::Type::U64,     // Cycles per execution
+            ]),
+            subs: ListStore::new(&[
+                gtk::Type::String,  // Address
+                gtk::Type::U64,     // Calls
+                gtk::Type::U64,     // Cycles (inclusive)
+                gtk::Type::String,  // Percentage of all cycles
+                gtk::Type::U64,     // Cycles per call
+            ]),
+            frame_times: ListStore::new(&[
+                gtk::Type::U32,     // Frame
+                gtk::Type::String,  // Cycles until the wait loop
+                gtk::Type::U64,     // Cycles of the frame
+                gtk::Type::String,  // Busy percentage
+            ]),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Profiler" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        self.frames.set_value(60.0);
+
+        let addr_view = TreeView::new_with_model(&self.addrs);
+        add_text_column(&addr_view, "Address");
+        add_text_column(&addr_view, "Cycles");
+        add_text_column(&addr_view, "%");
+        add_text_column(&addr_view, "Executions");
+        add_text_column(&addr_view, "Cycles/Exec");
+        make_sortable(&addr_view, &[0, 1, 1, 3, 4]);
+
+        let sub_view = TreeView::new_with_model(&self.subs);
+        add_text_column(&sub_view, "Subroutine");
+        add_text_column(&sub_view, "Calls");
+        add_text_column(&sub_view, "Cycles");
+        add_text_column(&sub_view, "%");
+        add_text_column(&sub_view, "Cycles/Call");
+        make_sortable(&sub_view, &[0, 1, 2, 2, 4]);
+
+        let frame_view = TreeView::new_with_model(&self.frame_times);
+        add_text_column(&frame_view, "Frame");
+        add_text_column(&frame_view, "Busy Cycles");
+        add_text_column(&frame_view, "Frame Cycles");
+        add_text_column(&frame_view, "Busy");
+
+        let control_box = gtk::Box::new(Orientation::Horizontal, 5);
+        control_box.set_border_width(5);
+        control_box.pack_start(&Label::new(Some("Frames:")), false, true, 0);
+        control_box.pack_start(&self.frames, false, true, 0);
+        control_box.pack_start(&self.start, false, true, 0);
+        control_box.pack_start(&self.summary, false, true, 0);
+
+        let hot_box = gtk::Box::new(Orientation::Horizontal, 5);
+        hot_box.pack_start(&addr_view, true, true, 0);
+        hot_box.pack_start(&sub_view, true, true, 0);
+        hot_box.pack_start(&frame_view, true, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&control_box, false, true, 0);
+        vbox.pack_start(&hot_box, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let this = self.clone();
+        self.start.connect_clicked(move |_| {
+            let frames = this.frames.get_value_as_int() as u32;
+            view.model.borrow_mut().profile(frames);
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        if let Some(profile) = data.profile {
+            self.show_profile(profile, data.symbols);
+        }
+    }
+}
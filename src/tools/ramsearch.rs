@@ -0,0 +1,301 @@
+//! Search WRAM for game variables and freeze them
+
+use super::Tool;
+use view::{View, RealMainView};
+use util::*;
+use data::{ModelData, MemorySpace};
+use ramsearch::{self, Width, Compare};
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, Button, Entry, ComboBoxText,
+    CheckButton, CellRendererText, TreePath};
+use gtk::prelude::*;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+/// Maximum number of candidates to list
+const MAX_RESULTS: usize = 1000;
+
+static WIDTHS: [Width; 3] = [Width::Byte, Width::Word, Width::Long];
+
+struct State {
+    search: Option<ramsearch::RamSearch>,
+    /// WRAM contents at the last update
+    wram: Vec<u8>,
+    /// User-assigned names of candidates, by WRAM offset
+    names: HashMap<usize, String>,
+    /// WRAM offsets of the listed candidates, by row
+    rows: Vec<usize>,
+    /// WRAM offsets that are currently frozen
+    frozen: Vec<usize>,
+}
+
+#[derive(Clone)]    //:
+pub struct RamSearch {
+    treeview: TreeView,
+    results: ListStore,
+    name_render: CellRendererText,
+    value_render: CellRendererText,
+    width: ComboBoxText,
+    signed: CheckButton,
+    new_search: Button,
+    step: Button,
+    compare: ComboBoxText,
+    value: Entry,
+    filter: Button,
+    freeze: Button,
+    unfreeze: Button,
+    count: Label,
+    state: Rc<RefCell<State>>,
+}
+
+impl RamSearch {
+    /// Returns the WRAM offset of the selected candidate
+    fn selected(&self) -> Option<usize> {
+        let state = self.state.borrow();
+        self.treeview.get_selection().get_selected_rows().0.first()
+            .and_then(|row| state.rows.get(row.get_indices()[0] as usize).cloned())
+    }
+
+    fn read_compare(&self) -> Result<Compare, String> {
+        Ok(match self.compare.get_active() {
+            0 => Compare::Equal,
+            1 => Compare::Changed,
+            2 => Compare::Increased,
+            3 => Compare::Decreased,
+            _ => {
+                let text = self.value.get_text().unwrap_or(String::new());
+                match parse_signed(&text) {
+                    Some(value) => Compare::Value(value),
+                    None => return Err(format!("Invalid value '{}'", text)),
+                }
+            }
+        })
+    }
+}
+
+impl Tool for RamSearch {
+    fn new() -> Self {
+        let model = ListStore::new(&[
+            gtk::Type::String,  // Address (Hex)
+            gtk::Type::String,  // Name
+            gtk::Type::String,  // Value
+            gtk::Type::String,  // Previous value
+            gtk::Type::Bool,    // Frozen
+        ]);
+        RamSearch {
+            treeview: TreeView::new_with_model(&model),
+            results: model,
+            name_render: CellRendererText::new(),
+            value_render: CellRendererText::new(),
+            width: combo_box_text(&[
+                "8-bit",
+                "16-bit",
+                "24-bit",
+            ]),
+            signed: CheckButton::new_with_label("Signed"),
+            new_search: Button::new_with_label("New Search"),
+            step: Button::new_with_label("Emulate Frame"),
+            compare: combo_box_text(&[
+                "Equal to previous",
+                "Changed",
+                "Increased",
+                "Decreased",
+                "Equal to value",
+            ]),
+            value: Entry::new(),
+            filter: Button::new_with_label("Filter"),
+            freeze: Button::new_with_label("Freeze"),
+            unfreeze: Button::new_with_label("Unfreeze"),
+            count: Label::new(Some("No search started")),
+            state: Rc::new(RefCell::new(State {
+                search: None,
+                wram: Vec::new(),
+                names: HashMap::new(),
+                rows: Vec::new(),
+                frozen: Vec::new(),
+            })),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "RAM Search" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        add_text_column(&self.treeview, "Address");
+        add_editable_text_column(&self.treeview, "Name", &self.name_render);
+        add_editable_text_column(&self.treeview, "Value", &self.value_render);
+        add_text_column(&self.treeview, "Previous");
+        add_text_column(&self.treeview, "Frozen");
+
+        self.width.set_active(0);
+        self.compare.set_active(0);
+        self.value.set_placeholder_text(Some("Value"));
+
+        let search_box = gtk::Box::new(Orientation::Horizontal, 5);
+        search_box.set_border_width(5);
+        search_box.pack_start(&self.width, false, true, 0);
+        search_box.pack_start(&self.signed, false, true, 0);
+        search_box.pack_start(&self.new_search, false, true, 0);
+        search_box.pack_start(&self.step, false, true, 0);
+        search_box.pack_end(&self.count, false, true, 0);
+
+        let filter_box = gtk::Box::new(Orientation::Horizontal, 5);
+        filter_box.set_border_width(5);
+        filter_box.pack_start(&self.compare, false, true, 0);
+        filter_box.pack_start(&self.value, false, true, 0);
+        filter_box.pack_start(&self.filter, false, true, 0);
+        filter_box.pack_end(&self.unfreeze, false, true, 0);
+        filter_box.pack_end(&self.freeze, false, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&search_box, false, true, 0);
+        vbox.pack_start(&filter_box, false, true, 0);
+        vbox.pack_start(&self.treeview, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let this = self.clone();
+        let v = view.clone();
+        self.new_search.connect_clicked(move |_| {
+            {
+                let mut state = this.state.borrow_mut();
+                let width = WIDTHS[this.width.get_active().max(0) as usize];
+                let search = ramsearch::RamSearch::new(&state.wram, width, this.signed.get_active());
+                state.search = Some(search);
+            }
+            v.model.borrow_mut().refresh();
+        });
+
+        let v = view.clone();
+        self.step.connect_clicked(move |_| {
+            if v.is_running() {
+                return;
+            }
+            v.model.borrow_mut().step();
+        });
+
+        let this = self.clone();
+        let v = view.clone();
+        self.filter.connect_clicked(move |_| {
+            let cmp = match this.read_compare() {
+                Ok(cmp) => cmp,
+                Err(e) => return v.error(&e),
+            };
+            // The dialog lets the run timer update the tool, so it can't be shown while the state is
+            // borrowed
+            let started = this.state.borrow().search.is_some();
+            if !started {
+                return v.error("No search started");
+            }
+            {
+                let mut state = this.state.borrow_mut();
+                let state = &mut *state;
+                if let Some(ref mut search) = state.search {
+                    search.filter(&state.wram, cmp);
+                }
+            }
+            v.model.borrow_mut().refresh();
+        });
+
+        let this = self.clone();
+        let v = view.clone();
+        self.freeze.connect_clicked(move |_| {
+            let offset = match this.selected() {
+                Some(offset) => offset,
+                None => return,
+            };
+            let bytes = {
+                let state = this.state.borrow();
+                let width = state.search.as_ref().map_or(1, |s| s.width().bytes());
+                state.wram[offset..offset + width].to_vec()
+            };
+            v.model.borrow_mut().freeze(offset, bytes);
+        });
+
+        let this = self.clone();
+        let v = view.clone();
+        self.unfreeze.connect_clicked(move |_| {
+            if let Some(offset) = this.selected() {
+                v.model.borrow_mut().unfreeze(offset);
+            }
+        });
+
+        // Renaming doesn't touch the model, so the row is updated right here
+        let this = self.clone();
+        self.name_render.connect_edited(move |_, path: TreePath, text: &str| {
+            let row = path.get_indices()[0] as usize;
+            let mut state = this.state.borrow_mut();
+            if let Some(&offset) = state.rows.get(row) {
+                state.names.insert(offset, text.to_string());
+                if let Some(entry) = this.results.iter_nth_child(None, row as i32) {
+                    this.results.set(&entry, &[1], &[&text]);
+                }
+            }
+        });
+
+        let this = self.clone();
+        self.value_render.connect_edited(move |_, path: TreePath, text: &str| {
+            let row = path.get_indices()[0] as usize;
+            let (offset, width, frozen) = {
+                let state = this.state.borrow();
+                let offset = match state.rows.get(row) {
+                    Some(&offset) => offset,
+                    None => return,
+                };
+                let width = state.search.as_ref().map_or(Width::Byte, |s| s.width());
+                (offset, width, state.frozen.contains(&offset))
+            };
+            let value = match parse_signed(text) {
+                Some(value) => value,
+                None => return view.error(&format!("Invalid value '{}'", text)),
+            };
+
+            let bytes = ramsearch::value_bytes(value, width);
+            if frozen {
+                view.model.borrow_mut().freeze(offset, bytes);
+            } else {
+                view.model.borrow_mut().poke_memory(MemorySpace::Wram, offset, &bytes);
+            }
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        state.wram.clear();
+        state.wram.extend_from_slice(&data.memory.wram);
+        state.frozen = data.freezes.iter().map(|f| f.offset).collect();
+
+        let search = match state.search {
+            Some(ref search) => search,
+            None => return,
+        };
+
+        let candidates = search.candidates();
+        let shown = &candidates[..candidates.len().min(MAX_RESULTS)];
+        self.count.set_text(&if candidates.len() > shown.len() {
+            format!("{} candidates ({} shown)", candidates.len(), shown.len())
+        } else {
+            format!("{} candidates", candidates.len())
+        });
+
+        set_row_count(&self.results, shown.len());
+        state.rows = shown.to_vec();
+        for (row, &offset) in shown.iter().enumerate() {
+            let value = ramsearch::read_value(&state.wram, offset, search.width(), search.signed());
+            let frozen = state.frozen.contains(&offset);
+            let name = state.names.get(&offset).map(|s| &**s).unwrap_or("");
+
+            let entry = self.results.iter_nth_child(None, row as i32).expect(&format!("child #{} not found", row));
+            self.results.set(&entry, &[0, 1, 2, 3, 4], &[
+                &format!("$7{:X}:{:04X}", 0xe + (offset >> 16), offset & 0xffff),
+                &name,
+                &value.to_string(),
+                &search.previous(offset).to_string(),
+                &frozen,
+            ]);
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Record the audio output to WAV files, and mute or solo voices
+
+use super::Tool;
+use view::RealMainView;
+use util::*;
+use data::ModelData;
+use apu::{VOICES, SAMPLE_RATE};
+
+use gtk::{self, ScrolledWindow, Orientation, Label, Button, CheckButton};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+
+#[derive(Clone)]    //:
+pub struct Recording {
+    record: Button,
+    stop: Button,
+    per_voice: CheckButton,
+    status: Label,
+    mute: Vec<CheckButton>,
+    solo: Vec<CheckButton>,
+}
+
+/// Combines the states of one check button per voice into a bit mask
+fn voice_bits(buttons: &[CheckButton]) -> u8 {
+    buttons.iter().enumerate().fold(0, |bits, (voice, button)| {
+        if button.get_active() { bits | (1 << voice) } else { bits }
+    })
+}
+
+impl Tool for Recording {
+    fn new() -> Self {
+        Recording {
+            record: Button::new_with_label("Record..."),
+            stop: Button::new_with_label("Stop"),
+            per_voice: CheckButton::new_with_label("Write each voice to its own track"),
+            status: Label::new(None),
+            mute: (0..VOICES).map(|_| CheckButton::new_with_label("Mute")).collect(),
+            solo: (0..VOICES).map(|_| CheckButton::new_with_label("Solo")).collect(),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Audio Recording" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        let control_box = gtk::Box::new(Orientation::Horizontal, 5);
+        control_box.set_border_width(5);
+        control_box.pack_start(&self.record, false, true, 0);
+        control_box.pack_start(&self.stop, false, true, 0);
+        control_box.pack_start(&self.per_voice, false, true, 0);
+
+        let voices_frame = gtk::Frame::new(Some("Voices"));
+        let voices_box = gtk::Box::new(Orientation::Vertical, 5);
+        voices_box.set_border_width(5);
+        for voice in 0..VOICES {
+            let hbox = gtk::Box::new(Orientation::Horizontal, 5);
+            hbox.pack_start(&Label::new(Some(&format!("Voice {}", voice))), false, true, 0);
+            hbox.pack_start(&self.mute[voice], false, true, 0);
+            hbox.pack_start(&self.solo[voice], false, true, 0);
+            voices_box.pack_start(&hbox, false, true, 0);
+        }
+        voices_frame.add(&voices_box);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 5);
+        vbox.pack_start(&control_box, false, true, 0);
+        vbox.pack_start(&self.status, false, true, 0);
+        vbox.pack_start(&voices_frame, false, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let this = self.clone();
+        let v = view.clone();
+        self.record.connect_clicked(move |_| {
+            if let Some(path) = choose_file(&v.win, "Record Audio", gtk::FileChooserAction::Save) {
+                let result = v.model.borrow_mut().start_recording(&path, this.per_voice.get_active());
+                if let Err(e) = result {
+                    v.error(&format!("Couldn't start recording to {}: {}", path.display(), e));
+                }
+            }
+        });
+
+        let v = view.clone();
+        self.stop.connect_clicked(move |_| {
+            let result = v.model.borrow_mut().stop_recording();
+            if let Err(e) = result {
+                v.error(&format!("Couldn't finish recording: {}", e));
+            }
+        });
+
+        for button in self.mute.iter().chain(self.solo.iter()) {
+            let this = self.clone();
+            let v = view.clone();
+            button.connect_toggled(move |_| {
+                // The model might be borrowed if the view is currently being updated
+                if let Ok(mut model) = v.model.try_borrow_mut() {
+                    model.set_voice_mute(voice_bits(&this.mute), voice_bits(&this.solo));
+                }
+            });
+        }
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        for voice in 0..VOICES {
+            self.mute[voice].set_active(data.muted_voices & (1 << voice) != 0);
+            self.solo[voice].set_active(data.soloed_voices & (1 << voice) != 0);
+        }
+
+        self.record.set_sensitive(data.recording.is_none());
+        self.stop.set_sensitive(data.recording.is_some());
+        self.status.set_text(&match data.recording {
+            Some(rec) => {
+                let tracks = if rec.per_voice { format!(" and {} voice tracks", VOICES) } else { String::new() };
+                format!("Recording to {}{}: {:.2} s", rec.path.display(), tracks,
+                    rec.frames as f64 / SAMPLE_RATE as f64)
+            }
+            None => match data.recording_error {
+                Some(e) => format!("Recording stopped: {}", e),
+                None => "Not recording".to_string(),
+            },
+        });
+    }
+}
@@ -0,0 +1,162 @@
+//! Browse the BRR samples in the sample directory, play them and export them as WAV files
+
+use super::Tool;
+use view::RealMainView;
+use util::*;
+use data::{ModelData, MemorySpace};
+use apu::{self, VOICES, SAMPLE_RATE};
+use brr::{self, DirEntry};
+use audio;
+use wav;
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, Button};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+/// Minimum length of played samples, in seconds. Looping samples are repeated to reach it.
+const PLAY_SECONDS: usize = 2;
+
+struct State {
+    aram: Vec<u8>,
+    entries: Vec<DirEntry>,
+}
+
+#[derive(Clone)]    //:
+pub struct Samples {
+    treeview: TreeView,
+    samples: ListStore,
+    dir: Label,
+    play: Button,
+    export: Button,
+    state: Rc<RefCell<State>>,
+}
+
+impl Samples {
+    /// Returns the directory entry of the selected row
+    fn selected(&self) -> Option<DirEntry> {
+        let rows = self.treeview.get_selection().get_selected_rows().0;
+        rows.first().and_then(|row| self.state.borrow().entries.get(row.get_indices()[0] as usize).cloned())
+    }
+}
+
+impl Tool for Samples {
+    fn new() -> Self {
+        let model = ListStore::new(&[
+            gtk::Type::String,  // Source number (Hex)
+            gtk::Type::String,  // Start address (Hex)
+            gtk::Type::String,  // Loop address (Hex)
+            gtk::Type::String,  // Length
+            gtk::Type::String,  // Loop start sample
+            gtk::Type::String,  // Voices using the sample
+        ]);
+        Samples {
+            treeview: TreeView::new_with_model(&model),
+            samples: model,
+            dir: Label::new(None),
+            play: Button::new_with_label("Play"),
+            export: Button::new_with_label("Export WAV..."),
+            state: Rc::new(RefCell::new(State {
+                aram: Vec::new(),
+                entries: Vec::new(),
+            })),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Samples" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        add_text_column(&self.treeview, "SRCN");
+        add_text_column(&self.treeview, "Start");
+        add_text_column(&self.treeview, "Loop");
+        add_text_column(&self.treeview, "Length");
+        add_text_column(&self.treeview, "Loop Start");
+        add_text_column(&self.treeview, "Voices");
+
+        let control_box = gtk::Box::new(Orientation::Horizontal, 5);
+        control_box.set_border_width(5);
+        control_box.pack_start(&self.dir, false, true, 0);
+        control_box.pack_end(&self.export, false, true, 0);
+        control_box.pack_end(&self.play, false, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&control_box, false, true, 0);
+        vbox.pack_start(&self.treeview, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let this = self.clone();
+        let v = view.clone();
+        self.play.connect_clicked(move |_| {
+            let entry = match this.selected() {
+                Some(entry) => entry,
+                None => return,
+            };
+            let sample = brr::decode(&this.state.borrow().aram, &entry);
+            let frames = sample.looped(SAMPLE_RATE as usize * PLAY_SECONDS).into_iter().map(|s| (s, s)).collect();
+            if let Err(e) = audio::play(frames) {
+                v.error(&format!("Couldn't play sample: {}", e));
+            }
+        });
+
+        let this = self.clone();
+        self.export.connect_clicked(move |_| {
+            let entry = match this.selected() {
+                Some(entry) => entry,
+                None => return,
+            };
+            let sample = brr::decode(&this.state.borrow().aram, &entry);
+            if let Some(path) = choose_file(&view.win, "Export Sample", gtk::FileChooserAction::Save) {
+                if let Err(e) = wav::write_sample(&path, SAMPLE_RATE, &sample.samples, sample.loop_start) {
+                    view.error(&format!("Couldn't write {}: {}", path.display(), e));
+                }
+            }
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let aram = data.memory(MemorySpace::Aram);
+        let dsp = &data.apu.dsp;
+        let dir = apu::dir_addr(dsp);
+        let entries = brr::dir_entries(aram, dir);
+        self.dir.set_text(&format!("Sample directory at ${:04X}", dir));
+
+        set_row_count(&self.samples, entries.len());
+        for (row, entry) in entries.iter().enumerate() {
+            // Samples are played at pitch $1000 by convention, so their length is given at 32 kHz
+            let length = match brr::block_count(aram, entry.start) {
+                Some(blocks) => {
+                    let samples = blocks * brr::BLOCK_SAMPLES;
+                    format!("{} samples ({:.3} s)", samples, samples as f64 / SAMPLE_RATE as f64)
+                }
+                None => "No end block".to_string(),
+            };
+            let loop_offset = entry.loop_addr.wrapping_sub(entry.start) as usize;
+            let loop_start = if loop_offset % brr::BLOCK_LEN == 0 {
+                format!("{}", loop_offset / brr::BLOCK_LEN * brr::BLOCK_SAMPLES)
+            } else {
+                "-".to_string()
+            };
+            let voices = (0..VOICES).filter(|&voice| apu::voice_reg(dsp, voice, 4) == entry.srcn)
+                                    .map(|voice| voice.to_string())
+                                    .collect::<Vec<_>>()
+                                    .join(", ");
+
+            let iter = self.samples.iter_nth_child(None, row as i32).expect(&format!("child #{} not found", row));
+            self.samples.set(&iter, &[0, 1, 2, 3, 4, 5], &[
+                &format!("${:02X}", entry.srcn),
+                &format!("${:04X}", entry.start),
+                &format!("${:04X}", entry.loop_addr),
+                &length,
+                &loop_start,
+                &voices,
+            ]);
+        }
+
+        let mut state = self.state.borrow_mut();
+        state.aram = aram.to_vec();
+        state.entries = entries;
+    }
+}
@@ -0,0 +1,118 @@
+//! View the cartridge RAM and import or export battery saves
+
+use super::Tool;
+use view::RealMainView;
+use util::*;
+use data::{ModelData, MemorySpace};
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, Button};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+
+/// Number of bytes displayed per row
+const ROW_LEN: usize = 16;
+
+#[derive(Clone)]    //:
+pub struct Sram {
+    rows: ListStore,
+    info: Label,
+    import: Button,
+    export: Button,
+}
+
+impl Tool for Sram {
+    fn new() -> Self {
+        Sram {
+            rows: ListStore::new(&[
+                gtk::Type::String,  // Offset (Hex)
+                gtk::Type::String,  // Bytes (Hex)
+                gtk::Type::String,  // ASCII
+                gtk::Type::Bool,    // Changed during the last step
+            ]),
+            info: Label::new(None),
+            import: Button::new_with_label("Import .srm..."),
+            export: Button::new_with_label("Export .srm..."),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "SRAM" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        let treeview = TreeView::new_with_model(&self.rows);
+        add_text_column(&treeview, "Offset");
+        add_text_column(&treeview, "Data");
+        add_text_column(&treeview, "ASCII");
+        add_text_column(&treeview, "Changed");
+
+        let control_box = gtk::Box::new(Orientation::Horizontal, 5);
+        control_box.set_border_width(5);
+        control_box.pack_start(&self.info, false, true, 0);
+        control_box.pack_end(&self.export, false, true, 0);
+        control_box.pack_end(&self.import, false, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&control_box, false, true, 0);
+        vbox.pack_start(&treeview, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let v = view.clone();
+        self.import.connect_clicked(move |_| {
+            if let Some(path) = choose_file(&v.win, "Import Battery Save", gtk::FileChooserAction::Open) {
+                let result = v.model.borrow_mut().import_sram(&path);
+                if let Err(e) = result {
+                    v.error(&format!("Couldn't import {}: {}", path.display(), e));
+                }
+            }
+        });
+
+        self.export.connect_clicked(move |_| {
+            if let Some(path) = choose_file(&view.win, "Export Battery Save", gtk::FileChooserAction::Save) {
+                let result = view.model.borrow().export_sram(&path);
+                if let Err(e) = result {
+                    view.error(&format!("Couldn't export to {}: {}", path.display(), e));
+                }
+            }
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let sram = data.memory(MemorySpace::Sram);
+        let prev = data.prev_memory.map(|prev| prev.get(MemorySpace::Sram))
+                                   .and_then(|prev| if prev.len() == sram.len() { Some(prev) } else { None });
+
+        let battery = match data.header {
+            Some(header) if header.has_battery() => "battery-backed",
+            Some(_) => "no battery",
+            None => "unknown cartridge type",
+        };
+        self.info.set_text(&if sram.is_empty() {
+            "The cartridge has no RAM".to_string()
+        } else {
+            format!("{} bytes of cartridge RAM ({})", sram.len(), battery)
+        });
+        self.import.set_sensitive(!sram.is_empty());
+        self.export.set_sensitive(!sram.is_empty());
+
+        let rows = (sram.len() + ROW_LEN - 1) / ROW_LEN;
+        set_row_count(&self.rows, rows);
+        for row in 0..rows {
+            let start = row * ROW_LEN;
+            let bytes = &sram[start..(start + ROW_LEN).min(sram.len())];
+            let ascii = bytes.iter().map(|&b| {
+                if b >= 0x20 && b < 0x7f { b as char } else { '.' }
+            }).collect::<String>();
+            let changed = prev.map_or(false, |prev| &prev[start..start + bytes.len()] != bytes);
+
+            let entry = self.rows.iter_nth_child(None, row as i32).expect(&format!("child #{} not found", row));
+            self.rows.set(&entry, &[0, 1, 2, 3], &[
+                &format!("${:05X}", start),
+                &bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" "),
+                &ascii,
+                &changed,
+            ]);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Load symbol files and browse the symbols
+
+use super::Tool;
+use view::{View, RealMainView};
+use util::*;
+use data::ModelData;
+use symbols::SymbolTable;
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, Button, Entry};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::path::PathBuf;
+
+struct State {
+    symbols: SymbolTable,
+    /// Addresses of the listed symbols, by row
+    shown: Vec<u32>,
+    /// Symbol file `symbols` was loaded from
+    path: Option<PathBuf>,
+}
+
+#[derive(Clone)]    //:
+pub struct Symbols {
+    treeview: TreeView,
+    symbols: ListStore,
+    load: Button,
+    clear: Button,
+    search: Entry,
+    run_to: Button,
+    file: Label,
+    state: Rc<RefCell<State>>,
+}
+
+impl Symbols {
+    /// Fills the list with the symbols matching the search text
+    fn update_list(&self) {
+        let search = self.search.get_text().unwrap_or(String::new()).to_lowercase();
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        let matching = state.symbols.symbols().iter()
+            .filter(|symbol| symbol.name.to_lowercase().contains(&search))
+            .collect::<Vec<_>>();
+
+        set_row_count(&self.symbols, matching.len());
+        state.shown.clear();
+        for (row, symbol) in matching.iter().enumerate() {
+            let entry = self.symbols.iter_nth_child(None, row as i32).expect(&format!("child #{} not found", row));
+            self.symbols.set(&entry, &[0, 1], &[
+                &format!("${:02X}:{:04X}", symbol.addr >> 16, symbol.addr & 0xffff),
+                &symbol.name,
+            ]);
+            state.shown.push(symbol.addr);
+        }
+    }
+}
+
+impl Tool for Symbols {
+    fn new() -> Self {
+        let model = ListStore::new(&[
+            gtk::Type::String,  // Address (Hex)
+            gtk::Type::String,  // Name
+        ]);
+        Symbols {
+            treeview: TreeView::new_with_model(&model),
+            symbols: model,
+            load: Button::new_with_label("Load..."),
+            clear: Button::new_with_label("Unload"),
+            search: Entry::new(),
+            run_to: Button::new_with_label("Run to Symbol"),
+            file: Label::new(None),
+            state: Rc::new(RefCell::new(State {
+                symbols: SymbolTable::new(),
+                shown: Vec::new(),
+                path: None,
+            })),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Symbols" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        add_text_column(&self.treeview, "Address");
+        add_text_column(&self.treeview, "Name");
+
+        self.search.set_placeholder_text(Some("Search"));
+
+        let control_box = gtk::Box::new(Orientation::Horizontal, 5);
+        control_box.set_border_width(5);
+        control_box.pack_start(&self.load, false, true, 0);
+        control_box.pack_start(&self.clear, false, true, 0);
+        control_box.pack_start(&self.search, true, true, 0);
+        control_box.pack_start(&self.run_to, false, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&control_box, false, true, 0);
+        vbox.pack_start(&self.file, false, true, 0);
+        vbox.pack_start(&self.treeview, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let v = view.clone();
+        self.load.connect_clicked(move |_| {
+            if let Some(path) = choose_file(&v.win, "Load Symbol File", gtk::FileChooserAction::Open) {
+                let result = v.model.borrow_mut().load_symbols(path.clone());
+                if let Err(e) = result {
+                    v.error(&format!("Couldn't load symbols from {}: {}", path.display(), e));
+                }
+            }
+        });
+
+        let v = view.clone();
+        self.clear.connect_clicked(move |_| {
+            let result = v.model.borrow_mut().clear_symbols();
+            if let Err(e) = result {
+                v.error(&format!("Couldn't save symbol file association: {}", e));
+            }
+        });
+
+        let this = self.clone();
+        self.search.connect_changed(move |_| {
+            this.update_list();
+        });
+
+        let this = self.clone();
+        self.run_to.connect_clicked(move |_| {
+            if view.is_running() {
+                return;
+            }
+            let rows = this.treeview.get_selection().get_selected_rows().0;
+            let addr = match rows.first() {
+                Some(row) => this.state.borrow().shown[row.get_indices()[0] as usize],
+                None => return,
+            };
+            let result = view.model.borrow_mut().run_to(addr);
+            if let Err(e) = result {
+                view.error(&e);
+            }
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        self.file.set_text(&match data.symbols_path {
+            Some(path) => format!("{} ({} symbols)", path.display(), data.symbols.symbols().len()),
+            None => "No symbol file loaded".to_string(),
+        });
+
+        // The table only changes when a symbol file is loaded, don't copy it on every update
+        let changed = {
+            let mut state = self.state.borrow_mut();
+            let changed = state.path.as_ref().map(|path| &**path) != data.symbols_path ||
+                          state.symbols.symbols().len() != data.symbols.symbols().len();
+            if changed {
+                state.symbols = data.symbols.clone();
+                state.path = data.symbols_path.map(|path| path.to_path_buf());
+            }
+            changed
+        };
+        if changed {
+            self.update_list();
+        }
+    }
+}
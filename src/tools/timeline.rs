@@ -0,0 +1,120 @@
+//! Show interrupts and other timing events on a diagram of the beam position
+
+use super::Tool;
+use view::RealMainView;
+use util::*;
+use data::{ModelData, VISIBLE_LINES};
+use timeline::{EventKind, EVENT_KINDS, DOTS_PER_LINE, LINES_PER_FRAME};
+
+use gdk_pixbuf::{Pixbuf, InterpType};
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, Image};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+
+/// Scale factor of the beam diagram
+const SCALE: i32 = 2;
+
+#[derive(Clone)]    //:
+pub struct Timeline {
+    events: ListStore,
+    diagram: Image,
+    legend: Label,
+}
+
+/// Colors a rectangle of the diagram, clipping at the edges
+fn fill_rect(data: &mut [u8], x: i32, y: i32, w: i32, h: i32, color: [u8; 3]) {
+    let (width, height) = (DOTS_PER_LINE as i32, LINES_PER_FRAME as i32);
+    for py in y.max(0)..(y + h).min(height) {
+        for px in x.max(0)..(x + w).min(width) {
+            let i = ((py * width + px) * 3) as usize;
+            data[i..i + 3].copy_from_slice(&color);
+        }
+    }
+}
+
+impl Tool for Timeline {
+    fn new() -> Self {
+        Timeline {
+            events: ListStore::new(&[
+                gtk::Type::U32,     // Scanline
+                gtk::Type::U32,     // H position
+                gtk::Type::String,  // Event
+                gtk::Type::String,  // Details
+            ]),
+            diagram: Image::new(),
+            legend: Label::new(None),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Timeline" }
+
+    fn uses_capture(&self) -> bool { true }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        let treeview = TreeView::new_with_model(&self.events);
+        add_text_column(&treeview, "Line");
+        add_text_column(&treeview, "H");
+        add_text_column(&treeview, "Event");
+        add_text_column(&treeview, "Details");
+
+        let legend = EVENT_KINDS.iter().map(|kind| {
+            let c = kind.color();
+            format!("<span foreground=\"#{:02x}{:02x}{:02x}\">\u{25a0}</span> {}", c[0], c[1], c[2], kind.name())
+        }).collect::<Vec<_>>().join("   ");
+        self.legend.set_markup(&format!("{}   <span foreground=\"#000000\">+</span> Stop", legend));
+
+        let diagram_box = gtk::Box::new(Orientation::Vertical, 5);
+        diagram_box.set_border_width(5);
+        diagram_box.pack_start(&self.diagram, false, true, 0);
+        diagram_box.pack_start(&self.legend, false, true, 0);
+
+        let hbox = gtk::Paned::new(Orientation::Horizontal);
+        hbox.pack1(&diagram_box, false, true);
+        hbox.pack2(&treeview, true, true);
+        win.add(&hbox);
+    }
+
+    fn connect_events(&mut self, _view: Rc<RealMainView>) {}
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let events = &data.capture.events;
+        let (width, height) = (DOTS_PER_LINE as i32, LINES_PER_FRAME as i32);
+
+        // Background: visible area, H-Blank and V-Blank in different shades
+        let mut pixels = vec![0; (width * height * 3) as usize];
+        fill_rect(&mut pixels, 0, 0, width, height, [0xd0, 0xd0, 0xd0]);
+        fill_rect(&mut pixels, 0, 0, width, VISIBLE_LINES as i32, [0xe8, 0xe8, 0xe8]);
+        fill_rect(&mut pixels, 22, 0, 256, VISIBLE_LINES as i32, [0xff, 0xff, 0xff]);
+
+        // HDMA is drawn first, since it happens on almost every line and would cover the rest
+        for event in events.iter().filter(|e| e.kind == EventKind::Hdma) {
+            fill_rect(&mut pixels, event.h as i32, event.line as i32, 2, 1, event.kind.color());
+        }
+        for event in events.iter().filter(|e| e.kind != EventKind::Hdma) {
+            fill_rect(&mut pixels, event.h as i32 - 2, event.line as i32 - 2, 5, 5, event.kind.color());
+        }
+
+        // Mark the stop position with a cross
+        let (h, v) = (data.capture.stop_pos.0 as i32, data.capture.stop_pos.1 as i32);
+        fill_rect(&mut pixels, h - 4, v, 9, 1, [0, 0, 0]);
+        fill_rect(&mut pixels, h, v - 4, 1, 9, [0, 0, 0]);
+
+        let pixbuf = Pixbuf::new_from_vec(pixels, 0, false, 8, width, height, width * 3);
+        let pixbuf = pixbuf.scale_simple(width * SCALE, height * SCALE, InterpType::Nearest).unwrap();
+        self.diagram.set_from_pixbuf(Some(&pixbuf));
+
+        // HDMA events are only listed in the diagram, the HDMA tool has the details
+        let listed = events.iter().filter(|e| e.kind != EventKind::Hdma).collect::<Vec<_>>();
+        set_row_count(&self.events, listed.len());
+        for (row, event) in listed.iter().enumerate() {
+            let entry = self.events.iter_nth_child(None, row as i32).expect(&format!("child #{} not found", row));
+            self.events.set(&entry, &[0, 1, 2, 3], &[
+                &(event.line as u32),
+                &(event.h as u32),
+                &event.kind.name(),
+                &event.detail,
+            ]);
+        }
+    }
+}
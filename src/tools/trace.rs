@@ -0,0 +1,133 @@
+//! Log executed CPU instructions to a file
+
+use super::Tool;
+use view::{View, RealMainView};
+use util::*;
+use data::ModelData;
+use trace::TraceOptions;
+
+use gtk::{self, ScrolledWindow, Orientation, Label, Button, Entry, SpinButton, CheckButton, Grid};
+use gtk::prelude::*;
+
+use std::path::PathBuf;
+use std::rc::Rc;
+
+#[derive(Clone)]    //:
+pub struct Trace {
+    path: Entry,
+    browse: Button,
+    skip_frames: SpinButton,
+    frames: SpinButton,
+    filter_start: Entry,
+    filter_end: Entry,
+    opcode_bytes: CheckButton,
+    start: Button,
+    result: Label,
+}
+
+impl Trace {
+    /// Builds trace options from the input widgets
+    fn read_options(&self) -> Result<TraceOptions, String> {
+        let start_text = self.filter_start.get_text().unwrap_or(String::new());
+        let end_text = self.filter_end.get_text().unwrap_or(String::new());
+        let filter = if start_text.trim().is_empty() && end_text.trim().is_empty() {
+            None
+        } else {
+            match (parse_hex(&start_text), parse_hex(&end_text)) {
+                (Some(start), Some(end)) => Some((start, end)),
+                _ => return Err(format!("Invalid address range '{}' - '{}'", start_text, end_text)),
+            }
+        };
+
+        Ok(TraceOptions {
+            skip_frames: self.skip_frames.get_value_as_int() as u32,
+            frames: self.frames.get_value_as_int() as u32,
+            filter: filter,
+            opcode_bytes: self.opcode_bytes.get_active(),
+        })
+    }
+}
+
+impl Tool for Trace {
+    fn new() -> Self {
+        Trace {
+            path: Entry::new(),
+            browse: Button::new_with_label("Browse..."),
+            skip_frames: SpinButton::new_with_range(0.0, 100000.0, 1.0),
+            frames: SpinButton::new_with_range(1.0, 10000.0, 1.0),
+            filter_start: Entry::new(),
+            filter_end: Entry::new(),
+            opcode_bytes: CheckButton::new_with_label("Include opcode bytes"),
+            start: Button::new_with_label("Start Trace"),
+            result: Label::new(None),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Trace" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        self.path.set_text("trace.log");
+        self.frames.set_value(1.0);
+        self.filter_start.set_placeholder_text(Some("$00:0000"));
+        self.filter_end.set_placeholder_text(Some("$FF:FFFF"));
+
+        let grid = Grid::new();
+        grid.set_border_width(5);
+        grid.set_row_spacing(5);
+        grid.set_column_spacing(5);
+
+        let path_box = gtk::Box::new(Orientation::Horizontal, 5);
+        path_box.pack_start(&self.path, true, true, 0);
+        path_box.pack_start(&self.browse, false, true, 0);
+        grid.attach(&Label::new(Some("Log file:")), 0, 0, 1, 1);
+        grid.attach(&path_box, 1, 0, 1, 1);
+
+        grid.attach(&Label::new(Some("Skip frames:")), 0, 1, 1, 1);
+        grid.attach(&self.skip_frames, 1, 1, 1, 1);
+        grid.attach(&Label::new(Some("Log frames:")), 0, 2, 1, 1);
+        grid.attach(&self.frames, 1, 2, 1, 1);
+
+        let filter_box = gtk::Box::new(Orientation::Horizontal, 5);
+        filter_box.pack_start(&self.filter_start, true, true, 0);
+        filter_box.pack_start(&Label::new(Some("-")), false, true, 0);
+        filter_box.pack_start(&self.filter_end, true, true, 0);
+        grid.attach(&Label::new(Some("Address range:")), 0, 3, 1, 1);
+        grid.attach(&filter_box, 1, 3, 1, 1);
+
+        grid.attach(&self.opcode_bytes, 1, 4, 1, 1);
+        grid.attach(&self.start, 1, 5, 1, 1);
+        grid.attach(&self.result, 1, 6, 1, 1);
+
+        win.add(&grid);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let this = self.clone();
+        let v = view.clone();
+        self.browse.connect_clicked(move |_| {
+            let filename = choose_file(&v.win, "Save Trace Log", gtk::FileChooserAction::Save);
+            if let Some(path) = filename.as_ref().and_then(|p| p.to_str()) {
+                this.path.set_text(path);
+            }
+        });
+
+        let this = self.clone();
+        self.start.connect_clicked(move |_| {
+            let opts = match this.read_options() {
+                Ok(opts) => opts,
+                Err(e) => return view.error(&e),
+            };
+            let path = PathBuf::from(this.path.get_text().unwrap_or(String::new()));
+
+            let result = view.model.borrow_mut().trace(path.clone(), opts);
+
+            match result {
+                Ok(lines) => this.result.set_text(&format!("Wrote {} lines to {}", lines, path.display())),
+                Err(e) => view.error(&format!("Error while writing trace: {}", e)),
+            }
+        });
+    }
+
+    fn update_model_data(&mut self, _data: &ModelData) {
+    }
+}
@@ -0,0 +1,300 @@
+//! Named, typed watches of WRAM locations
+
+use super::Tool;
+use view::{View, RealMainView};
+use util::*;
+use data::{ModelData, MemorySpace};
+use config;
+use ramsearch::Width;
+use watch::{Watch, Endian, FORMATS};
+
+use gtk::{self, TreeView, ListStore, ScrolledWindow, Orientation, Label, Button, Entry, ComboBoxText,
+    CheckButton, CellRendererText, TreePath};
+use gtk::prelude::*;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+static WIDTHS: [Width; 3] = [Width::Byte, Width::Word, Width::Long];
+
+struct State {
+    watches: Vec<Watch>,
+    /// Title of the ROM the watches belong to
+    rom_title: Option<String>,
+    /// WRAM contents at the last update
+    wram: Vec<u8>,
+    /// WRAM offsets that are currently frozen
+    frozen: Vec<usize>,
+}
+
+impl State {
+    /// Saves the watches of the current ROM
+    fn save(&self) {
+        let title = match self.rom_title {
+            Some(ref title) => title,
+            None => return,
+        };
+        let lines = self.watches.iter().map(Watch::to_line).collect::<Vec<_>>();
+        if let Err(e) = config::rom_file("watches", title).and_then(|path| config::write_lines(&path, &lines)) {
+            error!("couldn't save watches: {}", e);
+        }
+    }
+
+    /// Loads the watches of the ROM with the given title
+    fn load(&mut self, title: &str) {
+        self.rom_title = Some(title.to_string());
+        self.watches = match config::rom_file("watches", title).and_then(|path| config::read_lines(&path)) {
+            Ok(lines) => lines.iter().filter_map(|line| Watch::from_line(line)).collect(),
+            Err(e) => {
+                error!("couldn't load watches: {}", e);
+                Vec::new()
+            }
+        };
+    }
+}
+
+#[derive(Clone)]    //:
+pub struct Watches {
+    treeview: TreeView,
+    watches: ListStore,
+    value_render: CellRendererText,
+    name: Entry,
+    addr: Entry,
+    width: ComboBoxText,
+    signed: CheckButton,
+    big_endian: CheckButton,
+    format: ComboBoxText,
+    add: Button,
+    remove: Button,
+    freeze: Button,
+    unfreeze: Button,
+    state: Rc<RefCell<State>>,
+}
+
+impl Watches {
+    /// Builds a watch from the input widgets
+    fn read_input(&self) -> Result<Watch, String> {
+        let addr_text = self.addr.get_text().unwrap_or(String::new());
+        let addr = match parse_hex(&addr_text) {
+            Some(addr) => addr,
+            None => return Err(format!("Invalid address '{}'", addr_text)),
+        };
+        let name = self.name.get_text().unwrap_or(String::new());
+
+        Ok(Watch {
+            name: if name.trim().is_empty() { format!("${:06X}", addr) } else { name },
+            addr: addr,
+            width: WIDTHS[self.width.get_active().max(0) as usize],
+            signed: self.signed.get_active(),
+            endian: if self.big_endian.get_active() { Endian::Big } else { Endian::Little },
+            format: FORMATS[self.format.get_active().max(0) as usize],
+        })
+    }
+
+    /// Returns the index of the selected watch
+    fn selected(&self) -> Option<usize> {
+        self.treeview.get_selection().get_selected_rows().0.first()
+            .map(|row| row.get_indices()[0] as usize)
+    }
+}
+
+impl Tool for Watches {
+    fn new() -> Self {
+        let model = ListStore::new(&[
+            gtk::Type::String,  // Name
+            gtk::Type::String,  // Address (Hex)
+            gtk::Type::String,  // Type
+            gtk::Type::String,  // Format
+            gtk::Type::String,  // Value
+            gtk::Type::Bool,    // Frozen
+        ]);
+
+        let format = ComboBoxText::new();
+        for f in FORMATS.iter() {
+            format.append_text(f.name());
+        }
+
+        Watches {
+            treeview: TreeView::new_with_model(&model),
+            watches: model,
+            value_render: CellRendererText::new(),
+            name: Entry::new(),
+            addr: Entry::new(),
+            width: combo_box_text(&[
+                "8-bit",
+                "16-bit",
+                "24-bit",
+            ]),
+            signed: CheckButton::new_with_label("Signed"),
+            big_endian: CheckButton::new_with_label("Big Endian"),
+            format: format,
+            add: Button::new_with_label("Add"),
+            remove: Button::new_with_label("Remove"),
+            freeze: Button::new_with_label("Freeze"),
+            unfreeze: Button::new_with_label("Unfreeze"),
+            state: Rc::new(RefCell::new(State {
+                watches: Vec::new(),
+                rom_title: None,
+                wram: Vec::new(),
+                frozen: Vec::new(),
+            })),
+        }
+    }
+
+    fn get_name(&self) -> &'static str { "Watches" }
+
+    fn init_tab(&mut self, win: &ScrolledWindow) {
+        add_text_column(&self.treeview, "Name");
+        add_text_column(&self.treeview, "Address");
+        add_text_column(&self.treeview, "Type");
+        add_text_column(&self.treeview, "Format");
+        add_editable_text_column(&self.treeview, "Value", &self.value_render);
+        add_text_column(&self.treeview, "Frozen");
+
+        self.name.set_placeholder_text(Some("Name"));
+        self.addr.set_placeholder_text(Some("$7E:0000"));
+        self.width.set_active(0);
+        self.format.set_active(0);
+
+        let input = gtk::Box::new(Orientation::Horizontal, 5);
+        input.set_border_width(5);
+        input.pack_start(&self.name, false, true, 0);
+        input.pack_start(&self.addr, false, true, 0);
+        input.pack_start(&self.width, false, true, 0);
+        input.pack_start(&self.signed, false, true, 0);
+        input.pack_start(&self.big_endian, false, true, 0);
+        input.pack_start(&Label::new(Some("Display as:")), false, true, 0);
+        input.pack_start(&self.format, false, true, 0);
+        input.pack_end(&self.add, false, true, 0);
+
+        let buttons = gtk::Box::new(Orientation::Horizontal, 5);
+        buttons.set_border_width(5);
+        buttons.pack_start(&self.remove, false, true, 0);
+        buttons.pack_start(&self.freeze, false, true, 0);
+        buttons.pack_start(&self.unfreeze, false, true, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+        vbox.pack_start(&input, false, true, 0);
+        vbox.pack_start(&buttons, false, true, 0);
+        vbox.pack_start(&self.treeview, true, true, 0);
+        win.add(&vbox);
+    }
+
+    fn connect_events(&mut self, view: Rc<RealMainView>) {
+        let this = self.clone();
+        let v = view.clone();
+        self.add.connect_clicked(move |_| {
+            match this.read_input() {
+                Ok(watch) => {
+                    let mut state = this.state.borrow_mut();
+                    state.watches.push(watch);
+                    state.save();
+                }
+                Err(e) => return v.error(&e),
+            }
+            v.model.borrow_mut().refresh();
+        });
+
+        let this = self.clone();
+        let v = view.clone();
+        self.remove.connect_clicked(move |_| {
+            if let Some(index) = this.selected() {
+                let mut state = this.state.borrow_mut();
+                state.watches.remove(index);
+                state.save();
+            }
+            v.model.borrow_mut().refresh();
+        });
+
+        let this = self.clone();
+        let v = view.clone();
+        self.freeze.connect_clicked(move |_| {
+            // Freeze to the current value. Errors are shown after the state borrow ends, since the
+            // dialog lets the run timer update the tool.
+            let result = {
+                let state = this.state.borrow();
+                let watch = match this.selected().and_then(|i| state.watches.get(i)) {
+                    Some(watch) => watch,
+                    None => return,
+                };
+                match watch.wram_offset() {
+                    Some(offset) => {
+                        let bytes = (0..watch.width.bytes())
+                            .map(|i| state.wram[(offset + i) % state.wram.len()])
+                            .collect::<Vec<_>>();
+                        Ok((offset, bytes))
+                    }
+                    None => Err("Only WRAM locations can be frozen".to_string()),
+                }
+            };
+            match result {
+                Ok((offset, bytes)) => v.model.borrow_mut().freeze(offset, bytes),
+                Err(e) => v.error(&e),
+            }
+        });
+
+        let this = self.clone();
+        let v = view.clone();
+        self.unfreeze.connect_clicked(move |_| {
+            let offset = {
+                let state = this.state.borrow();
+                this.selected().and_then(|i| state.watches.get(i)).and_then(Watch::wram_offset)
+            };
+            if let Some(offset) = offset {
+                v.model.borrow_mut().unfreeze(offset);
+            }
+        });
+
+        let this = self.clone();
+        self.value_render.connect_edited(move |_, path: TreePath, text: &str| {
+            let row = path.get_indices()[0] as usize;
+            let result = {
+                let state = this.state.borrow();
+                let watch = match state.watches.get(row) {
+                    Some(watch) => watch,
+                    None => return,
+                };
+                match (watch.wram_offset(), watch.parse_value(text)) {
+                    (None, _) => Err("Only WRAM locations can be edited".to_string()),
+                    (_, None) => Err(format!("Invalid {} value '{}'", watch.format.name(), text)),
+                    (Some(offset), Some(bytes)) => Ok((offset, bytes, state.frozen.contains(&offset))),
+                }
+            };
+            let (offset, bytes, frozen) = match result {
+                Ok(edit) => edit,
+                Err(e) => return view.error(&e),
+            };
+
+            if frozen {
+                view.model.borrow_mut().freeze(offset, bytes);
+            } else {
+                view.model.borrow_mut().poke_memory(MemorySpace::Wram, offset, &bytes);
+            }
+        });
+    }
+
+    fn update_model_data(&mut self, data: &ModelData) {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        if state.rom_title.as_ref().map(|t| &**t) != Some(data.rom_title) {
+            state.load(data.rom_title);
+        }
+        state.wram.clear();
+        state.wram.extend_from_slice(&data.memory.wram);
+        state.frozen = data.freezes.iter().map(|f| f.offset).collect();
+
+        set_row_count(&self.watches, state.watches.len());
+        for (row, watch) in state.watches.iter().enumerate() {
+            let frozen = watch.wram_offset().map_or(false, |offset| state.frozen.contains(&offset));
+            let entry = self.watches.iter_nth_child(None, row as i32).expect(&format!("child #{} not found", row));
+            self.watches.set(&entry, &[0, 1, 2, 3, 4, 5], &[
+                &watch.name,
+                &data.symbols.format_addr(watch.addr),
+                &watch.type_string(),
+                &watch.format.name(),
+                &watch.format_value(&data.memory.wram),
+                &frozen,
+            ]);
+        }
+    }
+}
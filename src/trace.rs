@@ -0,0 +1,74 @@
+//! CPU execution trace logging
+//!
+//! Lines are written in the format used by bsnes-plus trace logs, so that traces can be diffed
+//! against logs created by other emulators:
+//!
+//! ```text
+//! 008000 sei                    A:0000 X:0000 Y:0000 S:01ff D:0000 DB:00 nvMXdIzc V:  0 H:  186
+//! ```
+//!
+//! The raw instruction bytes can optionally be inserted after the address.
+
+use data::CpuState;
+use disasm::Instr;
+
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug)]
+pub struct TraceOptions {
+    /// Number of frames to run before logging starts
+    pub skip_frames: u32,
+    /// Number of frames to log
+    pub frames: u32,
+    /// Only log instructions in this (inclusive) 24-bit address range
+    pub filter: Option<(u32, u32)>,
+    /// Include the raw instruction bytes in each line
+    pub opcode_bytes: bool,
+}
+
+pub struct Tracer {
+    out: BufWriter<File>,
+    opts: TraceOptions,
+    lines: u64,
+}
+
+impl Tracer {
+    /// Creates a tracer writing to a new file at `path`
+    pub fn create(path: &Path, opts: TraceOptions) -> io::Result<Tracer> {
+        Ok(Tracer {
+            out: BufWriter::new(try!(File::create(path))),
+            opts: opts,
+            lines: 0,
+        })
+    }
+
+    /// Logs an instruction about to be executed
+    ///
+    /// `cpu` is the register state before execution, `h` and `v` the PPU beam position.
+    pub fn log(&mut self, instr: &Instr, cpu: &CpuState, h: u16, v: u16) -> io::Result<()> {
+        if let Some((start, end)) = self.opts.filter {
+            if instr.addr < start || instr.addr > end {
+                return Ok(());
+            }
+        }
+
+        try!(write!(self.out, "{:06x} ", instr.addr));
+        if self.opts.opcode_bytes {
+            try!(write!(self.out, "{:<12}", instr.bytes_string().to_lowercase()));
+        }
+        try!(writeln!(self.out,
+            "{:<22} A:{:04x} X:{:04x} Y:{:04x} S:{:04x} D:{:04x} DB:{:02x} {} V:{:>3} H:{:>4}",
+            instr.to_string().to_lowercase(), cpu.a, cpu.x, cpu.y, cpu.s, cpu.d, cpu.dbr,
+            cpu.flags_string(), v, h));
+        self.lines += 1;
+        Ok(())
+    }
+
+    /// Flushes the log file, returning the number of lines written
+    pub fn finish(mut self) -> io::Result<u64> {
+        try!(self.out.flush());
+        Ok(self.lines)
+    }
+}
@@ -1,9 +1,11 @@
 //! Various GTK utilities
 
 use gtk::{self, TreeView, CellRendererPixbuf, CellRendererText, TreeViewColumn, Frame, CheckButton,
-    Orientation, Label, ComboBoxText, Widget};
+    Orientation, Label, ComboBoxText, Widget, Window, FileChooserAction, ListStore};
 use gtk::prelude::*;
 
+use std::path::PathBuf;
+
 pub fn add_pixbuf_column(tree_view: &TreeView, title: &str) {
     let next_col = tree_view.get_columns().len();
     let render = CellRendererPixbuf::new();
@@ -25,6 +27,52 @@ pub fn add_text_column(tree_view: &TreeView, title: &str) {
     tree_view.append_column(&column);
 }
 
+/// Add a named column with editable text cells to a tree view, using the given renderer
+///
+/// The caller is responsible for connecting to the renderer's `edited` signal.
+pub fn add_editable_text_column(tree_view: &TreeView, title: &str, render: &CellRendererText) {
+    let next_col = tree_view.get_columns().len();
+    render.set_property("editable", &true.to_value()).unwrap();
+    let column = TreeViewColumn::new();
+    column.set_title(title);
+    column.pack_start(render, false);
+    column.add_attribute(render, "text", next_col as i32);
+    tree_view.append_column(&column);
+}
+
+/// Background color of highlighted (usually: changed) values
+pub const HIGHLIGHT_COLOR: &'static str = "#ffd080";
+
+/// Add a named text column to a tree view, whose cells are highlighted when the `Bool` model column
+/// `highlight_col` is set
+pub fn add_highlighted_text_column(tree_view: &TreeView, title: &str, highlight_col: i32) {
+    let next_col = tree_view.get_columns().len();
+    let render = CellRendererText::new();
+    render.set_property("cell-background", &HIGHLIGHT_COLOR.to_value()).unwrap();
+    let column = TreeViewColumn::new();
+    column.set_title(title);
+    column.pack_start(&render, false);
+    column.add_attribute(&render, "text", next_col as i32);
+    column.add_attribute(&render, "cell-background-set", highlight_col);
+    tree_view.append_column(&column);
+}
+
+/// Appends or removes rows at the end of a `ListStore` until it contains `count` rows
+///
+/// Clearing and refilling a `ListStore` causes the `TreeView` to scroll up, so tools should resize
+/// the store and then overwrite the rows instead.
+pub fn set_row_count(store: &ListStore, count: usize) {
+    let mut rows = store.iter_n_children(None) as usize;
+    while rows > count {
+        let last = store.iter_nth_child(None, rows as i32 - 1).unwrap();
+        store.remove(&last);
+        rows -= 1;
+    }
+    for _ in rows..count {
+        store.append();
+    }
+}
+
 /// Creates a frame with title, containing 5 `CheckButtons` that will be stored in `layers`: BG1-4
 /// and OBJ (and optionally Backdrop).
 ///
@@ -122,3 +170,72 @@ pub fn combo_box_text(entries: &[&str]) -> ComboBoxText {
     }
     cb
 }
+
+/// Runs a modal file chooser dialog with the given title, returning the selected path
+///
+/// `action` should be `Open` or `Save`. Returns `None` if the dialog was cancelled.
+pub fn choose_file(parent: &Window, title: &str, action: FileChooserAction) -> Option<PathBuf> {
+    let file_chooser = gtk::FileChooserDialog::new(Some(title), Some(parent), action);
+    let accept = if action == FileChooserAction::Save { "Save" } else { "Open" };
+    file_chooser.add_buttons(&[
+        (accept, gtk::ResponseType::Ok as i32),
+        ("Cancel", gtk::ResponseType::Cancel as i32),
+    ]);
+
+    let result = file_chooser.run();
+    let filename = file_chooser.get_filename();
+    file_chooser.destroy();
+
+    if result == gtk::ResponseType::Ok as i32 { filename } else { None }
+}
+
+/// Escapes the characters that have special meaning in Pango markup
+pub fn escape_markup(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Parses a hexadecimal number, optionally prefixed with `$` or `0x`.
+///
+/// A `:` may be used to separate bank and offset of an address (`$7E:0010`).
+pub fn parse_hex(s: &str) -> Option<u32> {
+    let s = s.trim();
+    let s = if s.starts_with('$') {
+        &s[1..]
+    } else if s.starts_with("0x") || s.starts_with("0X") {
+        &s[2..]
+    } else {
+        s
+    };
+    let digits = s.chars().filter(|&c| c != ':').collect::<String>();
+    if digits.is_empty() { return None; }
+    u32::from_str_radix(&digits, 16).ok()
+}
+
+/// Parses a number like `parse_number`, but also allows a leading `-`
+pub fn parse_signed(s: &str) -> Option<i64> {
+    let s = s.trim();
+    if s.starts_with('-') {
+        parse_number(&s[1..]).map(|n| -(n as i64))
+    } else {
+        parse_number(s).map(|n| n as i64)
+    }
+}
+
+/// Parses a number that is hexadecimal if prefixed with `$` or `0x`, and decimal otherwise.
+pub fn parse_number(s: &str) -> Option<u32> {
+    let s = s.trim();
+    if s.starts_with('$') || s.starts_with("0x") || s.starts_with("0X") {
+        parse_hex(s)
+    } else {
+        s.parse().ok()
+    }
+}
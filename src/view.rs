@@ -3,33 +3,54 @@
 use model::Model;
 use data::ModelData;
 use tools::{Tool, TOOLS};
+use util::choose_file;
 
 use gdk_pixbuf::{Pixbuf, InterpType};
 
 use gtk::prelude::*;
-use gtk::{self, Window, WindowType, Image, Orientation, ToolButton, Label, Align, Frame};
+use gtk::{self, Window, WindowType, Image, Orientation, ToolButton, Label, Align, Frame,
+    SpinButton, Continue};
 
 use std::rc::{Rc, Weak};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
 pub trait View {
     fn update_model_data(&self, data: &ModelData);
     fn update_frame(&self, frame: &[u8]);
     fn update_info(&self, info: &str);
     fn error(&self, msg: &str);
+    /// Switches to the tab of the tool with the given name
+    fn focus_tool(&self, name: &str);
+    /// Whether the frame capture is needed: the visible tool shows it and emulation is halted
+    fn wants_capture(&self) -> bool;
 }
 
 pub struct MainView(Rc<RealMainView>);
 
 pub struct RealMainView {
-    win: Window,
+    pub win: Window,
     status: Label,
     frame: Image,
     pixbuf: RefCell<Pixbuf>,
     btn_open_rom: ToolButton,
+    btn_apply_patch: ToolButton,
     btn_open_save: ToolButton,
     btn_step_frame: ToolButton,
-
+    btn_run: ToolButton,
+    btn_pause: ToolButton,
+    btn_step_into: ToolButton,
+    btn_step_over: ToolButton,
+    btn_step_out: ToolButton,
+    btn_run_to_line: ToolButton,
+    run_to_line: SpinButton,
+    btn_export_disasm: ToolButton,
+    btn_export_spc: ToolButton,
+    /// Whether emulation is currently running continuously
+    running: Cell<bool>,
+    /// Whether the tool on the current page uses the frame capture
+    capture_wanted: Cell<bool>,
+
+    tools_book: gtk::Notebook,
     tools: RefCell<Vec<Box<Tool>>>,
 
     pub model: Rc<RefCell<Model>>,
@@ -65,6 +86,19 @@ impl View for RealMainView {
         dialog.run();
         dialog.destroy();
     }
+
+    fn focus_tool(&self, name: &str) {
+        let page = self.tools.borrow().iter().position(|tool| tool.get_name() == name);
+        if let Some(page) = page {
+            self.tools_book.set_current_page(Some(page as u32));
+        }
+    }
+
+    fn wants_capture(&self) -> bool {
+        // Capturing executes the frame instruction by instruction, which is too slow to do for
+        // every frame while running
+        self.capture_wanted.get() && !self.running.get()
+    }
 }
 
 impl MainView {
@@ -103,13 +137,24 @@ impl MainView {
             drop(file_chooser);
 
             if result == gtk::ResponseType::Ok as i32 {
-                match this.model.borrow_mut().load_rom(filename.unwrap()) {
+                let result = this.model.borrow_mut().load_rom(filename.unwrap(), None);
+                match result {
                     Ok(_) => {},
                     Err(e) => this.error(&format!("Error while loading ROM: {}", e)),
                 }
             }
         });
 
+        let this = self.0.clone();
+        self.0.btn_apply_patch.connect_clicked(move |_| {
+            if let Some(path) = choose_file(&this.win, "Apply Patch", gtk::FileChooserAction::Open) {
+                let result = this.model.borrow_mut().apply_patch(path);
+                if let Err(e) = result {
+                    this.error(&format!("Error while applying patch: {}", e));
+                }
+            }
+        });
+
         let this = self.0.clone();
         self.0.btn_open_save.connect_clicked(move |_| {
             let file_chooser = gtk::FileChooserDialog::new(
@@ -127,7 +172,8 @@ impl MainView {
             drop(file_chooser);
 
             if result == gtk::ResponseType::Ok as i32 {
-                match this.model.borrow_mut().load_save_state(filename.unwrap()) {
+                let result = this.model.borrow_mut().load_save_state(filename.unwrap());
+                match result {
                     Ok(_) => {},
                     Err(e) => this.error(&format!("Error while loading save state: {}", e)),
                 }
@@ -139,6 +185,97 @@ impl MainView {
             this.model.borrow_mut().step();
         });
 
+        let this = self.0.clone();
+        self.0.btn_run.connect_clicked(move |_| {
+            if this.running.get() { return; }
+            this.set_running(true);
+
+            let this = this.clone();
+            gtk::timeout_add(16, move || {
+                if !this.running.get() {
+                    return Continue(false);
+                }
+                // The model is busy if a dialog was opened while it's borrowed. Skip this frame.
+                let halted = match this.model.try_borrow_mut() {
+                    Ok(mut model) => model.run_frame(),
+                    Err(_) => false,
+                };
+                if halted {
+                    // Breakpoint hit
+                    this.set_running(false);
+                }
+                Continue(this.running.get())
+            });
+        });
+
+        let this = self.0.clone();
+        self.0.btn_pause.connect_clicked(move |_| {
+            this.set_running(false);
+        });
+
+        let this = self.0.clone();
+        self.0.btn_step_into.connect_clicked(move |_| {
+            this.model.borrow_mut().step_into();
+        });
+
+        let this = self.0.clone();
+        self.0.btn_step_over.connect_clicked(move |_| {
+            let result = this.model.borrow_mut().step_over();
+            if let Err(e) = result {
+                this.error(&e);
+            }
+        });
+
+        let this = self.0.clone();
+        self.0.btn_step_out.connect_clicked(move |_| {
+            let result = this.model.borrow_mut().step_out();
+            if let Err(e) = result {
+                this.error(&e);
+            }
+        });
+
+        let this = self.0.clone();
+        self.0.btn_run_to_line.connect_clicked(move |_| {
+            let line = this.run_to_line.get_value_as_int() as u16;
+            let result = this.model.borrow_mut().run_to_scanline(line);
+            if let Err(e) = result {
+                this.error(&e);
+            }
+        });
+
+        let this = self.0.clone();
+        self.0.btn_export_disasm.connect_clicked(move |_| {
+            if let Some(path) = choose_file(&this.win, "Export Disassembly", gtk::FileChooserAction::Save) {
+                let result = this.model.borrow().export_disassembly(&path);
+                if let Err(e) = result {
+                    this.error(&format!("Error while writing disassembly: {}", e));
+                }
+            }
+        });
+
+        let this = self.0.clone();
+        self.0.btn_export_spc.connect_clicked(move |_| {
+            if let Some(path) = choose_file(&this.win, "Export SPC", gtk::FileChooserAction::Save) {
+                let result = this.model.borrow().export_spc(&path);
+                if let Err(e) = result {
+                    this.error(&format!("Error while writing SPC file: {}", e));
+                }
+            }
+        });
+
+        let this = self.0.clone();
+        self.0.tools_book.connect_switch_page(move |_, _, page| {
+            // The page isn't switched yet, so remember whether the new tool needs a capture
+            let wanted = this.tools.borrow().get(page as usize).map_or(false, |tool| tool.uses_capture());
+            this.capture_wanted.set(wanted);
+            if wanted {
+                // Frames aren't captured while no tool shows them, so do it now
+                if let Ok(mut model) = this.model.try_borrow_mut() {
+                    model.refresh();
+                }
+            }
+        });
+
         let this = self.0.clone();
         for tool in &mut *self.0.tools.borrow_mut() {
             tool.connect_events(this.clone());
@@ -147,7 +284,29 @@ impl MainView {
 }
 
 impl RealMainView {
-    fn fill_tools_notebook(&mut self, book: &gtk::Notebook) {
+    /// Whether emulation is currently running continuously
+    pub fn is_running(&self) -> bool {
+        self.running.get()
+    }
+
+    /// Starts or stops continuous emulation. Stepping is disabled while running.
+    fn set_running(&self, running: bool) {
+        self.running.set(running);
+        for button in &[&self.btn_step_frame, &self.btn_step_into, &self.btn_step_over,
+                        &self.btn_step_out, &self.btn_run_to_line] {
+            button.set_sensitive(!running);
+        }
+        self.run_to_line.set_sensitive(!running);
+        if !running {
+            // The frame capture isn't updated while running
+            if let Ok(mut model) = self.model.try_borrow_mut() {
+                model.refresh();
+            }
+        }
+    }
+
+    fn fill_tools_notebook(&mut self) {
+        let book = &self.tools_book;
         TOOLS.with(|tools| {
             let mut tool_store = self.tools.borrow_mut();
             for &cons_tool in tools {
@@ -169,8 +328,21 @@ impl RealMainView {
             pixbuf: RefCell::new(unsafe { Pixbuf::new(0 /* RGB */, false, 8, 1, 1).unwrap() }),
             // FIXME The required generics are really ugly (and uncessary) here
             btn_open_rom: ToolButton::new(None::<&gtk::Box>, Some("Open ROM")),
+            btn_apply_patch: ToolButton::new(None::<&gtk::Box>, Some("Apply Patch")),
             btn_open_save: ToolButton::new(None::<&gtk::Box>, Some("Open Save State")),
             btn_step_frame: ToolButton::new(None::<&gtk::Box>, Some("Emulate Frame")),
+            btn_run: ToolButton::new(None::<&gtk::Box>, Some("Run")),
+            btn_pause: ToolButton::new(None::<&gtk::Box>, Some("Pause")),
+            btn_step_into: ToolButton::new(None::<&gtk::Box>, Some("Step Into")),
+            btn_step_over: ToolButton::new(None::<&gtk::Box>, Some("Step Over")),
+            btn_step_out: ToolButton::new(None::<&gtk::Box>, Some("Step Out")),
+            btn_run_to_line: ToolButton::new(None::<&gtk::Box>, Some("Run to Scanline")),
+            run_to_line: SpinButton::new_with_range(0.0, 261.0, 1.0),
+            btn_export_disasm: ToolButton::new(None::<&gtk::Box>, Some("Export Disassembly")),
+            btn_export_spc: ToolButton::new(None::<&gtk::Box>, Some("Export SPC")),
+            running: Cell::new(false),
+            capture_wanted: Cell::new(false),
+            tools_book: gtk::Notebook::new(),
             tools: RefCell::new(Vec::new()),
 
             model: model,
@@ -187,9 +359,10 @@ impl RealMainView {
         this.status.set_margin_left(10);
         this.status.set_margin_top(10);
 
-        let tools = gtk::Notebook::new();
-        tools.set_border_width(5);
-        this.fill_tools_notebook(&tools);
+        this.tools_book.set_border_width(5);
+        this.fill_tools_notebook();
+        let first_uses_capture = this.tools.borrow().first().map_or(false, |tool| tool.uses_capture());
+        this.capture_wanted.set(first_uses_capture);
 
         let statusframe = Frame::new(Some("Info"));
         statusframe.set_border_width(5);
@@ -202,13 +375,28 @@ impl RealMainView {
         let hsplit = gtk::Paned::new(gtk::Orientation::Horizontal);
         //hsplit.set_wide_handle(true); // FIXME Depends on GTK 3.16
         hsplit.pack1(&left_column, false, false);
-        hsplit.pack2(&tools, true, false);
+        hsplit.pack2(&this.tools_book, true, false);
 
         let menu = gtk::Toolbar::new();
         menu.set_border_width(5);
         menu.add(&this.btn_open_rom);
+        menu.add(&this.btn_apply_patch);
         menu.add(&this.btn_open_save);
         menu.add(&this.btn_step_frame);
+        menu.add(&this.btn_run);
+        menu.add(&this.btn_pause);
+        menu.add(&gtk::SeparatorToolItem::new());
+        menu.add(&this.btn_step_into);
+        menu.add(&this.btn_step_over);
+        menu.add(&this.btn_step_out);
+        menu.add(&gtk::SeparatorToolItem::new());
+        let line_item = gtk::ToolItem::new();
+        line_item.add(&this.run_to_line);
+        menu.add(&line_item);
+        menu.add(&this.btn_run_to_line);
+        menu.add(&gtk::SeparatorToolItem::new());
+        menu.add(&this.btn_export_disasm);
+        menu.add(&this.btn_export_spc);
 
         let vsplit = gtk::Box::new(Orientation::Vertical, 0);
         vsplit.pack_start(&menu, false, false, 0);
@@ -0,0 +1,188 @@
+//! Typed memory watches
+
+use memmap;
+use ramsearch::Width;
+use util::parse_hex;
+
+/// Byte order of a watched value
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+/// How a watched value is displayed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Hex,
+    Decimal,
+    /// Fixed-point with the low byte as the fraction (the low nibble for 8-bit values)
+    Fixed,
+    /// Binary-coded decimal, one digit per nibble
+    Bcd,
+}
+
+pub static FORMATS: [Format; 4] = [Format::Hex, Format::Decimal, Format::Fixed, Format::Bcd];
+
+impl Format {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Format::Hex => "Hex",
+            Format::Decimal => "Decimal",
+            Format::Fixed => "Fixed-point",
+            Format::Bcd => "BCD",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Watch {
+    pub name: String,
+    /// 24-bit bus address of the watched WRAM location
+    pub addr: u32,
+    pub width: Width,
+    pub signed: bool,
+    pub endian: Endian,
+    pub format: Format,
+}
+
+impl Watch {
+    /// Offset of the watched value in WRAM, or `None` if the address isn't mapped to WRAM
+    pub fn wram_offset(&self) -> Option<usize> {
+        memmap::wram_offset(self.addr)
+    }
+
+    /// Reads the raw (unsigned) value from WRAM
+    pub fn read(&self, wram: &[u8]) -> Option<u32> {
+        let offset = match self.wram_offset() {
+            Some(offset) => offset,
+            None => return None,
+        };
+        let bytes = self.width.bytes();
+        let mut value = 0;
+        for i in 0..bytes {
+            let b = wram[(offset + i) % wram.len()] as u32;
+            value |= match self.endian {
+                Endian::Little => b << (8 * i),
+                Endian::Big => b << (8 * (bytes - 1 - i)),
+            };
+        }
+        Some(value)
+    }
+
+    /// Sign-extends a raw value if the watch is signed
+    fn to_signed(&self, raw: u32) -> i64 {
+        if self.signed {
+            let shift = 32 - 8 * self.width.bytes() as u32;
+            ((raw << shift) as i32 >> shift) as i64
+        } else {
+            raw as i64
+        }
+    }
+
+    /// Number of fractional bits of fixed-point values
+    fn fraction_bits(&self) -> u32 {
+        if self.width == Width::Byte { 4 } else { 8 }
+    }
+
+    /// Formats the current value for display
+    pub fn format_value(&self, wram: &[u8]) -> String {
+        let raw = match self.read(wram) {
+            Some(raw) => raw,
+            None => return "<not in WRAM>".to_string(),
+        };
+        match self.format {
+            Format::Hex => format!("${:0width$X}", raw, width = 2 * self.width.bytes()),
+            Format::Decimal => self.to_signed(raw).to_string(),
+            Format::Fixed => {
+                let value = self.to_signed(raw) as f64 / (1 << self.fraction_bits()) as f64;
+                format!("{:.4}", value)
+            }
+            Format::Bcd => format!("{:0width$X}", raw, width = 2 * self.width.bytes()),
+        }
+    }
+
+    /// Parses a value typed by the user, in the watch's display format, into the bytes to write to
+    /// WRAM (in memory order)
+    pub fn parse_value(&self, text: &str) -> Option<Vec<u8>> {
+        let text = text.trim();
+        let raw = match self.format {
+            Format::Hex => match parse_hex(text) {
+                Some(raw) => raw as i64,
+                None => return None,
+            },
+            Format::Decimal => match text.parse::<i64>() {
+                Ok(value) => value,
+                Err(_) => return None,
+            },
+            Format::Fixed => match text.parse::<f64>() {
+                Ok(value) => (value * (1 << self.fraction_bits()) as f64).round() as i64,
+                Err(_) => return None,
+            },
+            // BCD digits look just like hex digits, as long as they're all decimal
+            Format::Bcd => {
+                if text.is_empty() || !text.chars().all(|c| c.is_digit(10)) { return None; }
+                match u32::from_str_radix(text, 16) {
+                    Ok(raw) => raw as i64,
+                    Err(_) => return None,
+                }
+            }
+        };
+
+        let bytes = self.width.bytes();
+        Some((0..bytes).map(|i| {
+            let shift = match self.endian {
+                Endian::Little => 8 * i,
+                Endian::Big => 8 * (bytes - 1 - i),
+            };
+            (raw >> shift) as u8
+        }).collect())
+    }
+
+    /// Short description of the type, like `u16 BE`
+    pub fn type_string(&self) -> String {
+        format!("{}{}{}",
+            if self.signed { "i" } else { "u" },
+            8 * self.width.bytes(),
+            if self.endian == Endian::Big { " BE" } else { "" })
+    }
+
+    /// Serializes the watch as a tab-separated line
+    pub fn to_line(&self) -> String {
+        format!("{}\t{:06X}\t{}\t{}\t{}\t{}",
+            self.name.replace('\t', " "),
+            self.addr,
+            self.width.bytes(),
+            if self.signed { "s" } else { "u" },
+            if self.endian == Endian::Big { "be" } else { "le" },
+            self.format.name())
+    }
+
+    /// Parses a line created by `to_line`
+    pub fn from_line(line: &str) -> Option<Watch> {
+        let fields = line.split('\t').collect::<Vec<_>>();
+        if fields.len() != 6 {
+            return None;
+        }
+
+        Some(Watch {
+            name: fields[0].to_string(),
+            addr: match u32::from_str_radix(fields[1], 16) {
+                Ok(addr) => addr,
+                Err(_) => return None,
+            },
+            width: match fields[2] {
+                "1" => Width::Byte,
+                "2" => Width::Word,
+                "3" => Width::Long,
+                _ => return None,
+            },
+            signed: fields[3] == "s",
+            endian: if fields[4] == "be" { Endian::Big } else { Endian::Little },
+            format: match FORMATS.iter().find(|f| f.name() == fields[5]) {
+                Some(&format) => format,
+                None => return None,
+            },
+        })
+    }
+}
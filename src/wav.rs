@@ -0,0 +1,114 @@
+//! Writing of 16-bit PCM WAV files
+
+use std::fs::File;
+use std::io::{self, Write, Seek, SeekFrom, BufWriter};
+use std::path::Path;
+
+/// Size of the RIFF and format headers, up to the start of the sample data
+const HEADER_LEN: u32 = 44;
+
+fn write_u16<W: Write>(out: &mut W, value: u16) -> io::Result<()> {
+    out.write_all(&[value as u8, (value >> 8) as u8])
+}
+
+fn write_u32<W: Write>(out: &mut W, value: u32) -> io::Result<()> {
+    out.write_all(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8])
+}
+
+/// Size of a `smpl` chunk with a single loop
+const SMPL_LEN: u32 = 8 + 36 + 24;
+
+/// Writes the file header for `data_len` bytes of samples, followed by `extra_len` bytes of other
+/// chunks
+fn write_header<W: Write>(out: &mut W, channels: u16, rate: u32, data_len: u32, extra_len: u32)
+                          -> io::Result<()> {
+    try!(out.write_all(b"RIFF"));
+    try!(write_u32(out, HEADER_LEN - 8 + data_len + extra_len));
+    try!(out.write_all(b"WAVEfmt "));
+    try!(write_u32(out, 16));
+    try!(write_u16(out, 1));    // PCM
+    try!(write_u16(out, channels));
+    try!(write_u32(out, rate));
+    try!(write_u32(out, rate * channels as u32 * 2));
+    try!(write_u16(out, channels * 2));
+    try!(write_u16(out, 16));
+    try!(out.write_all(b"data"));
+    write_u32(out, data_len)
+}
+
+/// A WAV file that is written incrementally. The sizes in the header are filled in by `finish`.
+pub struct WavWriter {
+    out: BufWriter<File>,
+    channels: u16,
+    /// Number of sample frames written so far
+    frames: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, channels: u16, rate: u32) -> io::Result<WavWriter> {
+        let mut out = BufWriter::new(try!(File::create(path)));
+        try!(write_header(&mut out, channels, rate, 0, 0));
+        Ok(WavWriter {
+            out: out,
+            channels: channels,
+            frames: 0,
+        })
+    }
+
+    /// Appends samples, interleaved by channel
+    pub fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        for &sample in samples {
+            try!(write_u16(&mut self.out, sample as u16));
+        }
+        self.frames += (samples.len() / self.channels as usize) as u32;
+        Ok(())
+    }
+
+    /// Number of sample frames written so far
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Updates the header and closes the file
+    pub fn finish(mut self) -> io::Result<()> {
+        let data_len = self.frames * self.channels as u32 * 2;
+        try!(self.out.seek(SeekFrom::Start(4)));
+        try!(write_u32(&mut self.out, HEADER_LEN - 8 + data_len));
+        try!(self.out.seek(SeekFrom::Start(HEADER_LEN as u64 - 4)));
+        try!(write_u32(&mut self.out, data_len));
+        self.out.flush()
+    }
+}
+
+/// Writes a mono WAV file. If `loop_start` is given, a sampler chunk is added that loops from
+/// there to the end.
+pub fn write_sample(path: &Path, rate: u32, samples: &[i16], loop_start: Option<usize>) -> io::Result<()> {
+    let mut out = BufWriter::new(try!(File::create(path)));
+    let data_len = samples.len() as u32 * 2;
+    let extra_len = if loop_start.is_some() { SMPL_LEN } else { 0 };
+    try!(write_header(&mut out, 1, rate, data_len, extra_len));
+    for &sample in samples {
+        try!(write_u16(&mut out, sample as u16));
+    }
+
+    if let Some(start) = loop_start {
+        try!(out.write_all(b"smpl"));
+        try!(write_u32(&mut out, SMPL_LEN - 8));
+        try!(write_u32(&mut out, 0));   // Manufacturer
+        try!(write_u32(&mut out, 0));   // Product
+        try!(write_u32(&mut out, 1000000000 / rate));  // Sample period in ns
+        try!(write_u32(&mut out, 60));  // MIDI unity note
+        try!(write_u32(&mut out, 0));   // Pitch fraction
+        try!(write_u32(&mut out, 0));   // SMPTE format
+        try!(write_u32(&mut out, 0));   // SMPTE offset
+        try!(write_u32(&mut out, 1));   // Number of loops
+        try!(write_u32(&mut out, 0));   // Sampler data
+        try!(write_u32(&mut out, 0));   // Cue point ID
+        try!(write_u32(&mut out, 0));   // Forward loop
+        try!(write_u32(&mut out, start as u32));
+        try!(write_u32(&mut out, (samples.len() as u32).saturating_sub(1)));
+        try!(write_u32(&mut out, 0));   // Fraction
+        try!(write_u32(&mut out, 0));   // Play count (infinite)
+    }
+    out.flush()
+}